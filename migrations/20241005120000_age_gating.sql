@@ -0,0 +1,8 @@
+-- Add migration script here
+
+ALTER TABLE classification ADD COLUMN minimum_age INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE client ADD COLUMN birth_date DATE;
+ALTER TABLE client ADD COLUMN parent_client_id INTEGER;
+ALTER TABLE client ADD COLUMN parental_age_limit INTEGER;
+ALTER TABLE client ADD FOREIGN KEY (parent_client_id) REFERENCES client(client_id);
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+ALTER TABLE language ADD COLUMN language_code VARCHAR(10) UNIQUE;
+
+ALTER TABLE movie ADD COLUMN tagline TEXT;
+
+CREATE TABLE movie_translation (
+    movie_id INTEGER NOT NULL,
+    language_id INTEGER NOT NULL,
+    title VARCHAR(100) NOT NULL,
+    summary TEXT,
+    tagline TEXT,
+    PRIMARY KEY (movie_id, language_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id),
+    FOREIGN KEY (language_id) REFERENCES language(language_id)
+);
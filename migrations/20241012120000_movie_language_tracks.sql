@@ -0,0 +1,32 @@
+-- Add migration script here
+
+CREATE TABLE movie_subtitle_language (
+    movie_id INTEGER NOT NULL,
+    language_id INTEGER NOT NULL,
+    PRIMARY KEY (movie_id, language_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id),
+    FOREIGN KEY (language_id) REFERENCES language(language_id)
+);
+
+CREATE TABLE movie_audio_language (
+    movie_id INTEGER NOT NULL,
+    language_id INTEGER NOT NULL,
+    PRIMARY KEY (movie_id, language_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id),
+    FOREIGN KEY (language_id) REFERENCES language(language_id)
+);
+
+INSERT INTO language(language_name)
+SELECT 'Spanish' WHERE EXISTS (SELECT 1 FROM movie WHERE has_spanish_subtitles)
+AND NOT EXISTS (SELECT 1 FROM language WHERE language_name = 'Spanish');
+
+INSERT INTO movie_subtitle_language(movie_id, language_id)
+SELECT m.movie_id, l.language_id FROM movie m
+INNER JOIN language l ON l.language_name = 'Spanish'
+WHERE m.has_spanish_subtitles;
+
+-- the original language is the only audio track we know about
+INSERT INTO movie_audio_language(movie_id, language_id)
+SELECT movie_id, original_language_id FROM movie;
+
+ALTER TABLE movie DROP COLUMN has_spanish_subtitles;
@@ -0,0 +1,25 @@
+-- Add migration script here
+
+ALTER TABLE movie ADD COLUMN runtime_minutes INTEGER;
+UPDATE movie SET runtime_minutes = duration_hours * 60;
+ALTER TABLE movie ALTER COLUMN runtime_minutes SET NOT NULL;
+ALTER TABLE movie DROP COLUMN duration_hours;
+
+-- amounts in whole units of an ISO 4217 currency
+ALTER TABLE movie ADD COLUMN budget BIGINT;
+ALTER TABLE movie ADD COLUMN budget_currency VARCHAR(3);
+ALTER TABLE movie ADD COLUMN box_office BIGINT;
+ALTER TABLE movie ADD COLUMN box_office_currency VARCHAR(3);
+
+CREATE TABLE movie_release (
+    movie_release_id SERIAL PRIMARY KEY,
+    movie_id INTEGER NOT NULL,
+    country_id INTEGER NOT NULL,
+    release_date DATE NOT NULL,
+    release_type VARCHAR(20) NOT NULL CHECK (release_type IN ('theatrical', 'streaming', 'festival')),
+    UNIQUE (movie_id, country_id, release_type),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id),
+    FOREIGN KEY (country_id) REFERENCES country(country_id)
+);
+
+CREATE INDEX idx_movie_release_date ON movie_release(release_date);
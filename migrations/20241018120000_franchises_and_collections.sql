@@ -0,0 +1,33 @@
+-- Add migration script here
+
+CREATE TABLE franchise (
+    franchise_id SERIAL PRIMARY KEY,
+    franchise_name VARCHAR(100) NOT NULL UNIQUE,
+    description TEXT
+);
+
+CREATE TABLE franchise_movie (
+    franchise_id INTEGER NOT NULL,
+    movie_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (franchise_id, movie_id),
+    FOREIGN KEY (franchise_id) REFERENCES franchise(franchise_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id)
+);
+
+CREATE TABLE collection (
+    collection_id SERIAL PRIMARY KEY,
+    title VARCHAR(100) NOT NULL,
+    description TEXT,
+    cover_image_url VARCHAR(100),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE collection_movie (
+    collection_id INTEGER NOT NULL,
+    movie_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (collection_id, movie_id),
+    FOREIGN KEY (collection_id) REFERENCES collection(collection_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id)
+);
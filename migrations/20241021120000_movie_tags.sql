@@ -0,0 +1,17 @@
+-- Add migration script here
+
+-- tag names are stored normalized: trimmed, single spaced and lowercase
+CREATE TABLE tag (
+    tag_id SERIAL PRIMARY KEY,
+    tag_name VARCHAR(50) NOT NULL UNIQUE
+);
+
+CREATE TABLE movie_tag (
+    movie_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    PRIMARY KEY (movie_id, tag_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id),
+    FOREIGN KEY (tag_id) REFERENCES tag(tag_id)
+);
+
+CREATE INDEX idx_tag_name_prefix ON tag(tag_name varchar_pattern_ops);
@@ -0,0 +1,29 @@
+-- Add migration script here
+
+CREATE TABLE movie_rating (
+    client_id INTEGER NOT NULL,
+    movie_id INTEGER NOT NULL,
+    rating INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    rated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (client_id, movie_id),
+    FOREIGN KEY (client_id) REFERENCES client(client_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id)
+);
+
+CREATE TABLE favorite_movie (
+    client_id INTEGER NOT NULL,
+    movie_id INTEGER NOT NULL,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (client_id, movie_id),
+    FOREIGN KEY (client_id) REFERENCES client(client_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id)
+);
+
+CREATE TABLE watched_movie (
+    client_id INTEGER NOT NULL,
+    movie_id INTEGER NOT NULL,
+    watched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (client_id, movie_id),
+    FOREIGN KEY (client_id) REFERENCES client(client_id),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id)
+);
@@ -0,0 +1,25 @@
+-- Add migration script here
+
+-- hourly engagement counters per movie and event type
+CREATE TABLE movie_engagement (
+    movie_id INTEGER NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    event_type VARCHAR(20) NOT NULL,
+    event_count BIGINT NOT NULL,
+    PRIMARY KEY (movie_id, bucket_start, event_type),
+    FOREIGN KEY (movie_id) REFERENCES movie(movie_id)
+);
+
+CREATE INDEX idx_movie_engagement_bucket ON movie_engagement(bucket_start);
+
+ALTER TABLE movie ADD COLUMN popularity_score DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+CREATE FUNCTION engagement_weight(event_type VARCHAR) RETURNS INTEGER AS $$
+    SELECT CASE event_type
+        WHEN 'view' THEN 1
+        WHEN 'rating' THEN 2
+        WHEN 'watched' THEN 3
+        WHEN 'favorite' THEN 4
+        ELSE 0
+    END
+$$ LANGUAGE SQL IMMUTABLE;
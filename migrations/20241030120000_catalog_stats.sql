@@ -0,0 +1,8 @@
+-- Add migration script here
+
+-- movies that existed before this column count as created now
+ALTER TABLE movie ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX idx_movie_created_at ON movie(created_at);
+
+ALTER TABLE client ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- argon2id hashes in PHC format don't fit in 100 characters with larger costs
+ALTER TABLE client ALTER COLUMN encrypted_password TYPE VARCHAR(255);
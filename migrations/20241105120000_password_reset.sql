@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- tokens carry the version they were issued for, bumping it logs out every session
+ALTER TABLE client ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
+
+-- only the sha-256 of the token is stored
+CREATE TABLE password_reset_token (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    client_id INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
@@ -0,0 +1,15 @@
+-- Add migration script here
+
+ALTER TABLE client ADD COLUMN email VARCHAR(254);
+ALTER TABLE client ADD CONSTRAINT client_email_key UNIQUE (email);
+ALTER TABLE client ADD COLUMN email_verified_at TIMESTAMPTZ;
+
+-- the address is kept with the token, so changing the email invalidates pending tokens
+CREATE TABLE email_verification_token (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    client_id INTEGER NOT NULL,
+    email VARCHAR(254) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
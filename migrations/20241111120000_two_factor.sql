@@ -0,0 +1,15 @@
+-- Add migration script here
+
+-- the secret is set on enrollment and only used for logins once totp_enabled_at is set
+ALTER TABLE client ADD COLUMN totp_secret VARCHAR(64);
+ALTER TABLE client ADD COLUMN totp_enabled_at TIMESTAMPTZ;
+-- last accepted time step, so a code can't be replayed
+ALTER TABLE client ADD COLUMN totp_last_step BIGINT;
+
+CREATE TABLE client_backup_code (
+    client_id INTEGER NOT NULL,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMPTZ,
+    PRIMARY KEY (client_id, code_hash),
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
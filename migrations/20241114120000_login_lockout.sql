@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- consecutive failed logins, reset by a successful one
+ALTER TABLE client ADD COLUMN failed_login_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE client ADD COLUMN locked_until TIMESTAMPTZ;
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+-- token buckets shared by every instance when RATE_LIMIT_STORE=postgres
+CREATE TABLE rate_limit_bucket (
+    bucket_key VARCHAR(128) PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    -- whether the last request found a token
+    last_allowed BOOLEAN NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,18 @@
+-- Add migration script here
+
+-- only the sha-256 of the key is stored, the prefix lets clients tell their keys apart
+CREATE TABLE api_key (
+    api_key_id SERIAL PRIMARY KEY,
+    client_id INTEGER NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    key_prefix VARCHAR(16) NOT NULL,
+    key_hash VARCHAR(64) NOT NULL UNIQUE,
+    scopes VARCHAR(32)[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
+
+CREATE INDEX api_key_client_idx ON api_key(client_id);
@@ -0,0 +1,28 @@
+-- Add migration script here
+
+-- third-party applications, public_id and the secret are the OAuth client_id and client_secret,
+-- public applications have no secret and rely on PKCE alone
+CREATE TABLE oauth_app (
+    app_id SERIAL PRIMARY KEY,
+    public_id VARCHAR(64) NOT NULL UNIQUE,
+    secret_hash VARCHAR(64),
+    owner_id INTEGER NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    redirect_uris VARCHAR(2048)[] NOT NULL,
+    scopes VARCHAR(32)[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (owner_id) REFERENCES client(client_id)
+);
+
+CREATE TABLE oauth_authorization_code (
+    code_hash VARCHAR(64) PRIMARY KEY,
+    app_id INTEGER NOT NULL,
+    client_id INTEGER NOT NULL,
+    redirect_uri VARCHAR(2048) NOT NULL,
+    scopes VARCHAR(32)[] NOT NULL,
+    code_challenge VARCHAR(128) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    FOREIGN KEY (app_id) REFERENCES oauth_app(app_id),
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
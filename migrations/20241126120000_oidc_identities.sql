@@ -0,0 +1,20 @@
+-- Add migration script here
+
+-- accounts of external OpenID Connect providers linked to a client
+CREATE TABLE client_identity (
+    issuer VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    client_id INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (issuer, subject),
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
+
+-- pending provider logins, looked up by the hash of the state parameter
+CREATE TABLE oidc_login_state (
+    state_hash VARCHAR(64) PRIMARY KEY,
+    nonce VARCHAR(64) NOT NULL,
+    code_verifier VARCHAR(128) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- one row per login, access tokens carry the session id so a single login can be revoked
+CREATE TABLE client_session (
+    session_id SERIAL PRIMARY KEY,
+    client_id INTEGER NOT NULL,
+    user_agent VARCHAR(255),
+    ip_address VARCHAR(45),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    FOREIGN KEY (client_id) REFERENCES client(client_id)
+);
+
+CREATE INDEX client_session_client_idx ON client_session(client_id);
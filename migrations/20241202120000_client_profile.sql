@@ -0,0 +1,17 @@
+-- Add migration script here
+
+ALTER TABLE client ADD COLUMN display_name VARCHAR(50);
+ALTER TABLE client ADD COLUMN avatar_url VARCHAR(500);
+ALTER TABLE client ADD COLUMN preferred_language_id INTEGER;
+ALTER TABLE client ADD FOREIGN KEY (preferred_language_id) REFERENCES language(language_id);
+ALTER TABLE client ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+-- deleted accounts stay as anonymous rows so their ratings still count
+ALTER TABLE client ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE TABLE client_favorite_genre (
+    client_id INTEGER NOT NULL,
+    genre_id INTEGER NOT NULL,
+    PRIMARY KEY (client_id, genre_id),
+    FOREIGN KEY (client_id) REFERENCES client(client_id),
+    FOREIGN KEY (genre_id) REFERENCES genre(genre_id)
+);
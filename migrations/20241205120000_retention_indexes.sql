@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- looked up by the retention purge
+CREATE INDEX client_session_expires_idx ON client_session(expires_at);
+CREATE INDEX client_unverified_created_idx ON client(created_at) WHERE email_verified_at IS NULL AND deleted_at IS NULL;
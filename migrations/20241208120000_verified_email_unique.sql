@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- an address only belongs to a client once it is verified, unverified ones can be claimed by anyone
+ALTER TABLE client DROP CONSTRAINT client_email_key;
+CREATE UNIQUE INDEX client_verified_email_key ON client(email) WHERE email_verified_at IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- failures older than the quiet window no longer count towards a lockout
+ALTER TABLE client ADD COLUMN last_failed_login_at TIMESTAMPTZ;
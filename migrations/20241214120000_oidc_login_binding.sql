@@ -0,0 +1,7 @@
+-- Add migration script here
+
+-- pending logins started before the binding can't be completed anymore
+DELETE FROM oidc_login_state;
+
+-- hash of the secret kept in a cookie of the browser that started the login
+ALTER TABLE oidc_login_state ADD COLUMN binding_hash VARCHAR(64) NOT NULL;
@@ -1,32 +1,130 @@
-use axum::{extract::{Request, State}, http::StatusCode, middleware::Next, response::IntoResponse};
-use tracing::{error, info};
+use axum::{extract::{Request, State}, http::{header, HeaderMap, HeaderValue, Method, StatusCode}, middleware::Next, response::IntoResponse, Extension};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tracing::error;
 
 use crate::user_service::token_provider::TokenProvider;
 
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+// browser sessions keep the token in an HttpOnly cookie, state-changing requests
+// repeat the CSRF cookie in the header
+pub const SESSION_COOKIE: &str = "movies_session";
+pub const CSRF_COOKIE: &str = "movies_csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+pub const MOVIES_READ_SCOPE: &str = "movies:read";
+pub const MOVIES_WRITE_SCOPE: &str = "movies:write";
+
 #[derive(Debug, Clone)]
 pub struct ClientInfo {
-    pub client_name: String
+    pub client_name: String,
+    pub email_verified: bool,
+    pub is_admin: bool,
+    // login session of the token, none for API keys and OAuth tokens
+    pub session_id: Option<i32>
+}
+
+#[derive(Clone)]
+pub struct AuthState {
+    pub token_key: String,
+    pub db_pool: PgPool,
+    // API keys and OAuth tokens are for the catalog, account routes need a login
+    pub allow_scoped_access: bool
 }
 
 pub async fn auth_middleware(
-    State(jwt_secret): State<String>,
+    State(auth_state): State<AuthState>,
     mut request: Request,
     next: Next
 ) -> Result<impl IntoResponse, StatusCode> {
 
-    let token_provider = TokenProvider::new(jwt_secret);
+    let allow_scoped_access = auth_state.allow_scoped_access;
 
-    let authorization_header = request.headers().get("Authorization")
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+    // scopes are none for a full login session
+    let (client_info, scopes) = match request.headers().get(API_KEY_HEADER) {
+        Some(api_key) if allow_scoped_access => {
+            let (client_info, scopes) = api_key_client(&auth_state.db_pool, api_key).await?;
+            (client_info, Some(scopes))
+        }
+        _ => token_client(auth_state, request.headers(), request.method()).await?,
+    };
 
-    let auth_str = authorization_header.to_str().map_err(|err| {
-        error!("Error converting auth header to string: {}", err);
-        StatusCode::BAD_REQUEST
-    })?;
+    if let Some(scopes) = scopes {
+        if !allow_scoped_access {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        if !scopes.iter().any(|scope| scope == required_scope(request.method())) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    request.extensions_mut().insert(client_info);
+
+    let response = next.run(request).await;
+
+    Ok(response)
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn required_scope(method: &Method) -> &'static str {
+    if is_safe_method(method) {
+        return MOVIES_READ_SCOPE;
+    }
+
+    MOVIES_WRITE_SCOPE
+}
+
+// value of a cookie of the request
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value)
+}
+
+// double-submit check, a cross-site page can make the browser send the cookies but can't read them
+fn csrf_token_matches(headers: &HeaderMap) -> bool {
+    let (Some(cookie_token), Some(header_token)) = (cookie(headers, CSRF_COOKIE), headers.get(CSRF_HEADER)) else {
+        return false;
+    };
+
+    let header_token = header_token.as_bytes();
 
-    let jwt_token_string = auth_str.strip_prefix("Bearer ").ok_or(StatusCode::UNAUTHORIZED)?;
+    // compared in constant time
+    !cookie_token.is_empty() && cookie_token.len() == header_token.len()
+        && cookie_token.bytes().zip(header_token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn token_client(auth_state: AuthState, headers: &HeaderMap, method: &Method) -> Result<(ClientInfo, Option<Vec<String>>), StatusCode> {
+    let token_provider = TokenProvider::new(auth_state.token_key);
+
+    // the Authorization header wins over the session cookie
+    let jwt_token_string = match headers.get(header::AUTHORIZATION) {
+        Some(authorization_header) => {
+            let auth_str = authorization_header.to_str().map_err(|err| {
+                error!("Error converting auth header to string: {}", err);
+                StatusCode::BAD_REQUEST
+            })?;
+
+            auth_str.strip_prefix("Bearer ").ok_or(StatusCode::UNAUTHORIZED)?
+        }
+        None => {
+            let session_token = cookie(headers, SESSION_COOKIE).ok_or(StatusCode::UNAUTHORIZED)?;
 
-    info!("The token is: |{}|", jwt_token_string);
+            if !is_safe_method(method) && !csrf_token_matches(headers) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+
+            session_token
+        }
+    };
 
     let claims = token_provider.verify_token(jwt_token_string)
         .map_err(|err| {
@@ -34,11 +132,122 @@ pub async fn auth_middleware(
             StatusCode::UNAUTHORIZED
         })?;
 
+    let client = sqlx::query!(r#"SELECT client.session_version, client.email_verified_at IS NOT NULL AS "email_verified!", client.is_admin,
+client_session.session_id AS "active_session_id?"
+FROM client LEFT JOIN client_session ON client_session.session_id = $2 AND client_session.client_id = client.client_id
+    AND client_session.revoked_at IS NULL AND client_session.expires_at > NOW()
+WHERE client.client_name = $1"#, claims.claims.sub, claims.claims.sid)
+        .fetch_optional(&auth_state.db_pool)
+        .await
+        .map_err(|err| {
+            error!("Error getting the client session: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // tokens issued before the last password change or reset are revoked
+    if client.session_version != claims.claims.ver {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    // full access tokens belong to a login session that must not have been revoked
+    let session_id = match claims.claims.scope {
+        Some(_) => None,
+        None => Some(client.active_session_id.ok_or(StatusCode::UNAUTHORIZED)?),
+    };
+
+    if let Some(session_id) = session_id {
+        // written at most once a minute, like the API key usage
+        sqlx::query!("UPDATE client_session SET last_seen_at = NOW()
+WHERE session_id = $1 AND last_seen_at < NOW() - INTERVAL '1 minute'", session_id)
+            .execute(&auth_state.db_pool)
+            .await
+            .map_err(|err| {
+                error!("Error updating the session activity: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
     let client_name = claims.claims.sub;
+    let scopes = claims.claims.scope.map(|scope| scope.split_whitespace().map(str::to_string).collect());
 
-    request.extensions_mut().insert(ClientInfo { client_name });
+    Ok((ClientInfo { client_name, email_verified: client.email_verified, is_admin: client.is_admin, session_id }, scopes))
+}
 
-    let response = next.run(request).await;
+// the client of an active key and the scopes granted to it
+async fn api_key_client(db_pool: &PgPool, api_key: &HeaderValue) -> Result<(ClientInfo, Vec<String>), StatusCode> {
+    let api_key = api_key.to_str().map_err(|err| {
+        error!("Error converting API key header to string: {}", err);
+        StatusCode::BAD_REQUEST
+    })?;
 
-    Ok(response)
+    let key_hash = hex::encode(Sha256::digest(api_key.as_bytes()));
+
+    let key = sqlx::query!(r#"SELECT api_key.api_key_id, api_key.scopes AS "scopes: Vec<String>", client.client_name,
+client.email_verified_at IS NOT NULL AS "email_verified!", client.is_admin
+FROM api_key JOIN client ON client.client_id = api_key.client_id
+WHERE api_key.key_hash = $1 AND api_key.revoked_at IS NULL AND (api_key.expires_at IS NULL OR api_key.expires_at > NOW())"#, key_hash)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|err| {
+            error!("Error getting the API key: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // written at most once a minute, busy keys would update the row on every request otherwise
+    sqlx::query!("UPDATE api_key SET last_used_at = NOW()
+WHERE api_key_id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')", key.api_key_id)
+        .execute(db_pool)
+        .await
+        .map_err(|err| {
+            error!("Error updating the API key usage: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let client_info = ClientInfo { client_name: key.client_name, email_verified: key.email_verified, is_admin: key.is_admin, session_id: None };
+
+    Ok((client_info, key.scopes))
+}
+
+// layered after `auth_middleware` on routes unverified accounts can't use
+pub async fn require_verified_email(
+    Extension(client_info): Extension<ClientInfo>,
+    request: Request,
+    next: Next
+) -> Result<impl IntoResponse, StatusCode> {
+    if !client_info.email_verified {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
+// layered after `auth_middleware` on catalog curation routes
+pub async fn require_admin(
+    Extension(client_info): Extension<ClientInfo>,
+    request: Request,
+    next: Next
+) -> Result<impl IntoResponse, StatusCode> {
+    if !client_info.is_admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[test]
+fn test_session_cookie_csrf() {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, HeaderValue::from_static("theme=dark; movies_session=jwt; movies_csrf=abc123"));
+
+    assert_eq!(cookie(&headers, SESSION_COOKIE), Some("jwt"));
+    assert_eq!(cookie(&headers, "missing"), None);
+    assert!(!csrf_token_matches(&headers));
+
+    headers.insert(CSRF_HEADER, HeaderValue::from_static("abc124"));
+    assert!(!csrf_token_matches(&headers));
+
+    headers.insert(CSRF_HEADER, HeaderValue::from_static("abc123"));
+    assert!(csrf_token_matches(&headers));
 }
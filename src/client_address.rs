@@ -0,0 +1,80 @@
+use std::{env, net::IpAddr};
+
+use axum::http::HeaderMap;
+use tracing::warn;
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+// reverse proxies in front of the service, TRUSTED_PROXIES=10.0.0.1,10.0.0.2
+// only their X-Forwarded-For entries are believed, anyone else could write whatever address they like
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    addresses: Vec<IpAddr>
+}
+
+impl TrustedProxies {
+    pub fn new(addresses: Vec<IpAddr>) -> Self {
+        Self { addresses }
+    }
+
+    pub fn from_env() -> Self {
+        let addresses = env::var("TRUSTED_PROXIES").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .filter_map(|address| match address.parse() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    warn!("Ignoring the invalid trusted proxy address {}", address);
+                    None
+                }
+            })
+            .collect();
+
+        Self::new(addresses)
+    }
+
+    // the peer, or when it is a trusted proxy the address it forwarded for, walking back through every trusted hop
+    pub fn client_ip(&self, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+        let forwarded: Vec<&str> = headers.get_all(FORWARDED_FOR).iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+
+        let mut client_ip = peer;
+
+        for address in forwarded.iter().rev() {
+            if !self.addresses.contains(&client_ip) {
+                break;
+            }
+
+            match address.parse() {
+                Ok(address) => client_ip = address,
+                Err(_) => break,
+            }
+        }
+
+        client_ip
+    }
+}
+
+#[test]
+fn test_client_ip_from_trusted_proxies() {
+    let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+    let trusted_proxies = TrustedProxies::new(vec![proxy, "10.0.0.2".parse().unwrap()]);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(FORWARDED_FOR, "6.6.6.6, 203.0.113.7, 10.0.0.2".parse().unwrap());
+
+    // the spoofed first entry is never reached, the last untrusted hop is the client
+    assert_eq!(trusted_proxies.client_ip(&headers, proxy), "203.0.113.7".parse::<IpAddr>().unwrap());
+
+    // a peer that isn't a proxy can't pick its address
+    let peer: IpAddr = "198.51.100.4".parse().unwrap();
+    assert_eq!(trusted_proxies.client_ip(&headers, peer), peer);
+    assert_eq!(TrustedProxies::default().client_ip(&headers, proxy), proxy);
+
+    headers.insert(FORWARDED_FOR, "garbage".parse().unwrap());
+    assert_eq!(trusted_proxies.client_ip(&headers, proxy), proxy);
+}
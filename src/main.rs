@@ -1,6 +1,6 @@
 pub mod user_service;
 mod movie_service;
-use std::{env, error, time::Duration};
+use std::{env, error, net::SocketAddr, time::Duration};
 
 use axum::{middleware, routing::get, Router};
 use dotenvy::dotenv;
@@ -8,6 +8,8 @@ use sqlx::{postgres::PgPoolOptions, PgPool};
 use tower_http::cors::CorsLayer;
 use tracing::info;
 mod auth_middleware;
+mod client_address;
+mod rate_limit;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn error::Error>> {
@@ -17,10 +19,19 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
     let postgres_pool = get_postgres_pool().await; 
     let token_jwt = env::var("JWT_SECRET").expect("JWT SECRET expected");
 
-    let user_service_router = user_service::get_router(postgres_pool.clone(), token_jwt.clone());
+    let rate_limiter = rate_limit::RateLimiter::from_env(postgres_pool.clone());
+
+    let user_service_router = user_service::get_router(postgres_pool.clone(), token_jwt.clone(), rate_limiter.clone());
+
+    let auth_state = auth_middleware::AuthState {
+        token_key: token_jwt,
+        db_pool: postgres_pool.clone(),
+        allow_scoped_access: true
+    };
 
     let movie_service_router = movie_service::get_router(postgres_pool)
-        .route_layer(middleware::from_fn_with_state(token_jwt, auth_middleware::auth_middleware));
+        .route_layer(middleware::from_fn_with_state(rate_limiter, rate_limit::rate_limit_middleware))
+        .route_layer(middleware::from_fn_with_state(auth_state, auth_middleware::auth_middleware));
 
     let app = Router::new()
         .route("/", get(root))
@@ -34,7 +45,8 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
 
     info!("Server just started listening in port 3000");
 
-    axum::serve(listener, app)
+    // the peer address is used to throttle logins
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 
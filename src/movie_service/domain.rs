@@ -1,20 +1,19 @@
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 // classifications
 #[derive(Debug, Serialize, Clone, Deserialize)]
 pub struct Classification {
     pub classification_id: i32,
     pub classification_name: String,
+    pub minimum_age: i32,
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
 pub struct ClassificationConstructor {
     pub classification_name: String,
-}
-
-impl ClassificationConstructor {
-    pub fn to_classification(self) -> Classification {
-        Classification {classification_id: 0, classification_name: self.classification_name}
-    }
+    #[serde(default)]
+    pub minimum_age: i32,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -28,12 +27,6 @@ pub struct GenreConstructor {
     pub genre_name: String
 }
 
-impl GenreConstructor {
-    pub fn to_genre(self) -> Genre {
-        Genre {genre_id: 0, genre_name: self.genre_name}
-    }
-}
-
 #[derive(Debug, Serialize, Clone)]
 pub struct Country {
     pub country_id: i32,
@@ -45,27 +38,18 @@ pub struct CountryConstructor {
     pub country_name: String
 }
 
-impl CountryConstructor {
-    pub fn to_country(self) -> Country {
-        Country { country_id: 0, country_name: self.country_name }
-    } 
-}
-
 #[derive(Debug, Serialize, Clone)]
 pub struct Language {
     pub language_id: i32,
-    pub language_name: String
+    pub language_name: String,
+    pub language_code: Option<String>
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
 pub struct LanguageConstructor {
-    pub language_name: String
-}
-
-impl LanguageConstructor {
-    pub fn to_language(self) -> Language {
-        Language { language_id: 0, language_name: self.language_name }
-    }
+    pub language_name: String,
+    #[serde(default)]
+    pub language_code: Option<String>
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
@@ -74,15 +58,55 @@ pub struct Movie {
     pub distribution_title: String,
     pub original_title: String,
     pub original_language: String,
-    pub has_spanish_subtitles: bool,
+    #[serde(default)]
+    pub subtitle_languages: Vec<String>,
+    #[serde(default)]
+    pub audio_languages: Vec<String>,
     pub production_year: i32,
     pub website_url: String,
     pub image_url: String,
-    pub duration_hours: i32,
+    pub runtime_minutes: i32,
     pub summary: Option<String>,
+    #[serde(default)]
+    pub tagline: Option<String>,
+    pub budget: Option<i64>,
+    pub budget_currency: Option<String>,
+    pub box_office: Option<i64>,
+    pub box_office_currency: Option<String>,
+    #[serde(default)]
+    pub popularity_score: f64,
     pub classification: String,
     pub origin_country: String,
     pub genre: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub releases: Json<Vec<MovieRelease>>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseType {
+    Theatrical,
+    Streaming,
+    Festival,
+}
+
+impl ReleaseType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseType::Theatrical => "theatrical",
+            ReleaseType::Streaming => "streaming",
+            ReleaseType::Festival => "festival",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct MovieRelease {
+    pub country: String,
+    pub release_date: NaiveDate,
+    pub release_type: ReleaseType,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -97,33 +121,331 @@ pub struct MovieConstructor {
     pub distribution_title: String,
     pub original_title: String,
     pub original_language: String,
-    pub has_spanish_subtitles: bool,
+    #[serde(default)]
+    pub subtitle_languages: Vec<String>,
+    #[serde(default)]
+    pub audio_languages: Vec<String>,
     pub production_year: i32,
     pub website_url: String,
     pub image_url: String,
-    pub duration_hours: i32,
+    pub runtime_minutes: i32,
     pub summary: Option<String>,
+    #[serde(default)]
+    pub tagline: Option<String>,
+    pub budget: Option<i64>,
+    pub budget_currency: Option<String>,
+    pub box_office: Option<i64>,
+    pub box_office_currency: Option<String>,
     pub classification: String,
     pub origin_country: String,
     pub genre: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub releases: Vec<MovieRelease>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MovieTranslation {
+    pub movie_id: i32,
+    pub language: String,
+    pub title: String,
+    pub summary: Option<String>,
+    pub tagline: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct MovieTranslationConstructor {
+    pub language: String,
+    pub title: String,
+    pub summary: Option<String>,
+    pub tagline: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MovieSortKey {
+    Runtime,
+    ProductionYear,
+    Budget,
+    BoxOffice,
+    ReleaseDate,
+    Popularity,
+}
+
+impl MovieSortKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovieSortKey::Runtime => "runtime",
+            MovieSortKey::ProductionYear => "production_year",
+            MovieSortKey::Budget => "budget",
+            MovieSortKey::BoxOffice => "box_office",
+            MovieSortKey::ReleaseDate => "release_date",
+            MovieSortKey::Popularity => "popularity",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// audio, subtitles and tags are comma separated lists, a movie has to carry every value
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MovieFilter {
+    pub audio: Option<String>,
+    pub subtitles: Option<String>,
+    pub tags: Option<String>,
+    pub min_runtime: Option<i32>,
+    pub max_runtime: Option<i32>,
+    pub released_from: Option<NaiveDate>,
+    pub released_until: Option<NaiveDate>,
+    pub release_type: Option<ReleaseType>,
+    pub sort: Option<MovieSortKey>,
+    #[serde(default)]
+    pub order: SortOrder,
+    // amounts in different currencies can't be compared, money sorts only rank one currency
+    pub currency: Option<String>,
+}
+
+impl MovieFilter {
+    pub fn audio_languages(&self) -> Vec<String> {
+        split_list(self.audio.as_deref())
+    }
+
+    pub fn subtitle_languages(&self) -> Vec<String> {
+        split_list(self.subtitles.as_deref())
+    }
+
+    pub fn tag_names(&self) -> Vec<String> {
+        normalize_tag_names(&split_list(self.tags.as_deref()))
+    }
+
+    pub fn release_type(&self) -> Option<&'static str> {
+        self.release_type.as_ref().map(ReleaseType::as_str)
+    }
+
+    pub fn sort_key(&self) -> Option<&'static str> {
+        self.sort.as_ref().map(MovieSortKey::as_str)
+    }
+
+    pub fn descending(&self) -> bool {
+        self.order == SortOrder::Desc
+    }
+
+    pub fn sort_currency(&self) -> Option<String> {
+        match self.sort {
+            Some(MovieSortKey::Budget | MovieSortKey::BoxOffice) => self.currency.as_deref().map(|currency| currency.trim().to_uppercase()),
+            _ => None,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let money_sort = matches!(self.sort, Some(MovieSortKey::Budget | MovieSortKey::BoxOffice));
+
+        !money_sort || self.sort_currency().is_some_and(|currency| currency.len() == 3)
+    }
+}
+
+fn split_list(list: Option<&str>) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+
+    for value in list.unwrap_or_default().split(',').map(str::trim) {
+        if !value.is_empty() && !values.iter().any(|existing| existing == value) {
+            values.push(value.to_string());
+        }
+    }
+
+    values
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendingWindow {
+    #[default]
+    Day,
+    Week,
+}
+
+impl TrendingWindow {
+    pub fn hours(&self) -> i32 {
+        match self {
+            TrendingWindow::Day => 24,
+            TrendingWindow::Week => 24 * 7,
+        }
+    }
+}
+
+// ratings and recommendations
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct RatingConstructor {
+    pub rating: i32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Recommendation {
+    pub movie_id: i32,
+    pub distribution_title: String,
+    pub image_url: String,
+    pub reason: String,
+    pub because_of_movie_id: Option<i32>,
+}
+
+// tags
+#[derive(Debug, Serialize, Clone)]
+pub struct Tag {
+    pub tag_id: i32,
+    pub tag_name: String,
+    pub movie_count: i64,
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct TagConstructor {
+    pub tag_name: String,
 }
 
-impl MovieConstructor {
-    pub fn to_movie(self) -> Movie {
-        Movie {
-            movie_id: 0,
-            distribution_title: self.distribution_title,
-            original_title: self.original_title,
-            original_language: self.original_language,
-            has_spanish_subtitles: self.has_spanish_subtitles,
-            production_year: self.production_year,
-            website_url: self.website_url,
-            image_url: self.image_url,
-            duration_hours: self.duration_hours,
-            summary: self.summary,
-            classification: self.classification,
-            genre: self.genre,
-            origin_country: self.origin_country
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct TagMerge {
+    pub source: String,
+    pub target: String,
+}
+
+// tag.tag_name is a VARCHAR(50)
+pub const MAX_TAG_NAME_LENGTH: usize = 50;
+
+pub fn normalize_tag_name(tag_name: &str) -> String {
+    tag_name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+pub fn normalize_tag_names(tag_names: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+
+    for tag_name in tag_names.iter().map(|tag_name| normalize_tag_name(tag_name)) {
+        if !tag_name.is_empty() && !normalized.contains(&tag_name) {
+            normalized.push(tag_name);
         }
     }
+
+    normalized
+}
+
+pub fn is_valid_tag_name(tag_name: &str) -> bool {
+    !tag_name.is_empty() && tag_name.chars().count() <= MAX_TAG_NAME_LENGTH
+}
+
+// franchises
+#[derive(Debug, Serialize, Clone)]
+pub struct Franchise {
+    pub franchise_id: i32,
+    pub franchise_name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct FranchiseConstructor {
+    pub franchise_name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FranchiseDetail {
+    pub franchise_id: i32,
+    pub franchise_name: String,
+    pub description: Option<String>,
+    pub movies: Vec<BasicMovie>,
+}
+
+// collections
+#[derive(Debug, Serialize, Clone)]
+pub struct Collection {
+    pub collection_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub cover_image_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct CollectionConstructor {
+    pub title: String,
+    pub description: Option<String>,
+    pub cover_image_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CollectionDetail {
+    pub collection_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub cover_image_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub movies: Vec<BasicMovie>,
+}
+
+// entry of a franchise or collection, appended at the end when there is no position
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct OrderedEntryConstructor {
+    pub movie_id: i32,
+    pub position: Option<i32>,
+}
+
+// catalog statistics
+#[derive(Debug, Serialize, Clone)]
+pub struct StatCount {
+    pub label: String,
+    pub movie_count: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DecadeCount {
+    pub decade: i32,
+    pub movie_count: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MonthlyGrowth {
+    pub month: DateTime<Utc>,
+    pub movies_added: i64,
+    pub total_movies: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CatalogStats {
+    pub total_movies: i64,
+    pub average_runtime_minutes: Option<f64>,
+    // between 0 and 1
+    pub spanish_subtitle_share: f64,
+    pub by_genre: Vec<StatCount>,
+    pub by_country: Vec<StatCount>,
+    pub by_original_language: Vec<StatCount>,
+    pub by_classification: Vec<StatCount>,
+    pub by_decade: Vec<DecadeCount>,
+    pub monthly_growth: Vec<MonthlyGrowth>,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[test]
+fn test_tag_names_normalization() {
+    let tags = vec!["  Time   Travel ".to_string(), "time travel".to_string(), " ".to_string(), "Heist".to_string()];
+
+    assert_eq!(normalize_tag_names(&tags), vec!["time travel", "heist"]);
+}
+
+#[test]
+fn test_money_sort_needs_a_currency() {
+    let filter = |query: &str| serde_urlencoded::from_str::<MovieFilter>(query).expect("valid movie filter");
+
+    assert!(!filter("sort=budget").is_valid());
+    assert!(!filter("sort=box_office&currency=dollars").is_valid());
+    assert!(filter("sort=runtime").is_valid());
+
+    assert_eq!(filter("sort=budget&currency=usd").sort_currency().as_deref(), Some("USD"));
+    assert_eq!(filter("sort=runtime&currency=usd").sort_currency(), None);
 }
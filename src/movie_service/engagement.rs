@@ -0,0 +1,180 @@
+use std::{collections::HashMap, mem, sync::{Arc, Mutex}, time::Duration};
+
+use chrono::{DateTime, DurationRound, Utc};
+use tracing::{error, warn};
+
+use super::repository::MovieRepository;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EngagementEvent {
+    View,
+    Rating,
+    Favorite,
+    Watched,
+}
+
+impl EngagementEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngagementEvent::View => "view",
+            EngagementEvent::Rating => "rating",
+            EngagementEvent::Favorite => "favorite",
+            EngagementEvent::Watched => "watched",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EngagementCount {
+    pub movie_id: i32,
+    pub bucket_start: DateTime<Utc>,
+    pub event: EngagementEvent,
+    pub event_count: i64,
+}
+
+// movie, hour bucket and event
+type CounterKey = (i32, DateTime<Utc>, EngagementEvent);
+
+// counters a failed flush may put back, past it the database has been down for too long
+// and the oldest counts are dropped instead of growing the buffer without bound
+const MAX_BUFFERED_COUNTERS: usize = 100_000;
+
+// events are counted in memory and written in batches by the flush job,
+// so requests never wait on a counter write
+#[derive(Debug)]
+pub struct EngagementRecorder {
+    buffer: Mutex<HashMap<CounterKey, i64>>,
+    max_counters: usize,
+}
+
+impl Default for EngagementRecorder {
+    fn default() -> Self {
+        Self::new(MAX_BUFFERED_COUNTERS)
+    }
+}
+
+impl EngagementRecorder {
+    pub fn new(max_counters: usize) -> Self {
+        Self { buffer: Mutex::default(), max_counters }
+    }
+
+    pub fn record(&self, movie_id: i32, event: EngagementEvent) {
+        let bucket_start = Utc::now()
+            .duration_trunc(chrono::Duration::hours(1))
+            .expect("valid hour bucket");
+
+        let mut buffer = self.buffer.lock().expect("engagement buffer poisoned");
+        *buffer.entry((movie_id, bucket_start, event)).or_default() += 1;
+    }
+
+    pub fn drain(&self) -> Vec<EngagementCount> {
+        let buffer = mem::take(&mut *self.buffer.lock().expect("engagement buffer poisoned"));
+
+        buffer.into_iter()
+            .map(|((movie_id, bucket_start, event), event_count)| EngagementCount {
+                movie_id,
+                bucket_start,
+                event,
+                event_count,
+            })
+            .collect()
+    }
+
+    // puts back counts a failed flush could not write, they go out with the next one.
+    // counters already in the buffer always take them, new ones only while there is room
+    pub fn restore(&self, counts: Vec<EngagementCount>) {
+        let mut buffer = self.buffer.lock().expect("engagement buffer poisoned");
+        let mut dropped = 0;
+
+        for count in counts {
+            let key = (count.movie_id, count.bucket_start, count.event);
+
+            if let Some(event_count) = buffer.get_mut(&key) {
+                *event_count += count.event_count;
+            } else if buffer.len() < self.max_counters {
+                buffer.insert(key, count.event_count);
+            } else {
+                dropped += 1;
+            }
+        }
+
+        if dropped > 0 {
+            warn!("Engagement buffer full, dropped {} counters that could not be flushed", dropped);
+        }
+    }
+}
+
+// counts still in the buffer are lost if the process stops before the next flush
+pub fn spawn_flush_job(db: Arc<dyn MovieRepository>, recorder: Arc<EngagementRecorder>, flush_every: Duration, popularity_every: Duration) {
+    tokio::spawn(async move {
+        let mut flush_interval = tokio::time::interval(flush_every);
+        let mut popularity_interval = tokio::time::interval(popularity_every);
+
+        loop {
+            tokio::select! {
+                _ = flush_interval.tick() => {
+                    let counts = recorder.drain();
+
+                    if counts.is_empty() {
+                        continue;
+                    }
+
+                    if let Err(err) = db.add_engagement_counts(&counts).await {
+                        error!("Error flushing {} engagement counters: {}", counts.len(), err);
+                        recorder.restore(counts);
+                    }
+                }
+                _ = popularity_interval.tick() => {
+                    if let Err(err) = db.refresh_popularity_scores().await {
+                        error!("Error refreshing popularity scores: {}", err);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn test_recorder_aggregates_until_drained() {
+    let recorder = EngagementRecorder::default();
+
+    recorder.record(1, EngagementEvent::View);
+    recorder.record(1, EngagementEvent::View);
+    recorder.record(2, EngagementEvent::Favorite);
+
+    let mut counts = recorder.drain();
+    counts.sort_by_key(|count| count.movie_id);
+
+    assert_eq!(counts.len(), 2);
+    assert_eq!((counts[0].movie_id, counts[0].event, counts[0].event_count), (1, EngagementEvent::View, 2));
+    assert_eq!((counts[1].movie_id, counts[1].event, counts[1].event_count), (2, EngagementEvent::Favorite, 1));
+    assert!(recorder.drain().is_empty());
+
+    // a failed flush merges back with what was recorded meanwhile
+    recorder.record(1, EngagementEvent::View);
+    recorder.restore(counts);
+
+    let mut counts = recorder.drain();
+    counts.sort_by_key(|count| count.movie_id);
+    assert_eq!((counts[0].movie_id, counts[0].event_count), (1, 3));
+}
+
+#[test]
+fn test_restore_stops_at_the_buffer_cap() {
+    let recorder = EngagementRecorder::new(2);
+
+    recorder.record(1, EngagementEvent::View);
+    recorder.record(2, EngagementEvent::View);
+    recorder.record(3, EngagementEvent::View);
+    let counts = recorder.drain();
+
+    // meanwhile movie 1 was viewed again, its counter takes the restored count
+    recorder.record(1, EngagementEvent::View);
+    recorder.restore(counts);
+
+    let mut counts = recorder.drain();
+    counts.sort_by_key(|count| count.movie_id);
+
+    assert_eq!(counts.len(), 2);
+    assert_eq!((counts[0].movie_id, counts[0].event_count), (1, 2));
+}
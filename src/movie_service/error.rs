@@ -14,9 +14,6 @@ pub enum MovieServiceError {
     #[error("Error generating JWT token")]
     JsonWebTokenError(#[from] jsonwebtoken::errors::Error),
 
-    #[error("Invalid password")]
-    InvalidPassword(String),
-
     #[error("Invalid language name")]
     InvalidLanguageName,
 
@@ -29,4 +26,25 @@ pub enum MovieServiceError {
     #[error("Invalid classification name")]
     InvalidClassificationName,
 
+    #[error("Rating must be between 1 and 5")]
+    InvalidRating,
+
+    #[error("Invalid tag name")]
+    InvalidTagName,
+
+    #[error("Movie not found")]
+    MovieNotFound,
+
+    #[error("Franchise not found")]
+    FranchiseNotFound,
+
+    #[error("Collection not found")]
+    CollectionNotFound,
+
+    #[error("Movie not allowed for the client age")]
+    AgeRestricted,
+
+    #[error("The movie has the same release twice")]
+    DuplicateRelease,
+
 }
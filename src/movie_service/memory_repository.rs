@@ -0,0 +1,951 @@
+use std::{cmp::{Ordering, Reverse}, collections::{BTreeMap, HashMap}, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use serde_json::json;
+use sqlx::types::Json;
+
+use super::domain::{BasicMovie, CatalogStats, Classification, Collection, CollectionConstructor, Country, DecadeCount, Franchise, FranchiseConstructor, Genre, Language, MonthlyGrowth, Movie, MovieFilter, MovieRelease, MovieSortKey, MovieTranslation, MovieTranslationConstructor, OrderedEntryConstructor, StatCount, Tag};
+use super::engagement::{EngagementCount, EngagementEvent};
+use super::error::{MovieServiceError, Result};
+use super::movie_database::MovieDataDb;
+use super::recommendation::Interaction;
+use super::repository::MovieRepository;
+use super::similarity::MovieFeatures;
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    // ids are the position in the list plus one, like a SERIAL column
+    languages: Vec<String>,
+    countries: Vec<String>,
+    genres: Vec<String>,
+    classifications: Vec<String>,
+    clients: Vec<String>,
+    movies: BTreeMap<i32, MovieDataDb>,
+    last_movie_id: i32,
+    movie_created_at: HashMap<i32, DateTime<Utc>>,
+    popularity_scores: HashMap<i32, f64>,
+    // by classification id, classifications without an entry are for every age
+    minimum_ages: HashMap<i32, i32>,
+    client_age_limits: HashMap<String, i32>,
+    // by movie and language id
+    translations: BTreeMap<(i32, i32), MovieTranslationConstructor>,
+    // by client name and movie id
+    ratings: BTreeMap<(String, i32), i32>,
+    // client name and movie id, oldest first
+    favorites: Vec<(String, i32)>,
+    watched: Vec<(String, i32)>,
+    engagement: HashMap<(i32, DateTime<Utc>, EngagementEvent), i64>,
+    tags: BTreeMap<i32, String>,
+    last_tag_id: i32,
+    franchises: BTreeMap<i32, Franchise>,
+    collections: BTreeMap<i32, Collection>,
+    // position by franchise or collection id and movie id
+    franchise_movies: BTreeMap<(i32, i32), i32>,
+    collection_movies: BTreeMap<(i32, i32), i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryMovieRepository {
+    state: Mutex<MemoryState>,
+}
+
+fn find_id(names: &[String], name: &str) -> Option<i32> {
+    names.iter().position(|candidate| candidate == name).map(|index| index as i32 + 1)
+}
+
+fn name_of(names: &[String], id: i32) -> String {
+    names[id as usize - 1].clone()
+}
+
+fn next_id<T>(entries: &BTreeMap<i32, T>) -> i32 {
+    entries.keys().last().map_or(1, |id| id + 1)
+}
+
+// mirrors the engagement_weight SQL function
+fn engagement_weight(event: EngagementEvent) -> f64 {
+    match event {
+        EngagementEvent::View => 1.0,
+        EngagementEvent::Rating => 2.0,
+        EngagementEvent::Watched => 3.0,
+        EngagementEvent::Favorite => 4.0,
+    }
+}
+
+impl InMemoryMovieRepository {
+    pub fn with_catalog(languages: &[&str], countries: &[&str], genres: &[&str], classifications: &[&str]) -> Self {
+        let to_vec = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        Self {
+            state: Mutex::new(MemoryState {
+                languages: to_vec(languages),
+                countries: to_vec(countries),
+                genres: to_vec(genres),
+                classifications: to_vec(classifications),
+                ..MemoryState::default()
+            }),
+        }
+    }
+
+    pub fn set_minimum_age(&self, classification_name: &str, minimum_age: i32) {
+        let mut state = self.state();
+        let classification_id = find_id(&state.classifications, classification_name).expect("known classification");
+
+        state.minimum_ages.insert(classification_id, minimum_age);
+    }
+
+    pub fn set_client_age_limit(&self, client_name: &str, age_limit: i32) {
+        self.state().client_age_limits.insert(client_name.to_string(), age_limit);
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MemoryState> {
+        self.state.lock().expect("in memory repository poisoned")
+    }
+}
+
+impl MemoryState {
+    fn minimum_age(&self, movie: &MovieDataDb) -> i32 {
+        self.minimum_ages.get(&movie.classification_id).copied().unwrap_or_default()
+    }
+
+    fn allowed(&self, movie_id: i32, age_limit: Option<i32>) -> bool {
+        self.movies.get(&movie_id)
+            .is_some_and(|movie| age_limit.is_none_or(|age_limit| self.minimum_age(movie) <= age_limit))
+    }
+
+    fn client_id(&mut self, client_name: &str) -> i32 {
+        if let Some(client_id) = find_id(&self.clients, client_name) {
+            return client_id;
+        }
+
+        self.clients.push(client_name.to_string());
+        self.clients.len() as i32
+    }
+
+    fn add_tags(&mut self, tag_names: &[String]) {
+        for tag_name in tag_names {
+            if !self.tags.values().any(|existing| existing == tag_name) {
+                self.last_tag_id += 1;
+                self.tags.insert(self.last_tag_id, tag_name.clone());
+            }
+        }
+    }
+
+    fn to_movie(&self, movie_id: i32, movie: &MovieDataDb) -> Movie {
+        let releases = movie.releases.iter()
+            .map(|release| MovieRelease {
+                country: name_of(&self.countries, release.country_id),
+                release_date: release.release_date,
+                release_type: serde_json::from_value(json!(release.release_type)).expect("stored release type"),
+            })
+            .collect();
+
+        let mut tags = movie.tags.clone();
+        tags.sort();
+
+        Movie {
+            movie_id,
+            distribution_title: movie.distribution_title.clone(),
+            original_title: movie.original_title.clone(),
+            original_language: name_of(&self.languages, movie.original_language_id),
+            subtitle_languages: movie.subtitle_language_ids.iter().map(|id| name_of(&self.languages, *id)).collect(),
+            audio_languages: movie.audio_language_ids.iter().map(|id| name_of(&self.languages, *id)).collect(),
+            production_year: movie.production_year,
+            website_url: movie.website_url.clone(),
+            image_url: movie.image_url.clone(),
+            runtime_minutes: movie.runtime_minutes,
+            summary: movie.summary.clone(),
+            tagline: movie.tagline.clone(),
+            budget: movie.budget,
+            budget_currency: movie.budget_currency.clone(),
+            box_office: movie.box_office,
+            box_office_currency: movie.box_office_currency.clone(),
+            popularity_score: self.popularity_scores.get(&movie_id).copied().unwrap_or_default(),
+            classification: name_of(&self.classifications, movie.classification_id),
+            origin_country: name_of(&self.countries, movie.origin_country_id),
+            genre: name_of(&self.genres, movie.genre_id),
+            tags,
+            releases: Json(releases),
+        }
+    }
+
+    // the allowed movies among the ids, in their order
+    fn basic_movies(&self, movie_ids: impl IntoIterator<Item = i32>, age_limit: Option<i32>) -> Vec<BasicMovie> {
+        movie_ids.into_iter()
+            .filter(|movie_id| self.allowed(*movie_id, age_limit))
+            .map(|movie_id| {
+                let movie = &self.movies[&movie_id];
+
+                BasicMovie { movie_id, distribution_title: movie.distribution_title.clone(), image_url: movie.image_url.clone() }
+            })
+            .collect()
+    }
+
+    fn matches_filter(&self, movie: &MovieDataDb, filter: &MovieFilter) -> bool {
+        let has_languages = |language_ids: &[i32], language_names: Vec<String>| language_names.iter()
+            .all(|language_name| find_id(&self.languages, language_name).is_some_and(|language_id| language_ids.contains(&language_id)));
+
+        let release_filter = filter.released_from.is_some() || filter.released_until.is_some() || filter.release_type.is_some();
+
+        let currency = match filter.sort {
+            Some(MovieSortKey::Budget) => &movie.budget_currency,
+            _ => &movie.box_office_currency,
+        };
+
+        has_languages(&movie.audio_language_ids, filter.audio_languages())
+            && has_languages(&movie.subtitle_language_ids, filter.subtitle_languages())
+            && filter.min_runtime.is_none_or(|min_runtime| movie.runtime_minutes >= min_runtime)
+            && filter.max_runtime.is_none_or(|max_runtime| movie.runtime_minutes <= max_runtime)
+            && (!release_filter || movie.releases.iter().any(|release| {
+                filter.released_from.is_none_or(|released_from| release.release_date >= released_from)
+                    && filter.released_until.is_none_or(|released_until| release.release_date <= released_until)
+                    && filter.release_type().is_none_or(|release_type| release.release_type == release_type)
+            }))
+            && filter.tag_names().iter().all(|tag_name| movie.tags.contains(tag_name))
+            && filter.sort_currency().is_none_or(|sort_currency| currency.as_deref().map(str::to_uppercase) == Some(sort_currency))
+    }
+
+    fn sort_value(&self, movie_id: i32, movie: &MovieDataDb, sort: Option<MovieSortKey>) -> Option<f64> {
+        match sort? {
+            MovieSortKey::Runtime => Some(movie.runtime_minutes as f64),
+            MovieSortKey::ProductionYear => Some(movie.production_year as f64),
+            MovieSortKey::Budget => movie.budget.map(|budget| budget as f64),
+            MovieSortKey::BoxOffice => movie.box_office.map(|box_office| box_office as f64),
+            MovieSortKey::Popularity => Some(self.popularity_scores.get(&movie_id).copied().unwrap_or_default()),
+            MovieSortKey::ReleaseDate => movie.releases.iter()
+                .map(|release| release.release_date.num_days_from_ce() as f64)
+                .reduce(f64::min),
+        }
+    }
+
+    // sorted like the page query, missing values last in both directions and the id breaking ties
+    fn page_movie_ids(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Vec<i32> {
+        let direction = if filter.descending() { -1.0 } else { 1.0 };
+
+        let mut movies: Vec<(i32, Option<f64>)> = self.movies.iter()
+            .filter(|(movie_id, movie)| self.allowed(**movie_id, age_limit) && self.matches_filter(movie, filter))
+            .map(|(movie_id, movie)| (*movie_id, self.sort_value(*movie_id, movie, filter.sort).map(|value| value * direction)))
+            .collect();
+
+        movies.sort_by(|(a_id, a), (b_id, b)| {
+            let by_value = match (a, b) {
+                (Some(a), Some(b)) => a.total_cmp(b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+
+            by_value.then(a_id.cmp(b_id))
+        });
+
+        movies.into_iter()
+            .map(|(movie_id, _)| movie_id)
+            .skip((page * quantity).max(0) as usize)
+            .take(quantity.max(0) as usize)
+            .collect()
+    }
+
+    fn ordered_movies(entries: &BTreeMap<(i32, i32), i32>, owner_id: i32) -> Vec<i32> {
+        let mut movies: Vec<(i32, i32)> = entries.iter()
+            .filter(|((entry_owner_id, _), _)| *entry_owner_id == owner_id)
+            .map(|((_, movie_id), position)| (*position, *movie_id))
+            .collect();
+
+        movies.sort();
+        movies.into_iter().map(|(_, movie_id)| movie_id).collect()
+    }
+
+    // appended at the end when the entry has no position
+    fn set_entry(entries: &mut BTreeMap<(i32, i32), i32>, owner_id: i32, entry: &OrderedEntryConstructor) {
+        let last_position = entries.iter()
+            .filter(|((entry_owner_id, _), _)| *entry_owner_id == owner_id)
+            .map(|(_, position)| *position)
+            .max()
+            .unwrap_or_default();
+
+        entries.insert((owner_id, entry.movie_id), entry.position.unwrap_or(last_position + 1));
+    }
+
+    fn counts_by(&self, labels: &[String], label_id: impl Fn(&MovieDataDb) -> i32) -> Vec<StatCount> {
+        let mut counts: Vec<StatCount> = labels.iter().enumerate()
+            .map(|(index, label)| StatCount {
+                label: label.clone(),
+                movie_count: self.movies.values().filter(|movie| label_id(movie) == index as i32 + 1).count() as i64,
+            })
+            .collect();
+
+        counts.sort_by(|a, b| b.movie_count.cmp(&a.movie_count).then(a.label.cmp(&b.label)));
+        counts
+    }
+}
+
+#[async_trait]
+impl MovieRepository for InMemoryMovieRepository {
+    async fn get_language_id(&self, language_name: String) -> Result<Option<i32>> {
+        Ok(find_id(&self.state().languages, &language_name))
+    }
+
+    async fn get_language_ids(&self, language_names: &[String]) -> Result<Vec<i32>> {
+        let state = self.state();
+
+        Ok(language_names.iter().filter_map(|name| find_id(&state.languages, name)).collect())
+    }
+
+    async fn get_country_id(&self, country_name: String) -> Result<Option<i32>> {
+        Ok(find_id(&self.state().countries, &country_name))
+    }
+
+    async fn get_genre_id(&self, genre_name: String) -> Result<Option<i32>> {
+        Ok(find_id(&self.state().genres, &genre_name))
+    }
+
+    async fn get_classification_id(&self, classification_name: String) -> Result<Option<i32>> {
+        Ok(find_id(&self.state().classifications, &classification_name))
+    }
+
+    async fn insert_movie(&self, movie: &MovieDataDb) -> Result<i32> {
+        let mut state = self.state();
+
+        state.last_movie_id += 1;
+        let movie_id = state.last_movie_id;
+        state.add_tags(&movie.tags);
+        state.movies.insert(movie_id, movie.clone());
+        state.movie_created_at.insert(movie_id, Utc::now());
+
+        Ok(movie_id)
+    }
+
+    async fn get_movie(&self, movie_id: i32) -> Result<Movie> {
+        let state = self.state();
+        let movie = state.movies.get(&movie_id).ok_or(MovieServiceError::MovieNotFound)?;
+
+        Ok(state.to_movie(movie_id, movie))
+    }
+
+    async fn update_movie(&self, movie_id: i32, movie: &MovieDataDb) -> Result<()> {
+        let mut state = self.state();
+
+        if !state.movies.contains_key(&movie_id) {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        state.add_tags(&movie.tags);
+        state.movies.insert(movie_id, movie.clone());
+
+        Ok(())
+    }
+
+    async fn delete_movie(&self, movie_id: i32) -> Result<()> {
+        let mut state = self.state();
+
+        state.movies.remove(&movie_id).ok_or(MovieServiceError::MovieNotFound)?;
+
+        state.movie_created_at.remove(&movie_id);
+        state.popularity_scores.remove(&movie_id);
+        state.translations.retain(|(translated_id, _), _| *translated_id != movie_id);
+        state.ratings.retain(|(_, rated_id), _| *rated_id != movie_id);
+        state.favorites.retain(|(_, favorite_id)| *favorite_id != movie_id);
+        state.watched.retain(|(_, watched_id)| *watched_id != movie_id);
+        state.engagement.retain(|(engaged_id, _, _), _| *engaged_id != movie_id);
+        state.franchise_movies.retain(|(_, entry_id), _| *entry_id != movie_id);
+        state.collection_movies.retain(|(_, entry_id), _| *entry_id != movie_id);
+
+        Ok(())
+    }
+
+    async fn get_movie_minimum_age(&self, movie_id: i32) -> Result<i32> {
+        let state = self.state();
+        let movie = state.movies.get(&movie_id).ok_or(MovieServiceError::MovieNotFound)?;
+
+        Ok(state.minimum_age(movie))
+    }
+
+    async fn get_client_age_limit(&self, client_name: &str) -> Result<Option<i32>> {
+        Ok(self.state().client_age_limits.get(client_name).copied())
+    }
+
+    async fn get_languages(&self) -> Result<Vec<Language>> {
+        Ok(self.state().languages.iter().enumerate()
+            .map(|(index, language_name)| Language { language_id: index as i32 + 1, language_name: language_name.clone(), language_code: None })
+            .collect())
+    }
+
+    async fn get_language(&self, language_id: i32) -> Result<Option<Language>> {
+        Ok(self.get_languages().await?.into_iter().find(|language| language.language_id == language_id))
+    }
+
+    async fn get_movie_translations(&self, movie_id: i32) -> Result<Vec<MovieTranslation>> {
+        let state = self.state();
+
+        Ok(state.translations.iter()
+            .filter(|((translated_id, _), _)| *translated_id == movie_id)
+            .map(|((movie_id, language_id), translation)| to_translation(&state, *movie_id, *language_id, translation))
+            .collect())
+    }
+
+    // languages have no codes here, the preferences match lowercase names
+    async fn get_preferred_translations(&self, languages: &[String], movie_ids: &[i32]) -> Result<Vec<MovieTranslation>> {
+        let state = self.state();
+        let rank = |language_id: i32| languages.iter().position(|language| *language == name_of(&state.languages, language_id).to_lowercase());
+
+        let mut preferred: BTreeMap<i32, (usize, MovieTranslation)> = BTreeMap::new();
+
+        for ((movie_id, language_id), translation) in &state.translations {
+            let Some(position) = rank(*language_id).filter(|_| movie_ids.contains(movie_id)) else {
+                continue;
+            };
+
+            if preferred.get(movie_id).is_none_or(|(best, _)| position < *best) {
+                preferred.insert(*movie_id, (position, to_translation(&state, *movie_id, *language_id, translation)));
+            }
+        }
+
+        Ok(preferred.into_values().map(|(_, translation)| translation).collect())
+    }
+
+    async fn upsert_movie_translation(&self, movie_id: i32, language_id: i32, translation: &MovieTranslationConstructor) -> Result<()> {
+        self.state().translations.insert((movie_id, language_id), translation.clone());
+
+        Ok(())
+    }
+
+    async fn delete_movie_translation(&self, movie_id: i32, language_id: i32) -> Result<()> {
+        self.state().translations.remove(&(movie_id, language_id));
+
+        Ok(())
+    }
+
+    async fn get_countries(&self) -> Result<Vec<Country>> {
+        Ok(self.state().countries.iter().enumerate()
+            .map(|(index, country_name)| Country { country_id: index as i32 + 1, country_name: country_name.clone() })
+            .collect())
+    }
+
+    async fn get_country(&self, country_id: i32) -> Result<Option<Country>> {
+        Ok(self.get_countries().await?.into_iter().find(|country| country.country_id == country_id))
+    }
+
+    async fn get_genres(&self) -> Result<Vec<Genre>> {
+        Ok(self.state().genres.iter().enumerate()
+            .map(|(index, genre_name)| Genre { genre_id: index as i32 + 1, genre_name: genre_name.clone() })
+            .collect())
+    }
+
+    async fn get_genre(&self, genre_id: i32) -> Result<Option<Genre>> {
+        Ok(self.get_genres().await?.into_iter().find(|genre| genre.genre_id == genre_id))
+    }
+
+    async fn get_classifications(&self) -> Result<Vec<Classification>> {
+        let state = self.state();
+
+        Ok(state.classifications.iter().enumerate()
+            .map(|(index, classification_name)| {
+                let classification_id = index as i32 + 1;
+
+                Classification {
+                    classification_id,
+                    classification_name: classification_name.clone(),
+                    minimum_age: state.minimum_ages.get(&classification_id).copied().unwrap_or_default(),
+                }
+            })
+            .collect())
+    }
+
+    async fn get_classification(&self, classification_id: i32) -> Result<Option<Classification>> {
+        Ok(self.get_classifications().await?.into_iter().find(|classification| classification.classification_id == classification_id))
+    }
+
+    async fn create_language(&self, language_name: String, _language_code: Option<String>) -> Result<()> {
+        self.state().languages.push(language_name);
+
+        Ok(())
+    }
+
+    async fn create_country(&self, country_name: String) -> Result<()> {
+        self.state().countries.push(country_name);
+
+        Ok(())
+    }
+
+    async fn create_genre(&self, genre_name: String) -> Result<()> {
+        self.state().genres.push(genre_name);
+
+        Ok(())
+    }
+
+    async fn create_classification(&self, classification_name: String, minimum_age: i32) -> Result<()> {
+        let mut state = self.state();
+
+        state.classifications.push(classification_name);
+        let classification_id = state.classifications.len() as i32;
+        state.minimum_ages.insert(classification_id, minimum_age);
+
+        Ok(())
+    }
+
+    async fn get_basic_movie_page(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Result<Vec<BasicMovie>> {
+        let state = self.state();
+
+        Ok(state.basic_movies(state.page_movie_ids(page, quantity, age_limit, filter), None))
+    }
+
+    async fn get_movie_page(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Result<Vec<Movie>> {
+        let state = self.state();
+
+        Ok(state.page_movie_ids(page, quantity, age_limit, filter).into_iter()
+            .map(|movie_id| state.to_movie(movie_id, &state.movies[&movie_id]))
+            .collect())
+    }
+
+    async fn get_movie_search(&self, movie_name: String, age_limit: Option<i32>, tag_names: &[String]) -> Result<Vec<Movie>> {
+        let state = self.state();
+        let movie_name = movie_name.to_lowercase();
+
+        Ok(state.movies.iter()
+            .filter(|(movie_id, movie)| state.allowed(**movie_id, age_limit)
+                && (movie.distribution_title.to_lowercase().contains(&movie_name) || movie.original_title.to_lowercase().contains(&movie_name))
+                && tag_names.iter().all(|tag_name| movie.tags.contains(tag_name)))
+            .map(|(movie_id, movie)| state.to_movie(*movie_id, movie))
+            .collect())
+    }
+
+    async fn get_basic_movies_by_ids(&self, movie_ids: &[i32], age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        Ok(self.state().basic_movies(movie_ids.iter().copied(), age_limit))
+    }
+
+    async fn get_movie_features(&self) -> Result<Vec<MovieFeatures>> {
+        let state = self.state();
+
+        Ok(state.movies.iter()
+            .map(|(movie_id, movie)| MovieFeatures {
+                movie_id: *movie_id,
+                original_language_id: movie.original_language_id,
+                classification_id: movie.classification_id,
+                production_year: movie.production_year,
+                genre_ids: vec![movie.genre_id],
+                country_ids: vec![movie.origin_country_id],
+                tag_ids: state.tags.iter()
+                    .filter(|(_, tag_name)| movie.tags.contains(tag_name))
+                    .map(|(tag_id, _)| *tag_id)
+                    .collect(),
+            })
+            .collect())
+    }
+
+    async fn set_rating(&self, client_name: &str, movie_id: i32, rating: i32) -> Result<()> {
+        let mut state = self.state();
+
+        if !state.movies.contains_key(&movie_id) {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        state.client_id(client_name);
+        state.ratings.insert((client_name.to_string(), movie_id), rating);
+
+        Ok(())
+    }
+
+    async fn delete_rating(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        self.state().ratings.remove(&(client_name.to_string(), movie_id));
+
+        Ok(())
+    }
+
+    async fn add_favorite(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        let mut state = self.state();
+        let favorite = (client_name.to_string(), movie_id);
+
+        if !state.movies.contains_key(&movie_id) {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        state.client_id(client_name);
+
+        if !state.favorites.contains(&favorite) {
+            state.favorites.push(favorite);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_favorite(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        self.state().favorites.retain(|(favorite_client, favorite_id)| (favorite_client.as_str(), *favorite_id) != (client_name, movie_id));
+
+        Ok(())
+    }
+
+    // watching again moves the movie to the front of the history
+    async fn add_watched(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        let mut state = self.state();
+
+        if !state.movies.contains_key(&movie_id) {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        state.client_id(client_name);
+        state.watched.retain(|(watched_client, watched_id)| (watched_client.as_str(), *watched_id) != (client_name, movie_id));
+        state.watched.push((client_name.to_string(), movie_id));
+
+        Ok(())
+    }
+
+    async fn get_favorite_movies(&self, client_name: &str, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let state = self.state();
+
+        let movie_ids = state.favorites.iter().rev()
+            .filter(|(favorite_client, _)| favorite_client == client_name)
+            .map(|(_, movie_id)| *movie_id);
+
+        Ok(state.basic_movies(movie_ids, age_limit))
+    }
+
+    async fn get_watched_movies(&self, client_name: &str, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let state = self.state();
+
+        let movie_ids = state.watched.iter().rev()
+            .filter(|(watched_client, _)| watched_client == client_name)
+            .map(|(_, movie_id)| *movie_id);
+
+        Ok(state.basic_movies(movie_ids, age_limit))
+    }
+
+    async fn get_watched_movie_ids(&self, client_name: &str) -> Result<Vec<i32>> {
+        Ok(self.state().watched.iter()
+            .filter(|(watched_client, _)| watched_client == client_name)
+            .map(|(_, movie_id)| *movie_id)
+            .collect())
+    }
+
+    async fn get_interactions(&self, client_name: Option<&str>) -> Result<Vec<Interaction>> {
+        let state = self.state();
+
+        let mut pairs: Vec<(&str, i32)> = state.ratings.keys().map(|(client, movie_id)| (client.as_str(), *movie_id))
+            .chain(state.favorites.iter().map(|(client, movie_id)| (client.as_str(), *movie_id)))
+            .chain(state.watched.iter().map(|(client, movie_id)| (client.as_str(), *movie_id)))
+            .filter(|(client, _)| client_name.is_none_or(|client_name| client_name == *client))
+            .collect();
+
+        pairs.sort();
+        pairs.dedup();
+
+        Ok(pairs.into_iter()
+            .map(|(client, movie_id)| {
+                let favorite = state.favorites.iter().any(|(favorite_client, favorite_id)| favorite_client == client && *favorite_id == movie_id);
+                let rating = state.ratings.get(&(client.to_string(), movie_id)).copied();
+
+                Interaction {
+                    client_id: find_id(&state.clients, client).expect("known client"),
+                    movie_id,
+                    strength: rating.unwrap_or(if favorite { 5 } else { 3 }),
+                }
+            })
+            .collect())
+    }
+
+    // counters of movies that no longer exist are dropped, like the join of the insert
+    async fn add_engagement_counts(&self, counts: &[EngagementCount]) -> Result<()> {
+        let mut state = self.state();
+
+        for count in counts {
+            if state.movies.contains_key(&count.movie_id) {
+                *state.engagement.entry((count.movie_id, count.bucket_start, count.event)).or_default() += count.event_count;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn refresh_popularity_scores(&self) -> Result<()> {
+        let mut state = self.state();
+        let now = Utc::now();
+
+        let mut scores: HashMap<i32, f64> = state.movies.keys().map(|movie_id| (*movie_id, 0.0)).collect();
+
+        for ((movie_id, bucket_start, event), event_count) in &state.engagement {
+            if *bucket_start > now - Duration::days(30) {
+                let age_seconds = (now - *bucket_start).num_seconds() as f64;
+
+                *scores.entry(*movie_id).or_default() += *event_count as f64 * engagement_weight(*event) * 0.5_f64.powf(age_seconds / 259200.0);
+            }
+        }
+
+        state.popularity_scores = scores;
+
+        Ok(())
+    }
+
+    async fn get_trending_movies(&self, window_hours: i32, quantity: i64, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let state = self.state();
+        let since = Utc::now() - Duration::hours(window_hours as i64);
+
+        let mut scores: BTreeMap<i32, f64> = BTreeMap::new();
+
+        for ((movie_id, bucket_start, event), event_count) in &state.engagement {
+            if *bucket_start >= since {
+                *scores.entry(*movie_id).or_default() += *event_count as f64 * engagement_weight(*event);
+            }
+        }
+
+        let mut ranking: Vec<(i32, f64)> = scores.into_iter().collect();
+        ranking.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then(a_id.cmp(b_id)));
+
+        let mut movies = state.basic_movies(ranking.into_iter().map(|(movie_id, _)| movie_id), age_limit);
+        movies.truncate(quantity.max(0) as usize);
+
+        Ok(movies)
+    }
+
+    async fn get_tags_by_prefix(&self, prefix: &str, quantity: i64) -> Result<Vec<Tag>> {
+        let state = self.state();
+
+        let mut tags: Vec<Tag> = state.tags.iter()
+            .filter(|(_, tag_name)| tag_name.starts_with(prefix))
+            .map(|(tag_id, tag_name)| Tag {
+                tag_id: *tag_id,
+                tag_name: tag_name.clone(),
+                movie_count: state.movies.values().filter(|movie| movie.tags.contains(tag_name)).count() as i64,
+            })
+            .collect();
+
+        tags.sort_by(|a, b| b.movie_count.cmp(&a.movie_count).then(a.tag_name.cmp(&b.tag_name)));
+        tags.truncate(quantity.max(0) as usize);
+
+        Ok(tags)
+    }
+
+    async fn add_movie_tag(&self, movie_id: i32, tag_name: String) -> Result<()> {
+        let mut state = self.state();
+
+        let movie = state.movies.get_mut(&movie_id).ok_or(MovieServiceError::MovieNotFound)?;
+
+        if !movie.tags.contains(&tag_name) {
+            movie.tags.push(tag_name.clone());
+        }
+
+        state.add_tags(&[tag_name]);
+
+        Ok(())
+    }
+
+    async fn delete_movie_tag(&self, movie_id: i32, tag_name: &str) -> Result<()> {
+        if let Some(movie) = self.state().movies.get_mut(&movie_id) {
+            movie.tags.retain(|movie_tag| movie_tag != tag_name);
+        }
+
+        Ok(())
+    }
+
+    async fn get_tag_id(&self, tag_name: &str) -> Result<Option<i32>> {
+        Ok(self.state().tags.iter().find(|(_, existing)| *existing == tag_name).map(|(tag_id, _)| *tag_id))
+    }
+
+    async fn merge_tags(&self, source_tag_id: i32, target_tag_id: i32) -> Result<()> {
+        let mut state = self.state();
+
+        let Some(source) = state.tags.remove(&source_tag_id) else {
+            return Ok(());
+        };
+        let target = state.tags[&target_tag_id].clone();
+
+        for movie in state.movies.values_mut() {
+            if movie.tags.contains(&source) {
+                movie.tags.retain(|movie_tag| *movie_tag != source);
+
+                if !movie.tags.contains(&target) {
+                    movie.tags.push(target.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_franchises(&self) -> Result<Vec<Franchise>> {
+        let mut franchises: Vec<Franchise> = self.state().franchises.values().cloned().collect();
+        franchises.sort_by(|a, b| a.franchise_name.cmp(&b.franchise_name));
+
+        Ok(franchises)
+    }
+
+    async fn get_franchise(&self, franchise_id: i32) -> Result<Franchise> {
+        self.state().franchises.get(&franchise_id).cloned().ok_or(MovieServiceError::FranchiseNotFound)
+    }
+
+    async fn create_franchise(&self, franchise: &FranchiseConstructor) -> Result<i32> {
+        let mut state = self.state();
+        let franchise_id = next_id(&state.franchises);
+
+        state.franchises.insert(franchise_id, Franchise {
+            franchise_id,
+            franchise_name: franchise.franchise_name.clone(),
+            description: franchise.description.clone(),
+        });
+
+        Ok(franchise_id)
+    }
+
+    async fn get_franchise_movies(&self, franchise_id: i32, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let state = self.state();
+
+        Ok(state.basic_movies(MemoryState::ordered_movies(&state.franchise_movies, franchise_id), age_limit))
+    }
+
+    async fn set_franchise_movie(&self, franchise_id: i32, entry: &OrderedEntryConstructor) -> Result<()> {
+        let mut state = self.state();
+
+        if !state.franchises.contains_key(&franchise_id) {
+            return Err(MovieServiceError::FranchiseNotFound);
+        }
+
+        if !state.movies.contains_key(&entry.movie_id) {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        MemoryState::set_entry(&mut state.franchise_movies, franchise_id, entry);
+
+        Ok(())
+    }
+
+    async fn delete_franchise_movie(&self, franchise_id: i32, movie_id: i32) -> Result<()> {
+        self.state().franchise_movies.remove(&(franchise_id, movie_id)).ok_or(MovieServiceError::MovieNotFound)?;
+
+        Ok(())
+    }
+
+    async fn get_collections(&self) -> Result<Vec<Collection>> {
+        let mut collections: Vec<Collection> = self.state().collections.values().cloned().collect();
+        collections.sort_by_key(|collection| Reverse(collection.created_at));
+
+        Ok(collections)
+    }
+
+    async fn get_collection(&self, collection_id: i32) -> Result<Collection> {
+        self.state().collections.get(&collection_id).cloned().ok_or(MovieServiceError::CollectionNotFound)
+    }
+
+    async fn create_collection(&self, collection: &CollectionConstructor) -> Result<i32> {
+        let mut state = self.state();
+        let collection_id = next_id(&state.collections);
+
+        state.collections.insert(collection_id, Collection {
+            collection_id,
+            title: collection.title.clone(),
+            description: collection.description.clone(),
+            cover_image_url: collection.cover_image_url.clone(),
+            created_at: Utc::now(),
+        });
+
+        Ok(collection_id)
+    }
+
+    async fn update_collection(&self, collection_id: i32, collection: &CollectionConstructor) -> Result<()> {
+        let mut state = self.state();
+        let stored = state.collections.get_mut(&collection_id).ok_or(MovieServiceError::CollectionNotFound)?;
+
+        stored.title = collection.title.clone();
+        stored.description = collection.description.clone();
+        stored.cover_image_url = collection.cover_image_url.clone();
+
+        Ok(())
+    }
+
+    async fn delete_collection(&self, collection_id: i32) -> Result<()> {
+        let mut state = self.state();
+
+        state.collections.remove(&collection_id).ok_or(MovieServiceError::CollectionNotFound)?;
+        state.collection_movies.retain(|(entry_collection_id, _), _| *entry_collection_id != collection_id);
+
+        Ok(())
+    }
+
+    async fn get_collection_movies(&self, collection_id: i32, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let state = self.state();
+
+        Ok(state.basic_movies(MemoryState::ordered_movies(&state.collection_movies, collection_id), age_limit))
+    }
+
+    async fn set_collection_movie(&self, collection_id: i32, entry: &OrderedEntryConstructor) -> Result<()> {
+        let mut state = self.state();
+
+        if !state.collections.contains_key(&collection_id) {
+            return Err(MovieServiceError::CollectionNotFound);
+        }
+
+        if !state.movies.contains_key(&entry.movie_id) {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        MemoryState::set_entry(&mut state.collection_movies, collection_id, entry);
+
+        Ok(())
+    }
+
+    async fn delete_collection_movie(&self, collection_id: i32, movie_id: i32) -> Result<()> {
+        self.state().collection_movies.remove(&(collection_id, movie_id)).ok_or(MovieServiceError::MovieNotFound)?;
+
+        Ok(())
+    }
+
+    // no language codes here, subtitles count as spanish by name
+    async fn get_catalog_stats(&self) -> Result<CatalogStats> {
+        let state = self.state();
+        let total_movies = state.movies.len() as i64;
+
+        let average_runtime_minutes = (total_movies > 0).then(|| {
+            state.movies.values().map(|movie| movie.runtime_minutes as f64).sum::<f64>() / total_movies as f64
+        });
+
+        let spanish_subtitled = state.movies.values()
+            .filter(|movie| movie.subtitle_language_ids.iter().any(|language_id| name_of(&state.languages, *language_id) == "Spanish"))
+            .count();
+
+        let spanish_subtitle_share = if total_movies == 0 {
+            0.0
+        } else {
+            spanish_subtitled as f64 / total_movies as f64
+        };
+
+        let mut decades: BTreeMap<i32, i64> = BTreeMap::new();
+        let mut months: BTreeMap<DateTime<Utc>, i64> = BTreeMap::new();
+
+        for (movie_id, movie) in &state.movies {
+            *decades.entry(movie.production_year / 10 * 10).or_default() += 1;
+
+            let created_at = state.movie_created_at[movie_id];
+            let month = created_at.date_naive().with_day(1).expect("first day of the month")
+                .and_hms_opt(0, 0, 0).expect("midnight").and_utc();
+            *months.entry(month).or_default() += 1;
+        }
+
+        let mut total = 0;
+        let monthly_growth = months.into_iter()
+            .map(|(month, movies_added)| {
+                total += movies_added;
+                MonthlyGrowth { month, movies_added, total_movies: total }
+            })
+            .collect();
+
+        Ok(CatalogStats {
+            total_movies,
+            average_runtime_minutes,
+            spanish_subtitle_share,
+            by_genre: state.counts_by(&state.genres, |movie| movie.genre_id),
+            by_country: state.counts_by(&state.countries, |movie| movie.origin_country_id),
+            by_original_language: state.counts_by(&state.languages, |movie| movie.original_language_id),
+            by_classification: state.counts_by(&state.classifications, |movie| movie.classification_id),
+            by_decade: decades.into_iter().map(|(decade, movie_count)| DecadeCount { decade, movie_count }).collect(),
+            monthly_growth,
+            computed_at: Utc::now(),
+        })
+    }
+}
+
+fn to_translation(state: &MemoryState, movie_id: i32, language_id: i32, translation: &MovieTranslationConstructor) -> MovieTranslation {
+    MovieTranslation {
+        movie_id,
+        language: name_of(&state.languages, language_id),
+        title: translation.title.clone(),
+        summary: translation.summary.clone(),
+        tagline: translation.tagline.clone(),
+    }
+}
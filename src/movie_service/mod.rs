@@ -1,22 +1,103 @@
-use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, routing::{delete, get, post, put}, Extension, Json, Router};
-use domain::{ClassificationConstructor, CountryConstructor, GenreConstructor, LanguageConstructor, Movie, MovieConstructor};
+use axum::{extract::{Path, Query, State}, http::{header, HeaderMap, StatusCode}, middleware, response::IntoResponse, routing::{delete, get, post, put}, Extension, Json, Router};
+use domain::{ClassificationConstructor, CollectionConstructor, CountryConstructor, FranchiseConstructor, GenreConstructor, LanguageConstructor, Movie, MovieConstructor, MovieFilter, MovieTranslationConstructor, OrderedEntryConstructor, RatingConstructor, TagConstructor, TagMerge, TrendingWindow};
+use engagement::{EngagementEvent, EngagementRecorder};
+use error::MovieServiceError;
 use movie_database::MovieDb;
+use serde::Deserialize;
+use serde_json::json;
+use recommendation::RecommendationModel;
+use repository::MovieRepository;
+use similarity::{SimilarityCache, SimilarityWeights};
+use stats::StatsCache;
 use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
 use tracing::error;
 
-use crate::auth_middleware::ClientInfo;
+use crate::auth_middleware::{self, ClientInfo};
 
 mod domain;
+mod engagement;
 pub mod error;
 mod movie_database;
+#[cfg(test)]
+mod memory_repository;
+mod recommendation;
+mod repository;
 mod service;
+mod similarity;
+mod stats;
+
+#[derive(Debug, Deserialize)]
+struct LanguageQuery {
+    lang: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct TagAutocompleteQuery {
+    #[serde(default)]
+    prefix: String,
+    quantity: Option<i64>
+}
+
+#[derive(Debug, Deserialize)]
+struct QuantityQuery {
+    quantity: Option<usize>
+}
+
+#[derive(Debug, Deserialize)]
+struct TrendingQuery {
+    #[serde(default)]
+    window: TrendingWindow,
+    quantity: Option<i64>
+}
 
 #[derive(Clone, Debug)]
 struct MovieServiceState {
-    db_pool: PgPool
+    movie_repository: Arc<dyn MovieRepository>,
+    similarity_cache: Arc<SimilarityCache>,
+    recommendation_model: Arc<RecommendationModel>,
+    engagement: Arc<EngagementRecorder>,
+    stats_cache: Arc<StatsCache>
 }
 
 pub fn get_router(db_pool: PgPool) -> Router {
+    let movie_repository: Arc<dyn MovieRepository> = Arc::new(MovieDb::new(db_pool));
+
+    let similarity_cache_ttl = Duration::from_secs_f64(similarity::env_f64("SIMILARITY_CACHE_SECONDS", 600.0));
+    let similarity_cache = SimilarityCache::new(SimilarityWeights::from_env(), similarity_cache_ttl);
+
+    let recommendation_model = Arc::new(RecommendationModel::default());
+    let recommendation_refresh = Duration::from_secs_f64(similarity::env_f64("RECOMMENDATION_REFRESH_SECONDS", 900.0));
+    recommendation::spawn_refresh_job(movie_repository.clone(), recommendation_model.clone(), recommendation_refresh);
+
+    let engagement = Arc::new(EngagementRecorder::default());
+    let engagement_flush = Duration::from_secs_f64(similarity::env_f64("ENGAGEMENT_FLUSH_SECONDS", 10.0));
+    let popularity_refresh = Duration::from_secs_f64(similarity::env_f64("POPULARITY_REFRESH_SECONDS", 300.0));
+    engagement::spawn_flush_job(movie_repository.clone(), engagement.clone(), engagement_flush, popularity_refresh);
+
+    let stats_cache_ttl = Duration::from_secs_f64(similarity::env_f64("STATS_CACHE_SECONDS", 300.0));
+
+    // public contributions need a verified email
+    let verified_router = Router::new()
+        .route("/movie/:movieId/rating", put(rate_movie))
+        .route_layer(middleware::from_fn(auth_middleware::require_verified_email));
+
+    // tags, franchises and collections are curated by admins, who also see the catalog stats
+    let admin_router = Router::new()
+        .route("/movie/:movieId/tag", put(add_movie_tag))
+        .route("/movie/:movieId/tag/:tagName", delete(delete_movie_tag))
+        .route("/tag/merge", post(merge_tags))
+        .route("/franchise", post(create_franchise))
+        .route("/franchise/:franchiseId/movie", put(set_franchise_movie))
+        .route("/franchise/:franchiseId/movie/:movieId", delete(delete_franchise_movie))
+        .route("/collection", post(create_collection))
+        .route("/collection/:collectionId", put(update_collection))
+        .route("/collection/:collectionId", delete(delete_collection))
+        .route("/collection/:collectionId/movie", put(set_collection_movie))
+        .route("/collection/:collectionId/movie/:movieId", delete(delete_collection_movie))
+        .route("/stats", get(get_catalog_stats))
+        .route_layer(middleware::from_fn(auth_middleware::require_admin));
+
     Router::new()
         .route("/", get(health_check))
         .route("/genre", get(get_genres))
@@ -29,19 +110,42 @@ pub fn get_router(db_pool: PgPool) -> Router {
         .route("/language/:languageId", get(get_language))
         .route("/movie/page/:pageIndex/:quantity", get(get_movies))
         .route("/movie/:movieId", get(get_movie))
+        .route("/movie/:movieId/translation", get(get_movie_translations))
+        .route("/movie/:movieId/similar", get(get_similar_movies))
         .route("/basic_data_movie/page/:pageIndex/:quantity", get(get_movie_basic_data))
         .route("/search/:movieName", get(get_movie_search))
+        .route("/trending", get(get_trending_movies))
+        .route("/me/recommendations", get(get_recommendations))
+        .route("/me/favorites", get(get_favorite_movies))
+        .route("/me/watched", get(get_watched_movies))
+        .route("/tag", get(get_tag_autocomplete))
+        .route("/franchise", get(get_franchises))
+        .route("/franchise/:franchiseId", get(get_franchise))
+        .route("/collection", get(get_collections))
+        .route("/collection/:collectionId", get(get_collection))
         // POSTS
         .route("/language", post(create_language))
         .route("/classification", post(create_classification))
         .route("/country", post(create_country))
         .route("/genre", post(create_genre))
         .route("/movie", post(create_movie))
+        .route("/movie/:movieId/translation", put(set_movie_translation))
+        .route("/movie/:movieId/translation/:languageName", delete(delete_movie_translation))
+        .route("/movie/:movieId/rating", delete(delete_rating))
+        .route("/movie/:movieId/favorite", put(add_favorite))
+        .route("/movie/:movieId/favorite", delete(delete_favorite))
+        .route("/movie/:movieId/watched", put(add_watched))
 
         .route("/movie/:movieId", delete(delete_movie))
         .route("/movie", put(update_movie))
+        .merge(verified_router)
+        .merge(admin_router)
         .with_state(MovieServiceState {
-            db_pool,
+            movie_repository,
+            similarity_cache: Arc::new(similarity_cache),
+            recommendation_model,
+            engagement,
+            stats_cache: Arc::new(StatsCache::new(stats_cache_ttl))
         })
 }
 
@@ -50,9 +154,7 @@ async fn health_check(Extension(client_info): Extension<ClientInfo>) -> String {
 }
 
 async fn create_language(State(state): State<MovieServiceState>, Json(language_constructor): Json<LanguageConstructor>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
-
-    db.create_language_db(language_constructor.language_name).await.map_err(|err| {
+    state.movie_repository.create_language(language_constructor.language_name, language_constructor.language_code).await.map_err(|err| {
         error!("Error creating a lenguage: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -61,9 +163,7 @@ async fn create_language(State(state): State<MovieServiceState>, Json(language_c
 }
 
 async fn create_country(State(state): State<MovieServiceState>, Json(country_constructor): Json<CountryConstructor>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
-
-    db.create_country_db(country_constructor.country_name).await.map_err(|err| {
+    state.movie_repository.create_country(country_constructor.country_name).await.map_err(|err| {
         error!("Error creating country: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -72,9 +172,7 @@ async fn create_country(State(state): State<MovieServiceState>, Json(country_con
 }
 
 async fn create_genre(State(state): State<MovieServiceState>, Json(genre_constructor): Json<GenreConstructor>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
-
-    db.create_genre_db(genre_constructor.genre_name).await.map_err(|err| {
+    state.movie_repository.create_genre(genre_constructor.genre_name).await.map_err(|err| {
         error!("Error creating gengre: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -83,45 +181,106 @@ async fn create_genre(State(state): State<MovieServiceState>, Json(genre_constru
 }
 
 async fn create_movie(State(state): State<MovieServiceState>, Json(movie_constructor): Json<MovieConstructor>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    service::create_movie(movie_database, movie_constructor).await.map_err(|err| {
-        error!("Error creating movie: {}", err);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    service::create_movie(state.movie_repository.as_ref(), movie_constructor).await.map_err(movie_write_status)?;
 
     Ok(StatusCode::OK)
 }
 
 async fn delete_movie(State(state): State<MovieServiceState>, Path(id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
-
-    db.delete_movie_db(id).await.map_err(|err| {
-        error!("Error creating Movie: {}", err);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    state.movie_repository.delete_movie(id).await.map_err(movie_write_status)?;
 
     Ok(StatusCode::OK)
 }
 
 async fn update_movie(State(state): State<MovieServiceState>, Json(movie): Json<Movie>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
+    service::update_movie(state.movie_repository.as_ref(), movie).await.map_err(movie_write_status)?;
+
+    Ok(StatusCode::OK)
+}
+
+fn movie_write_status(err: MovieServiceError) -> StatusCode {
+    match err {
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        MovieServiceError::InvalidLanguageName
+        | MovieServiceError::InvalidCountryName
+        | MovieServiceError::InvalidGenreName
+        | MovieServiceError::InvalidClassificationName
+        | MovieServiceError::InvalidTagName => StatusCode::BAD_REQUEST,
+        MovieServiceError::DuplicateRelease => StatusCode::CONFLICT,
+        err => {
+            error!("Error writing movie: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
 
-    db.update_movie_db(movie).await.map_err(|err| {
-        error!("Error updating movie: {}", err);
+fn requested_languages(language_query: &LanguageQuery, headers: &HeaderMap) -> Vec<String> {
+    let accept_language = headers.get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+
+    service::parse_language_preferences(language_query.lang.as_deref(), accept_language)
+}
+
+async fn get_client_age_limit(db: &dyn MovieRepository, client_info: &ClientInfo) -> Result<Option<i32>, StatusCode> {
+    db.get_client_age_limit(&client_info.client_name).await.map_err(|err| {
+        error!("Error getting the client age limit: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn get_movie_translations(State(state): State<MovieServiceState>, Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let translations = state.movie_repository.get_movie_translations(movie_id).await.map_err(|err| {
+        error!("Error getting movie translations: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+
+    Ok((StatusCode::OK, Json(translations)))
+}
+
+async fn set_movie_translation(State(state): State<MovieServiceState>, Path(movie_id): Path<i32>,
+    Json(translation): Json<MovieTranslationConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    service::set_movie_translation(state.movie_repository.as_ref(), movie_id, translation).await.map_err(|err| match err {
+        MovieServiceError::InvalidLanguageName => StatusCode::BAD_REQUEST,
+        err => {
+            error!("Error setting movie translation: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_movie_translation(State(state): State<MovieServiceState>,
+    Path((movie_id, language_name)): Path<(i32, String)>) -> Result<impl IntoResponse, StatusCode> {
+    service::delete_movie_translation(state.movie_repository.as_ref(), movie_id, language_name).await.map_err(|err| match err {
+        MovieServiceError::InvalidLanguageName => StatusCode::BAD_REQUEST,
+        err => {
+            error!("Error deleting movie translation: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
     Ok(StatusCode::OK)
 }
 
-async fn get_movie_search(State(state): State<MovieServiceState>, Path(movie_name): Path<String>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool); 
+// only the tags of the filter apply to the search
+async fn get_movie_search(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, Query(filter): Query<MovieFilter>, headers: HeaderMap,
+    Path(movie_name): Path<String>) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
 
-    let movies = db.get_movie_search_db(movie_name).await.map_err(|err| {
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+
+    let mut movies = db.get_movie_search(movie_name, age_limit, &filter.tag_names()).await.map_err(|err| {
         error!("Error getting movie by name: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    service::localize_movies(db, &mut movies, &requested_languages(&language_query, &headers)).await.map_err(|err| {
+        error!("Error localizing movies: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
     let movies = serde_json::to_string(&movies).map_err(|err| {
         error!("Error stringifing movies: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -130,16 +289,30 @@ async fn get_movie_search(State(state): State<MovieServiceState>, Path(movie_nam
     Ok((StatusCode::OK, movies))
 }
 
-async fn get_movie_basic_data(State(state): State<MovieServiceState>, Path((page, quantity)): Path<(i64, i64)>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
+async fn get_movie_basic_data(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, Query(filter): Query<MovieFilter>, headers: HeaderMap, Path((page, quantity)): Path<(i64, i64)>) -> Result<impl IntoResponse, StatusCode> {
+    if !filter.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let movie_database = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(movie_database, &client_info).await?;
 
-    let movies = movie_database.get_basic_movie_page(page, quantity)
+    let mut movies = movie_database.get_basic_movie_page(page, quantity, age_limit, &filter)
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
+    service::localize_basic_movies(movie_database, &mut movies, &requested_languages(&language_query, &headers))
+        .await
+        .map_err(|err| {
+            error!("Error localizing movies: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
     let movies_json = serde_json::to_string(&movies).map_err(|err| {
         error!("Error mapping genres to string in serde_json: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -150,9 +323,7 @@ async fn get_movie_basic_data(State(state): State<MovieServiceState>, Path((page
 
 // get classification 
 async fn get_classifications(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
-
-    let classifications = db.get_classifications_db().await.map_err(|err| {
+    let classifications = state.movie_repository.get_classifications().await.map_err(|err| {
         error!("Error getting classifications from db: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -165,12 +336,10 @@ async fn get_classifications(State(state): State<MovieServiceState>) -> Result<i
 }
 
 async fn get_classification(State(state): State<MovieServiceState>, Path(id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
-    let db = MovieDb::new(state.db_pool);
-
-    let classifications = db.get_classification_db(id).await.map_err(|err| {
+    let classifications = state.movie_repository.get_classification(id).await.map_err(|err| {
         error!("Error getting classifications from db: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    })?.ok_or(StatusCode::NOT_FOUND)?;
 
     let classifications = serde_json::to_string(&classifications).map_err(|err| {
         error!("Parsing classifications: {}", err);
@@ -183,9 +352,8 @@ async fn get_classification(State(state): State<MovieServiceState>, Path(id): Pa
 async fn create_classification(State(state): State<MovieServiceState>,
     Json(classification_constructor): Json<ClassificationConstructor>) -> Result<impl IntoResponse, StatusCode> {
 
-    let db = MovieDb::new(state.db_pool);
-
-    db.create_classification_db(classification_constructor.classification_name).await.map_err(|err| {
+    state.movie_repository.create_classification(classification_constructor.classification_name, classification_constructor.minimum_age)
+        .await.map_err(|err| {
         error!("Error creating classification: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -193,16 +361,30 @@ async fn create_classification(State(state): State<MovieServiceState>,
     Ok(StatusCode::OK)
 }
 
-async fn get_movies(State(state): State<MovieServiceState>, Path((page, quantity)): Path<(i64, i64)>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
+async fn get_movies(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, Query(filter): Query<MovieFilter>, headers: HeaderMap, Path((page, quantity)): Path<(i64, i64)>) -> Result<impl IntoResponse, StatusCode> {
+    if !filter.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let movie_database = state.movie_repository.as_ref();
 
-    let movies = movie_database.get_movie_page(page, quantity)
+    let age_limit = get_client_age_limit(movie_database, &client_info).await?;
+
+    let mut movies = movie_database.get_movie_page(page, quantity, age_limit, &filter)
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
+    service::localize_movies(movie_database, &mut movies, &requested_languages(&language_query, &headers))
+        .await
+        .map_err(|err| {
+            error!("Error localizing movies: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
     let movies_json = serde_json::to_string(&movies).map_err(|err| {
         error!("Error mapping genres to string in serde_json: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -211,16 +393,23 @@ async fn get_movies(State(state): State<MovieServiceState>, Path((page, quantity
     Ok((StatusCode::OK, movies_json))
 } 
 
-async fn get_movie(State(state): State<MovieServiceState>, Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
+async fn get_movie(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, headers: HeaderMap, Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let languages = requested_languages(&language_query, &headers);
 
-    let movie = movie_database.get_movie(movie_id)
+    let movie = service::get_movie(state.movie_repository.as_ref(), movie_id, &client_info.client_name, &languages)
         .await
-        .map_err(|err| {
-            error!("Error getting genres in the movie database: {}", err);
-            StatusCode::INTERNAL_SERVER_ERROR
+        .map_err(|err| match err {
+            MovieServiceError::AgeRestricted => StatusCode::FORBIDDEN,
+            MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+            err => {
+                error!("Error getting movie in the movie database: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
         })?;
 
+    state.engagement.record(movie_id, EngagementEvent::View);
+
     let movie_json = serde_json::to_string(&movie).map_err(|err| {
         error!("Error mapping genres to string in serde_json: {}", err);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -230,9 +419,7 @@ async fn get_movie(State(state): State<MovieServiceState>, Path(movie_id): Path<
 } 
 
 async fn get_languages(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    let languages = movie_database.get_languages()
+    let languages = state.movie_repository.get_languages()
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
@@ -247,15 +434,14 @@ async fn get_languages(State(state): State<MovieServiceState>) -> Result<impl In
     Ok((StatusCode::OK, languages_json))
 } 
 
-async fn get_language(State(state): State<MovieServiceState>, Path(country_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    let language = movie_database.get_country(country_id)
+async fn get_language(State(state): State<MovieServiceState>, Path(language_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let language = state.movie_repository.get_language(language_id)
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
     let language_json = serde_json::to_string(&language).map_err(|err| {
         error!("Error mapping genres to string in serde_json: {}", err);
@@ -266,9 +452,7 @@ async fn get_language(State(state): State<MovieServiceState>, Path(country_id):
 } 
 
 async fn get_countries(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    let countries = movie_database.get_countries()
+    let countries = state.movie_repository.get_countries()
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
@@ -284,14 +468,13 @@ async fn get_countries(State(state): State<MovieServiceState>) -> Result<impl In
 } 
 
 async fn get_country(State(state): State<MovieServiceState>, Path(country_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    let country = movie_database.get_country(country_id)
+    let country = state.movie_repository.get_country(country_id)
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
     let country_json = serde_json::to_string(&country).map_err(|err| {
         error!("Error mapping genres to string in serde_json: {}", err);
@@ -302,9 +485,7 @@ async fn get_country(State(state): State<MovieServiceState>, Path(country_id): P
 } 
 
 async fn get_genres(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    let genres = movie_database.get_genres()
+    let genres = state.movie_repository.get_genres()
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
@@ -320,14 +501,13 @@ async fn get_genres(State(state): State<MovieServiceState>) -> Result<impl IntoR
 }
 
 async fn get_genre(State(state): State<MovieServiceState>, Path(genre_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
-    let movie_database = MovieDb::new(state.db_pool);
-
-    let genre = movie_database.get_genre(genre_id)
+    let genre = state.movie_repository.get_genre(genre_id)
         .await
         .map_err(|err| {
             error!("Error getting genres in the movie database: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
     let genre_json = serde_json::to_string(&genre).map_err(|err| {
         error!("Error mapping genres to string in serde_json: {}", err);
@@ -336,3 +516,365 @@ async fn get_genre(State(state): State<MovieServiceState>, Path(genre_id): Path<
 
     Ok((StatusCode::OK, genre_json))
 }
+
+async fn get_similar_movies(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, Query(quantity_query): Query<QuantityQuery>, headers: HeaderMap,
+    Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+    let languages = requested_languages(&language_query, &headers);
+    let quantity = quantity_query.quantity.unwrap_or(10);
+
+    let movies = service::get_similar_movies(db, &state.similarity_cache, movie_id, quantity, age_limit, &languages)
+        .await
+        .map_err(|err| {
+            error!("Error getting similar movies: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((StatusCode::OK, Json(movies)))
+}
+
+async fn get_trending_movies(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, Query(trending_query): Query<TrendingQuery>,
+    headers: HeaderMap) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+    let quantity = trending_query.quantity.unwrap_or(10);
+
+    let mut movies = db.get_trending_movies(trending_query.window.hours(), quantity, age_limit).await.map_err(|err| {
+        error!("Error getting trending movies: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    service::localize_basic_movies(db, &mut movies, &requested_languages(&language_query, &headers)).await.map_err(|err| {
+        error!("Error localizing movies: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(movies)))
+}
+
+// ratings, favorites, watch history and recommendations
+async fn rate_movie(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(movie_id): Path<i32>, Json(rating): Json<RatingConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    service::rate_movie(state.movie_repository.as_ref(), &client_info.client_name, movie_id, rating.rating).await.map_err(|err| match err {
+        MovieServiceError::InvalidRating => StatusCode::BAD_REQUEST,
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error rating movie: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    state.engagement.record(movie_id, EngagementEvent::Rating);
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_rating(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.delete_rating(&client_info.client_name, movie_id).await.map_err(|err| {
+        error!("Error deleting rating: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn add_favorite(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.add_favorite(&client_info.client_name, movie_id).await.map_err(|err| match err {
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error adding favorite: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    state.engagement.record(movie_id, EngagementEvent::Favorite);
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_favorite(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.delete_favorite(&client_info.client_name, movie_id).await.map_err(|err| {
+        error!("Error deleting favorite: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn add_watched(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(movie_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.add_watched(&client_info.client_name, movie_id).await.map_err(|err| match err {
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error adding watched movie: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    state.engagement.record(movie_id, EngagementEvent::Watched);
+
+    Ok(StatusCode::OK)
+}
+
+async fn get_favorite_movies(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+
+    let movies = db.get_favorite_movies(&client_info.client_name, age_limit).await.map_err(|err| {
+        error!("Error getting favorite movies: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(movies)))
+}
+
+async fn get_watched_movies(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+
+    let movies = db.get_watched_movies(&client_info.client_name, age_limit).await.map_err(|err| {
+        error!("Error getting watched movies: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(movies)))
+}
+
+async fn get_recommendations(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, Query(quantity_query): Query<QuantityQuery>,
+    headers: HeaderMap) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+    let languages = requested_languages(&language_query, &headers);
+    let quantity = quantity_query.quantity.unwrap_or(10);
+
+    let recommendations = service::get_recommendations(db, &state.recommendation_model, &client_info.client_name,
+        quantity, age_limit, &languages)
+        .await
+        .map_err(|err| {
+            error!("Error getting recommendations: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((StatusCode::OK, Json(recommendations)))
+}
+
+// tags
+async fn get_tag_autocomplete(State(state): State<MovieServiceState>, Query(query): Query<TagAutocompleteQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let prefix = domain::normalize_tag_name(&query.prefix);
+
+    let tags = state.movie_repository.get_tags_by_prefix(&prefix, query.quantity.unwrap_or(10)).await.map_err(|err| {
+        error!("Error getting tags: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(tags)))
+}
+
+async fn add_movie_tag(State(state): State<MovieServiceState>, Path(movie_id): Path<i32>,
+    Json(tag): Json<TagConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    service::add_movie_tag(state.movie_repository.as_ref(), movie_id, &tag.tag_name).await.map_err(|err| match err {
+        MovieServiceError::InvalidTagName => StatusCode::BAD_REQUEST,
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error adding tag to movie: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_movie_tag(State(state): State<MovieServiceState>,
+    Path((movie_id, tag_name)): Path<(i32, String)>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.delete_movie_tag(movie_id, &domain::normalize_tag_name(&tag_name)).await.map_err(|err| {
+        error!("Error removing tag from movie: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn merge_tags(State(state): State<MovieServiceState>, Json(tag_merge): Json<TagMerge>) -> Result<impl IntoResponse, StatusCode> {
+    service::merge_tags(state.movie_repository.as_ref(), &tag_merge.source, &tag_merge.target).await.map_err(|err| match err {
+        MovieServiceError::InvalidTagName => StatusCode::BAD_REQUEST,
+        err => {
+            error!("Error merging tags: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+// franchises
+async fn get_franchises(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
+    let franchises = state.movie_repository.get_franchises().await.map_err(|err| {
+        error!("Error getting franchises: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(franchises)))
+}
+
+async fn get_franchise(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, headers: HeaderMap, Path(franchise_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+    let languages = requested_languages(&language_query, &headers);
+
+    let franchise = service::get_franchise(db, franchise_id, age_limit, &languages).await.map_err(|err| match err {
+        MovieServiceError::FranchiseNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error getting franchise: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::OK, Json(franchise)))
+}
+
+async fn create_franchise(State(state): State<MovieServiceState>, Json(franchise): Json<FranchiseConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    let franchise_id = state.movie_repository.create_franchise(&franchise).await.map_err(|err| {
+        error!("Error creating franchise: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(json!({ "franchise_id": franchise_id }))))
+}
+
+async fn set_franchise_movie(State(state): State<MovieServiceState>, Path(franchise_id): Path<i32>,
+    Json(entry): Json<OrderedEntryConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.set_franchise_movie(franchise_id, &entry).await.map_err(|err| match err {
+        MovieServiceError::FranchiseNotFound | MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error adding movie to franchise: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_franchise_movie(State(state): State<MovieServiceState>,
+    Path((franchise_id, movie_id)): Path<(i32, i32)>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.delete_franchise_movie(franchise_id, movie_id).await.map_err(|err| match err {
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error removing movie from franchise: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+// collections
+async fn get_collections(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
+    let collections = state.movie_repository.get_collections().await.map_err(|err| {
+        error!("Error getting collections: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(collections)))
+}
+
+async fn get_collection(State(state): State<MovieServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Query(language_query): Query<LanguageQuery>, headers: HeaderMap, Path(collection_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.movie_repository.as_ref();
+
+    let age_limit = get_client_age_limit(db, &client_info).await?;
+    let languages = requested_languages(&language_query, &headers);
+
+    let collection = service::get_collection(db, collection_id, age_limit, &languages).await.map_err(|err| match err {
+        MovieServiceError::CollectionNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error getting collection: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::OK, Json(collection)))
+}
+
+async fn create_collection(State(state): State<MovieServiceState>, Json(collection): Json<CollectionConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    let collection_id = state.movie_repository.create_collection(&collection).await.map_err(|err| {
+        error!("Error creating collection: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(json!({ "collection_id": collection_id }))))
+}
+
+async fn update_collection(State(state): State<MovieServiceState>, Path(collection_id): Path<i32>,
+    Json(collection): Json<CollectionConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.update_collection(collection_id, &collection).await.map_err(|err| match err {
+        MovieServiceError::CollectionNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error updating collection: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_collection(State(state): State<MovieServiceState>, Path(collection_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.delete_collection(collection_id).await.map_err(|err| match err {
+        MovieServiceError::CollectionNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error deleting collection: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn set_collection_movie(State(state): State<MovieServiceState>, Path(collection_id): Path<i32>,
+    Json(entry): Json<OrderedEntryConstructor>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.set_collection_movie(collection_id, &entry).await.map_err(|err| match err {
+        MovieServiceError::CollectionNotFound | MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error adding movie to collection: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_collection_movie(State(state): State<MovieServiceState>,
+    Path((collection_id, movie_id)): Path<(i32, i32)>) -> Result<impl IntoResponse, StatusCode> {
+    state.movie_repository.delete_collection_movie(collection_id, movie_id).await.map_err(|err| match err {
+        MovieServiceError::MovieNotFound => StatusCode::NOT_FOUND,
+        err => {
+            error!("Error removing movie from collection: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+// admin
+async fn get_catalog_stats(State(state): State<MovieServiceState>) -> Result<impl IntoResponse, StatusCode> {
+    let stats = state.stats_cache.get(state.movie_repository.as_ref()).await.map_err(|err| {
+        error!("Error computing catalog stats: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::OK, Json(stats)))
+}
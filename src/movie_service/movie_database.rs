@@ -1,26 +1,74 @@
-use sqlx::{pool, PgPool};
-use tracing::error;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{types::Json, PgPool, Postgres, Transaction};
+
+use super::domain::{BasicMovie, CatalogStats, Classification, Collection, CollectionConstructor, Country, DecadeCount, Franchise, FranchiseConstructor, Genre, Language, MonthlyGrowth, Movie, MovieFilter, MovieRelease, MovieTranslation, MovieTranslationConstructor, OrderedEntryConstructor, StatCount, Tag};
+use super::engagement::EngagementCount;
+use super::error::{MovieServiceError, Result};
+use super::recommendation::Interaction;
+use super::repository::MovieRepository;
+use super::similarity::MovieFeatures;
+
+// postgres codes for unique_violation and foreign_key_violation
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+fn map_unique_violation(err: sqlx::Error) -> MovieServiceError {
+    match err {
+        sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some(UNIQUE_VIOLATION)
+            && db_err.constraint() == Some("movie_release_movie_id_country_id_release_type_key") => MovieServiceError::DuplicateRelease,
+        err => err.into()
+    }
+}
 
-use super::domain::{BasicMovie, Classification, Country, Genre, Language, Movie, MovieConstructor};
-use super::error::Result;
+// writes that reference a movie, franchise or collection that doesn't exist
+fn map_missing_reference(err: sqlx::Error) -> MovieServiceError {
+    match err {
+        sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some(FOREIGN_KEY_VIOLATION) => {
+            match db_err.constraint() {
+                Some("franchise_movie_franchise_id_fkey") => MovieServiceError::FranchiseNotFound,
+                Some("collection_movie_collection_id_fkey") => MovieServiceError::CollectionNotFound,
+                _ => MovieServiceError::MovieNotFound
+            }
+        }
+        err => err.into()
+    }
+}
 
+#[derive(Debug)]
 pub struct MovieDb {
     pool: PgPool
 }
 
+#[derive(Debug, Clone)]
 pub struct MovieDataDb {
     pub distribution_title: String,
     pub original_title: String,
     pub original_language_id: i32,
-    pub has_spanish_subtitles: bool,
+    pub subtitle_language_ids: Vec<i32>,
+    pub audio_language_ids: Vec<i32>,
     pub production_year: i32,
     pub website_url: String,
     pub image_url: String,
-    pub duration_hours: i32,
+    pub runtime_minutes: i32,
     pub summary: Option<String>,
+    pub tagline: Option<String>,
+    pub budget: Option<i64>,
+    pub budget_currency: Option<String>,
+    pub box_office: Option<i64>,
+    pub box_office_currency: Option<String>,
     pub origin_country_id: i32,
     pub genre_id: i32,
     pub classification_id: i32,
+    pub releases: Vec<MovieReleaseDb>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MovieReleaseDb {
+    pub country_id: i32,
+    pub release_date: NaiveDate,
+    pub release_type: String,
 }
 
 impl MovieDb {
@@ -28,30 +76,98 @@ impl MovieDb {
         MovieDb { pool }
     }
 
-    pub async fn insert_movie(&self, movie: &MovieDataDb) -> Result<()> {
+    async fn insert_releases(tx: &mut Transaction<'_, Postgres>, movie_id: i32, releases: &[MovieReleaseDb]) -> Result<()> {
+        let country_ids: Vec<i32> = releases.iter().map(|release| release.country_id).collect();
+        let release_dates: Vec<NaiveDate> = releases.iter().map(|release| release.release_date).collect();
+        let release_types: Vec<String> = releases.iter().map(|release| release.release_type.clone()).collect();
+
+        sqlx::query!(
+            "INSERT INTO movie_release(movie_id, country_id, release_date, release_type)
+SELECT $1, UNNEST($2::INTEGER[]), UNNEST($3::DATE[]), UNNEST($4::VARCHAR[])",
+            movie_id, &country_ids, &release_dates, &release_types
+        ).execute(tx).await.map_err(map_unique_violation)?;
+
+        Ok(())
+    }
+
+    // tag names have to be normalized already
+    async fn insert_tags(tx: &mut Transaction<'_, Postgres>, movie_id: i32, tag_names: &[String]) -> Result<()> {
+        sqlx::query!("INSERT INTO tag(tag_name) SELECT UNNEST($1::VARCHAR[]) ON CONFLICT (tag_name) DO NOTHING", tag_names)
+            .execute(&mut *tx).await?;
+
+        sqlx::query!("INSERT INTO movie_tag(movie_id, tag_id) SELECT $1, tag_id FROM tag WHERE tag_name = ANY($2)
+ON CONFLICT DO NOTHING", movie_id, tag_names)
+            .execute(&mut *tx).await.map_err(map_missing_reference)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MovieRepository for MovieDb {
+    async fn get_language_id(&self, language_name: String) -> Result<Option<i32>> {
+        let language_id = sqlx::query_scalar!("SELECT language_id FROM language WHERE language_name = $1", language_name)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(language_id)
+    }
+
+    async fn get_language_ids(&self, language_names: &[String]) -> Result<Vec<i32>> {
+        let language_ids = sqlx::query_scalar!("SELECT language_id FROM language WHERE language_name = ANY($1)", language_names)
+            .fetch_all(&self.pool).await?;
+
+        Ok(language_ids)
+    }
+
+    async fn get_country_id(&self, country_name: String) -> Result<Option<i32>> {
+        let country_id = sqlx::query_scalar!("SELECT country_id FROM country WHERE country_name = $1", country_name)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(country_id)
+    }
+
+    async fn get_genre_id(&self, genre_name: String) -> Result<Option<i32>> {
+        let genre_id = sqlx::query_scalar!("SELECT genre_id FROM genre WHERE genre_name = $1", genre_name)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(genre_id)
+    }
 
+    async fn get_classification_id(&self, classification_name: String) -> Result<Option<i32>> {
+        let classification_id = sqlx::query_scalar!("SELECT classification_id FROM classification WHERE classification_name = $1", classification_name)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(classification_id)
+    }
+
+    async fn insert_movie(&self, movie: &MovieDataDb) -> Result<i32> {
         let mut tx = self.pool.begin().await?;
 
         let movie_id = sqlx::query_scalar!(
             "
         INSERT INTO movie (
             distribution_title, original_title, original_language_id, 
-            has_spanish_subtitles, production_year, website_url, image_url, 
-            duration_hours, summary, classification_id
+            production_year, website_url, image_url, 
+            runtime_minutes, summary, tagline, classification_id,
+            budget, budget_currency, box_office, box_office_currency
         ) VALUES (
-            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14
         ) RETURNING movie_id
         ",
             movie.distribution_title,
             movie.original_title,
             movie.original_language_id,
-            movie.has_spanish_subtitles,
             movie.production_year,
             movie.website_url,
             movie.image_url,
-            movie.duration_hours,
+            movie.runtime_minutes,
             movie.summary,
-            movie.classification_id
+            movie.tagline,
+            movie.classification_id,
+            movie.budget,
+            movie.budget_currency,
+            movie.box_office,
+            movie.box_office_currency
         )
             .fetch_one(&mut tx)
         .await?;
@@ -66,219 +182,836 @@ impl MovieDb {
             movie_id, movie.genre_id
         ).execute(&mut tx).await?;
 
+        sqlx::query!(
+            "INSERT INTO movie_subtitle_language(movie_id, language_id) SELECT $1, UNNEST($2::INTEGER[])",
+            movie_id, &movie.subtitle_language_ids
+        ).execute(&mut tx).await?;
+
+        sqlx::query!(
+            "INSERT INTO movie_audio_language(movie_id, language_id) SELECT $1, UNNEST($2::INTEGER[])",
+            movie_id, &movie.audio_language_ids
+        ).execute(&mut tx).await?;
+
+        Self::insert_releases(&mut tx, movie_id, &movie.releases).await?;
+        Self::insert_tags(&mut tx, movie_id, &movie.tags).await?;
+
+        tx.commit().await?;
+
+        Ok(movie_id)
+    }
+
+    async fn get_movie(&self, movie_id: i32) -> Result<Movie> {
+        let movie = sqlx::query_as!(Movie, "SELECT 
+m.movie_id, m.distribution_title, m.original_title, l.language_name AS original_language,
+ARRAY(SELECT sl.language_name::TEXT FROM movie_subtitle_language ms
+    INNER JOIN language sl ON sl.language_id = ms.language_id WHERE ms.movie_id = m.movie_id) AS \"subtitle_languages!\",
+ARRAY(SELECT al.language_name::TEXT FROM movie_audio_language ma
+    INNER JOIN language al ON al.language_id = ma.language_id WHERE ma.movie_id = m.movie_id) AS \"audio_languages!\",
+m.production_year, m.website_url, m.image_url, m.runtime_minutes,
+m.summary, m.tagline, m.budget, m.budget_currency, m.box_office, m.box_office_currency, m.popularity_score,
+c.classification_name AS classification, co.country_name AS origin_country, g.genre_name AS genre,
+ARRAY(SELECT t.tag_name::TEXT FROM movie_tag mt INNER JOIN tag t ON t.tag_id = mt.tag_id
+    WHERE mt.movie_id = m.movie_id ORDER BY t.tag_name) AS \"tags!\",
+COALESCE((SELECT JSON_AGG(JSON_BUILD_OBJECT(
+        'country', rc.country_name, 'release_date', r.release_date, 'release_type', r.release_type
+    ) ORDER BY r.release_date)
+    FROM movie_release r INNER JOIN country rc ON rc.country_id = r.country_id
+    WHERE r.movie_id = m.movie_id), '[]') AS \"releases!: Json<Vec<MovieRelease>>\"
+FROM movie m
+INNER JOIN language l ON l.language_id = m.original_language_id
+INNER JOIN classification c ON c.classification_id = m.classification_id
+INNER JOIN movie_genre mg ON mg.movie_id = m.movie_id
+INNER JOIN genre g ON g.genre_id = mg.genre_id
+INNER JOIN movie_country mc ON mc.movie_id = m.movie_id
+INNER JOIN country co ON co.country_id = mc.country_id
+WHERE m.movie_id = $1", movie_id)
+            .fetch_optional(&self.pool).await?;
+
+        movie.ok_or(MovieServiceError::MovieNotFound)
+    }
+
+    async fn update_movie(&self, movie_id: i32, movie: &MovieDataDb) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!("UPDATE movie SET distribution_title = $1, original_title = $2, 
+        original_language_id = $3, production_year = $4, website_url = $5,
+        image_url = $6, runtime_minutes = $7, summary = $8, classification_id = $9, tagline = $11,
+        budget = $12, budget_currency = $13, box_office = $14, box_office_currency = $15 WHERE movie_id = $10", 
+        movie.distribution_title, movie.original_title, movie.original_language_id,
+        movie.production_year, movie.website_url, movie.image_url, movie.runtime_minutes, movie.summary,
+        movie.classification_id, movie_id, movie.tagline,
+        movie.budget, movie.budget_currency, movie.box_office, movie.box_office_currency).execute(&mut tx).await?;
+
+        // dropping the transaction rolls it back
+        if result.rows_affected() == 0 {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        sqlx::query!("DELETE FROM movie_release WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        Self::insert_releases(&mut tx, movie_id, &movie.releases).await?;
+
+        sqlx::query!("DELETE FROM movie_tag WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        Self::insert_tags(&mut tx, movie_id, &movie.tags).await?;
+
+        sqlx::query!("DELETE FROM movie_subtitle_language WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("INSERT INTO movie_subtitle_language(movie_id, language_id) SELECT $1, UNNEST($2::INTEGER[])",
+            movie_id, &movie.subtitle_language_ids).execute(&mut tx).await?;
+
+        sqlx::query!("DELETE FROM movie_audio_language WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("INSERT INTO movie_audio_language(movie_id, language_id) SELECT $1, UNNEST($2::INTEGER[])",
+            movie_id, &movie.audio_language_ids).execute(&mut tx).await?;
+
+        sqlx::query!("UPDATE movie_genre SET genre_id = $2 WHERE movie_id = $1", movie_id, movie.genre_id)
+            .execute(&mut tx).await?;
+
+        sqlx::query!("UPDATE movie_country SET country_id = $2 WHERE movie_id = $1", movie_id, movie.origin_country_id)
+            .execute(&mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_movie(&self, movie_id: i32) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!("DELETE FROM movie_translation WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_release WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_tag WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_rating WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_engagement WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM favorite_movie WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM watched_movie WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM franchise_movie WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM collection_movie WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_subtitle_language WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_audio_language WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_country WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM movie_genre WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        let result = sqlx::query!("DELETE FROM movie WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+
+        if result.rows_affected() == 0 {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
         tx.commit().await?;
 
         Ok(())
     }
 
-    pub async fn get_basic_movie_page(&self, page: i64, quantity: i64) -> Result<Vec<BasicMovie>> {
+    async fn get_movie_minimum_age(&self, movie_id: i32) -> Result<i32> {
+        let minimum_age = sqlx::query_scalar!("SELECT c.minimum_age FROM movie m
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE m.movie_id = $1", movie_id)
+            .fetch_optional(&self.pool).await?;
+
+        minimum_age.ok_or(MovieServiceError::MovieNotFound)
+    }
+
+    // age of the client, lowered by the parental limit if the account has one
+    async fn get_client_age_limit(&self, client_name: &str) -> Result<Option<i32>> {
+        let age_limit = sqlx::query_scalar!("SELECT 
+LEAST(EXTRACT(YEAR FROM AGE(birth_date))::INTEGER, parental_age_limit) 
+FROM client WHERE client_name = $1", client_name)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(age_limit.flatten())
+    }
+
+    // languages
+    async fn get_languages(&self) -> Result<Vec<Language>> {
+        let languages = sqlx::query_as!(Language, "SELECT * FROM language")
+            .fetch_all(&self.pool).await?;
+
+        Ok(languages)
+    }
+
+    async fn get_language(&self, language_id: i32) -> Result<Option<Language>> {
+        let language = sqlx::query_as!(Language, "SELECT * FROM language WHERE language_id = $1", language_id)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(language)
+    }
+
+    // translations
+    async fn get_movie_translations(&self, movie_id: i32) -> Result<Vec<MovieTranslation>> {
+        let translations = sqlx::query_as!(MovieTranslation, "SELECT 
+t.movie_id, l.language_name AS language, t.title, t.summary, t.tagline FROM movie_translation t
+INNER JOIN language l ON l.language_id = t.language_id
+WHERE t.movie_id = $1", movie_id)
+            .fetch_all(&self.pool).await?;
+
+        Ok(translations)
+    }
+
+    // for each movie the translation in the most preferred language it has, matched by code or name
+    async fn get_preferred_translations(&self, languages: &[String], movie_ids: &[i32]) -> Result<Vec<MovieTranslation>> {
+        let translations = sqlx::query_as!(MovieTranslation, "SELECT DISTINCT ON (t.movie_id)
+t.movie_id, l.language_name AS language, t.title, t.summary, t.tagline FROM movie_translation t
+INNER JOIN language l ON l.language_id = t.language_id
+WHERE t.movie_id = ANY($2) AND (LOWER(l.language_code) = ANY($1) OR LOWER(l.language_name) = ANY($1))
+ORDER BY t.movie_id, LEAST(array_position($1, LOWER(l.language_code)), array_position($1, LOWER(l.language_name)))",
+            languages, movie_ids)
+            .fetch_all(&self.pool).await?;
+
+        Ok(translations)
+    }
+
+    async fn upsert_movie_translation(&self, movie_id: i32, language_id: i32, translation: &MovieTranslationConstructor) -> Result<()> {
+        sqlx::query!("INSERT INTO movie_translation(movie_id, language_id, title, summary, tagline)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (movie_id, language_id) DO UPDATE SET title = $3, summary = $4, tagline = $5",
+            movie_id, language_id, translation.title, translation.summary, translation.tagline)
+            .execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn delete_movie_translation(&self, movie_id: i32, language_id: i32) -> Result<()> {
+        sqlx::query!("DELETE FROM movie_translation WHERE movie_id = $1 AND language_id = $2", movie_id, language_id)
+            .execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    // countries
+    async fn get_countries(&self) -> Result<Vec<Country>> {
+        let countries = sqlx::query_as!(Country, "SELECT * FROM country")
+            .fetch_all(&self.pool).await?;
+
+        Ok(countries)
+    }
+
+    async fn get_country(&self, country_id: i32) -> Result<Option<Country>> {
+        let country = sqlx::query_as!(Country, "SELECT * FROM country WHERE country_id = $1", country_id)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(country)
+    }
+
+    async fn get_genres(&self) -> Result<Vec<Genre>> {
+        let genres = sqlx::query_as!(Genre, "SELECT * FROM genre")
+            .fetch_all(&self.pool).await?;
+
+        Ok(genres)
+    }
+
+    async fn get_genre(&self, genre_id: i32) -> Result<Option<Genre>> {
+        let genre = sqlx::query_as!(Genre, "SELECT * FROM genre WHERE genre_id = $1", genre_id)
+            .fetch_optional(&self.pool).await?;
+
+        Ok(genre)
+    }
+
+    // classifications
+    async fn get_classifications(&self) -> Result<Vec<Classification>> {
+        let classifications = sqlx::query_as!(Classification, "SELECT * FROM classification").fetch_all(&self.pool).await?;
+        Ok(classifications)
+    }
+
+    async fn get_classification(&self, classification_id: i32) -> Result<Option<Classification>> {
+        let classification = sqlx::query_as!(Classification, "SELECT * FROM classification WHERE classification_id = $1", classification_id)
+        .fetch_optional(&self.pool).await?;
+
+        Ok(classification)
+    }
+
+    // pages and search
+    async fn get_basic_movie_page(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Result<Vec<BasicMovie>> {
 
         let offset = page * quantity;
 
         let movies = sqlx::query_as!(BasicMovie, "SELECT 
-movie_id, distribution_title, image_url FROM movie OFFSET $1 LIMIT $2", offset, quantity)
+m.movie_id, m.distribution_title, m.image_url FROM movie m
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE ($3::INTEGER IS NULL OR c.minimum_age <= $3)
+AND (SELECT COUNT(*) FROM movie_audio_language ma INNER JOIN language al ON al.language_id = ma.language_id
+    WHERE ma.movie_id = m.movie_id AND al.language_name = ANY($4)) = CARDINALITY($4)
+AND (SELECT COUNT(*) FROM movie_subtitle_language ms INNER JOIN language sl ON sl.language_id = ms.language_id
+    WHERE ms.movie_id = m.movie_id AND sl.language_name = ANY($5)) = CARDINALITY($5)
+AND ($6::INTEGER IS NULL OR m.runtime_minutes >= $6)
+AND ($7::INTEGER IS NULL OR m.runtime_minutes <= $7)
+AND (($8::DATE IS NULL AND $9::DATE IS NULL AND $10::TEXT IS NULL) OR EXISTS (SELECT 1 FROM movie_release r
+    WHERE r.movie_id = m.movie_id AND ($8::DATE IS NULL OR r.release_date >= $8)
+    AND ($9::DATE IS NULL OR r.release_date <= $9) AND ($10::TEXT IS NULL OR r.release_type = $10)))
+AND (SELECT COUNT(*) FROM movie_tag mt INNER JOIN tag t ON t.tag_id = mt.tag_id
+    WHERE mt.movie_id = m.movie_id AND t.tag_name = ANY($13)) = CARDINALITY($13)
+AND ($14::TEXT IS NULL OR UPPER(CASE $11::TEXT WHEN 'budget' THEN m.budget_currency ELSE m.box_office_currency END) = $14)
+ORDER BY (CASE $11::TEXT
+    WHEN 'runtime' THEN m.runtime_minutes
+    WHEN 'production_year' THEN m.production_year
+    WHEN 'budget' THEN m.budget
+    WHEN 'box_office' THEN m.box_office
+    WHEN 'popularity' THEN m.popularity_score
+    WHEN 'release_date' THEN (SELECT MIN(r.release_date) - DATE '1970-01-01' FROM movie_release r WHERE r.movie_id = m.movie_id)
+END) * (CASE WHEN $12 THEN -1 ELSE 1 END) NULLS LAST, m.movie_id
+OFFSET $1 LIMIT $2", offset, quantity, age_limit, &filter.audio_languages(), &filter.subtitle_languages(),
+    filter.min_runtime, filter.max_runtime, filter.released_from, filter.released_until, filter.release_type(),
+    filter.sort_key(), filter.descending(), &filter.tag_names(), filter.sort_currency())
             .fetch_all(&self.pool).await?;        
 
         Ok(movies)
     }
 
-    pub async fn get_movie_page(&self, page: i64, quantity: i64) -> Result<Vec<Movie>> {
+    async fn get_movie_page(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Result<Vec<Movie>> {
 
         let offset = page * quantity;
 
         let movies = sqlx::query_as!(Movie, "SELECT 
 m.movie_id, m.distribution_title, m.original_title, l.language_name AS original_language,
-m.has_spanish_subtitles, m.production_year, m.website_url, m.image_url, m.duration_hours,
-m.summary, c.classification_name AS classification, co.country_name AS origin_country,
-g.genre_name AS genre FROM movie m
+ARRAY(SELECT sl.language_name::TEXT FROM movie_subtitle_language ms
+    INNER JOIN language sl ON sl.language_id = ms.language_id WHERE ms.movie_id = m.movie_id) AS \"subtitle_languages!\",
+ARRAY(SELECT al.language_name::TEXT FROM movie_audio_language ma
+    INNER JOIN language al ON al.language_id = ma.language_id WHERE ma.movie_id = m.movie_id) AS \"audio_languages!\",
+m.production_year, m.website_url, m.image_url, m.runtime_minutes,
+m.summary, m.tagline, m.budget, m.budget_currency, m.box_office, m.box_office_currency, m.popularity_score,
+c.classification_name AS classification, co.country_name AS origin_country, g.genre_name AS genre,
+ARRAY(SELECT t.tag_name::TEXT FROM movie_tag mt INNER JOIN tag t ON t.tag_id = mt.tag_id
+    WHERE mt.movie_id = m.movie_id ORDER BY t.tag_name) AS \"tags!\",
+COALESCE((SELECT JSON_AGG(JSON_BUILD_OBJECT(
+        'country', rc.country_name, 'release_date', r.release_date, 'release_type', r.release_type
+    ) ORDER BY r.release_date)
+    FROM movie_release r INNER JOIN country rc ON rc.country_id = r.country_id
+    WHERE r.movie_id = m.movie_id), '[]') AS \"releases!: Json<Vec<MovieRelease>>\"
+FROM movie m
 INNER JOIN language l ON l.language_id = m.original_language_id
 INNER JOIN classification c ON c.classification_id = m.classification_id
 INNER JOIN movie_genre mg ON mg.movie_id = m.movie_id
 INNER JOIN genre g ON g.genre_id = mg.genre_id
 INNER JOIN movie_country mc ON mc.movie_id = m.movie_id
 INNER JOIN country co ON co.country_id = mc.country_id
-OFFSET $1 LIMIT $2", offset, quantity)
+WHERE ($3::INTEGER IS NULL OR c.minimum_age <= $3)
+AND (SELECT COUNT(*) FROM movie_audio_language ma INNER JOIN language al ON al.language_id = ma.language_id
+    WHERE ma.movie_id = m.movie_id AND al.language_name = ANY($4)) = CARDINALITY($4)
+AND (SELECT COUNT(*) FROM movie_subtitle_language ms INNER JOIN language sl ON sl.language_id = ms.language_id
+    WHERE ms.movie_id = m.movie_id AND sl.language_name = ANY($5)) = CARDINALITY($5)
+AND ($6::INTEGER IS NULL OR m.runtime_minutes >= $6)
+AND ($7::INTEGER IS NULL OR m.runtime_minutes <= $7)
+AND (($8::DATE IS NULL AND $9::DATE IS NULL AND $10::TEXT IS NULL) OR EXISTS (SELECT 1 FROM movie_release r
+    WHERE r.movie_id = m.movie_id AND ($8::DATE IS NULL OR r.release_date >= $8)
+    AND ($9::DATE IS NULL OR r.release_date <= $9) AND ($10::TEXT IS NULL OR r.release_type = $10)))
+AND (SELECT COUNT(*) FROM movie_tag mt INNER JOIN tag t ON t.tag_id = mt.tag_id
+    WHERE mt.movie_id = m.movie_id AND t.tag_name = ANY($13)) = CARDINALITY($13)
+AND ($14::TEXT IS NULL OR UPPER(CASE $11::TEXT WHEN 'budget' THEN m.budget_currency ELSE m.box_office_currency END) = $14)
+ORDER BY (CASE $11::TEXT
+    WHEN 'runtime' THEN m.runtime_minutes
+    WHEN 'production_year' THEN m.production_year
+    WHEN 'budget' THEN m.budget
+    WHEN 'box_office' THEN m.box_office
+    WHEN 'popularity' THEN m.popularity_score
+    WHEN 'release_date' THEN (SELECT MIN(r.release_date) - DATE '1970-01-01' FROM movie_release r WHERE r.movie_id = m.movie_id)
+END) * (CASE WHEN $12 THEN -1 ELSE 1 END) NULLS LAST, m.movie_id
+OFFSET $1 LIMIT $2", offset, quantity, age_limit, &filter.audio_languages(), &filter.subtitle_languages(),
+    filter.min_runtime, filter.max_runtime, filter.released_from, filter.released_until, filter.release_type(),
+    filter.sort_key(), filter.descending(), &filter.tag_names(), filter.sort_currency())
             .fetch_all(&self.pool).await?;        
 
         Ok(movies)
     }
 
-    pub async fn get_movie(&self, movie_id: i32) -> Result<Movie> {
-        let country = sqlx::query_as!(Movie, "SELECT 
+    // keeps the order of the given ids
+    async fn get_basic_movies_by_ids(&self, movie_ids: &[i32], age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let movies = sqlx::query_as!(BasicMovie, "SELECT 
+m.movie_id, m.distribution_title, m.image_url FROM movie m
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE m.movie_id = ANY($1) AND ($2::INTEGER IS NULL OR c.minimum_age <= $2)
+ORDER BY ARRAY_POSITION($1, m.movie_id)", movie_ids, age_limit)
+            .fetch_all(&self.pool).await?;
+
+        Ok(movies)
+    }
+
+    async fn get_movie_features(&self) -> Result<Vec<MovieFeatures>> {
+        let features = sqlx::query_as!(MovieFeatures, "SELECT 
+m.movie_id, m.original_language_id, m.classification_id, m.production_year,
+ARRAY(SELECT genre_id FROM movie_genre WHERE movie_id = m.movie_id) AS \"genre_ids!\",
+ARRAY(SELECT country_id FROM movie_country WHERE movie_id = m.movie_id) AS \"country_ids!\",
+ARRAY(SELECT tag_id FROM movie_tag WHERE movie_id = m.movie_id) AS \"tag_ids!\"
+FROM movie m")
+            .fetch_all(&self.pool).await?;
+
+        Ok(features)
+    }
+
+    // catalog writes
+    async fn create_language(&self, language_name: String, language_code: Option<String>) -> Result<()> {
+        sqlx::query!("INSERT INTO language(language_name, language_code) VALUES($1, $2)", language_name, language_code)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_country(&self, country_name: String) -> Result<()> {
+        sqlx::query!("INSERT INTO country(country_name) VALUES($1)", country_name).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_genre(&self, genre_name: String) -> Result<()> {
+        sqlx::query!("INSERT INTO genre(genre_name) VALUES($1)", genre_name).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_classification(&self, classification_name: String, minimum_age: i32) -> Result<()> {
+        sqlx::query!("INSERT INTO classification(classification_name, minimum_age) VALUES($1, $2)", classification_name, minimum_age)
+        .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_movie_search(&self, movie_name: String, age_limit: Option<i32>, tag_names: &[String]) -> Result<Vec<Movie>> {
+        let movie_name = format!("%{}%", movie_name);
+        let movies = sqlx::query_as!(Movie, "SELECT 
 m.movie_id, m.distribution_title, m.original_title, l.language_name AS original_language,
-m.has_spanish_subtitles, m.production_year, m.website_url, m.image_url, m.duration_hours,
-m.summary, c.classification_name AS classification, co.country_name AS origin_country,
-g.genre_name AS genre FROM movie m
+ARRAY(SELECT sl.language_name::TEXT FROM movie_subtitle_language ms
+    INNER JOIN language sl ON sl.language_id = ms.language_id WHERE ms.movie_id = m.movie_id) AS \"subtitle_languages!\",
+ARRAY(SELECT al.language_name::TEXT FROM movie_audio_language ma
+    INNER JOIN language al ON al.language_id = ma.language_id WHERE ma.movie_id = m.movie_id) AS \"audio_languages!\",
+m.production_year, m.website_url, m.image_url, m.runtime_minutes,
+m.summary, m.tagline, m.budget, m.budget_currency, m.box_office, m.box_office_currency, m.popularity_score,
+c.classification_name AS classification, co.country_name AS origin_country, g.genre_name AS genre,
+ARRAY(SELECT t.tag_name::TEXT FROM movie_tag mt INNER JOIN tag t ON t.tag_id = mt.tag_id
+    WHERE mt.movie_id = m.movie_id ORDER BY t.tag_name) AS \"tags!\",
+COALESCE((SELECT JSON_AGG(JSON_BUILD_OBJECT(
+        'country', rc.country_name, 'release_date', r.release_date, 'release_type', r.release_type
+    ) ORDER BY r.release_date)
+    FROM movie_release r INNER JOIN country rc ON rc.country_id = r.country_id
+    WHERE r.movie_id = m.movie_id), '[]') AS \"releases!: Json<Vec<MovieRelease>>\"
+FROM movie m
 INNER JOIN language l ON l.language_id = m.original_language_id
 INNER JOIN classification c ON c.classification_id = m.classification_id
 INNER JOIN movie_genre mg ON mg.movie_id = m.movie_id
 INNER JOIN genre g ON g.genre_id = mg.genre_id
 INNER JOIN movie_country mc ON mc.movie_id = m.movie_id
 INNER JOIN country co ON co.country_id = mc.country_id
-WHERE m.movie_id = $1", movie_id)
-            .fetch_one(&self.pool).await?;
+WHERE (distribution_title ILIKE $1 OR original_title ILIKE $1)
+AND ($2::INTEGER IS NULL OR c.minimum_age <= $2)
+AND (SELECT COUNT(*) FROM movie_tag mt INNER JOIN tag t ON t.tag_id = mt.tag_id
+    WHERE mt.movie_id = m.movie_id AND t.tag_name = ANY($3)) = CARDINALITY($3)", movie_name, age_limit, tag_names).fetch_all(&self.pool).await?;
+        Ok(movies)
+    }
 
-        Ok(country)
+    // ratings, favorites and watch history
+    async fn set_rating(&self, client_name: &str, movie_id: i32, rating: i32) -> Result<()> {
+        sqlx::query!("INSERT INTO movie_rating(client_id, movie_id, rating)
+SELECT client_id, $2, $3 FROM client WHERE client_name = $1
+ON CONFLICT (client_id, movie_id) DO UPDATE SET rating = $3, rated_at = NOW()", client_name, movie_id, rating)
+            .execute(&self.pool).await.map_err(map_missing_reference)?;
+
+        Ok(())
     }
 
-    // languages
-    pub async fn get_languages(&self) -> Result<Vec<Language>> {
-        let languages = sqlx::query_as!(Language, "SELECT * FROM language")
+    async fn delete_rating(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        sqlx::query!("DELETE FROM movie_rating WHERE movie_id = $2
+AND client_id = (SELECT client_id FROM client WHERE client_name = $1)", client_name, movie_id)
+            .execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn add_favorite(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        sqlx::query!("INSERT INTO favorite_movie(client_id, movie_id)
+SELECT client_id, $2 FROM client WHERE client_name = $1 ON CONFLICT DO NOTHING", client_name, movie_id)
+            .execute(&self.pool).await.map_err(map_missing_reference)?;
+
+        Ok(())
+    }
+
+    async fn delete_favorite(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        sqlx::query!("DELETE FROM favorite_movie WHERE movie_id = $2
+AND client_id = (SELECT client_id FROM client WHERE client_name = $1)", client_name, movie_id)
+            .execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn add_watched(&self, client_name: &str, movie_id: i32) -> Result<()> {
+        sqlx::query!("INSERT INTO watched_movie(client_id, movie_id)
+SELECT client_id, $2 FROM client WHERE client_name = $1
+ON CONFLICT (client_id, movie_id) DO UPDATE SET watched_at = NOW()", client_name, movie_id)
+            .execute(&self.pool).await.map_err(map_missing_reference)?;
+
+        Ok(())
+    }
+
+    async fn get_favorite_movies(&self, client_name: &str, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let movies = sqlx::query_as!(BasicMovie, "SELECT 
+m.movie_id, m.distribution_title, m.image_url FROM favorite_movie f
+INNER JOIN client cl ON cl.client_id = f.client_id
+INNER JOIN movie m ON m.movie_id = f.movie_id
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE cl.client_name = $1 AND ($2::INTEGER IS NULL OR c.minimum_age <= $2)
+ORDER BY f.added_at DESC", client_name, age_limit)
             .fetch_all(&self.pool).await?;
 
-        Ok(languages)
+        Ok(movies)
     }
 
-    pub async fn get_language(&self, language_id: i32) -> Result<Language> {
-        let language = sqlx::query_as!(Language, "SELECT * FROM language WHERE language_id = $1", language_id)
-            .fetch_one(&self.pool).await?;
+    async fn get_watched_movies(&self, client_name: &str, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let movies = sqlx::query_as!(BasicMovie, "SELECT 
+m.movie_id, m.distribution_title, m.image_url FROM watched_movie w
+INNER JOIN client cl ON cl.client_id = w.client_id
+INNER JOIN movie m ON m.movie_id = w.movie_id
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE cl.client_name = $1 AND ($2::INTEGER IS NULL OR c.minimum_age <= $2)
+ORDER BY w.watched_at DESC", client_name, age_limit)
+            .fetch_all(&self.pool).await?;
 
-        Ok(language)
+        Ok(movies)
     }
 
-    pub async fn get_language_id(&self, language_name: String) -> Result<Option<i32>> {
-        let language_id = sqlx::query_scalar!("SELECT language_id FROM language WHERE language_name = $1", language_name)
-            .fetch_optional(&self.pool).await?;
+    async fn get_watched_movie_ids(&self, client_name: &str) -> Result<Vec<i32>> {
+        let movie_ids = sqlx::query_scalar!("SELECT w.movie_id FROM watched_movie w
+INNER JOIN client cl ON cl.client_id = w.client_id
+WHERE cl.client_name = $1", client_name)
+            .fetch_all(&self.pool).await?;
 
-        Ok(language_id)
+        Ok(movie_ids)
     }
 
-    pub async fn create_language_db(&self, language_name: String) -> Result<()> {
-        sqlx::query!("INSERT INTO language(language_name) VALUES($1)", language_name).execute(&self.pool).await?;
+    // every interaction, or only the ones of a client
+    async fn get_interactions(&self, client_name: Option<&str>) -> Result<Vec<Interaction>> {
+        let interactions = sqlx::query_as!(Interaction, "SELECT 
+i.client_id AS \"client_id!\", i.movie_id AS \"movie_id!\",
+COALESCE(r.rating, CASE WHEN f.movie_id IS NOT NULL THEN 5 ELSE 3 END) AS \"strength!\"
+FROM (
+    SELECT client_id, movie_id FROM movie_rating
+    UNION SELECT client_id, movie_id FROM favorite_movie
+    UNION SELECT client_id, movie_id FROM watched_movie
+) i
+LEFT JOIN movie_rating r ON r.client_id = i.client_id AND r.movie_id = i.movie_id
+LEFT JOIN favorite_movie f ON f.client_id = i.client_id AND f.movie_id = i.movie_id
+WHERE $1::VARCHAR IS NULL OR i.client_id = (SELECT client_id FROM client WHERE client_name = $1)", client_name)
+            .fetch_all(&self.pool).await?;
+
+        Ok(interactions)
+    }
+
+    // engagement
+    async fn add_engagement_counts(&self, counts: &[EngagementCount]) -> Result<()> {
+        let movie_ids: Vec<i32> = counts.iter().map(|count| count.movie_id).collect();
+        let bucket_starts: Vec<DateTime<Utc>> = counts.iter().map(|count| count.bucket_start).collect();
+        let event_types: Vec<String> = counts.iter().map(|count| count.event.as_str().to_string()).collect();
+        let event_counts: Vec<i64> = counts.iter().map(|count| count.event_count).collect();
+
+        // counters of movies deleted since they were recorded are dropped by the join
+        sqlx::query!("INSERT INTO movie_engagement(movie_id, bucket_start, event_type, event_count)
+SELECT u.movie_id, u.bucket_start, u.event_type, u.event_count
+FROM UNNEST($1::INTEGER[], $2::TIMESTAMPTZ[], $3::VARCHAR[], $4::BIGINT[]) AS u(movie_id, bucket_start, event_type, event_count)
+INNER JOIN movie m ON m.movie_id = u.movie_id
+ON CONFLICT (movie_id, bucket_start, event_type)
+DO UPDATE SET event_count = movie_engagement.event_count + EXCLUDED.event_count",
+            &movie_ids, &bucket_starts, &event_types, &event_counts)
+            .execute(&self.pool).await?;
+
         Ok(())
     }
 
-    // countries
-    pub async fn get_countries(&self) -> Result<Vec<Country>> {
-        let countries = sqlx::query_as!(Country, "SELECT * FROM country")
+    // weighted engagement of the last 30 days, halving every three days
+    async fn refresh_popularity_scores(&self) -> Result<()> {
+        sqlx::query!("UPDATE movie m SET popularity_score = s.score FROM (
+    SELECT mv.movie_id, COALESCE(SUM(e.event_count * engagement_weight(e.event_type)
+        * POWER(0.5, EXTRACT(EPOCH FROM NOW() - e.bucket_start) / 259200)), 0) AS score
+    FROM movie mv
+    LEFT JOIN movie_engagement e ON e.movie_id = mv.movie_id AND e.bucket_start > NOW() - INTERVAL '30 days'
+    GROUP BY mv.movie_id
+) s WHERE s.movie_id = m.movie_id")
+            .execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn get_trending_movies(&self, window_hours: i32, quantity: i64, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let movies = sqlx::query_as!(BasicMovie, "SELECT 
+m.movie_id, m.distribution_title, m.image_url FROM movie m
+INNER JOIN classification c ON c.classification_id = m.classification_id
+INNER JOIN (
+    SELECT movie_id, SUM(event_count * engagement_weight(event_type)) AS score FROM movie_engagement
+    WHERE bucket_start >= NOW() - MAKE_INTERVAL(hours => $1)
+    GROUP BY movie_id
+) t ON t.movie_id = m.movie_id
+WHERE $3::INTEGER IS NULL OR c.minimum_age <= $3
+ORDER BY t.score DESC, m.movie_id
+LIMIT $2", window_hours, quantity, age_limit)
             .fetch_all(&self.pool).await?;
 
-        Ok(countries)
+        Ok(movies)
     }
 
-    pub async fn get_country(&self, country_id: i32) -> Result<Country> {
-        let country = sqlx::query_as!(Country, "SELECT * FROM country WHERE country_id = $1", country_id)
-            .fetch_one(&self.pool).await?;
+    // tags
+    async fn get_tags_by_prefix(&self, prefix: &str, quantity: i64) -> Result<Vec<Tag>> {
+        let prefix = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+
+        let tags = sqlx::query_as!(Tag, "SELECT 
+t.tag_id, t.tag_name, COUNT(mt.movie_id) AS \"movie_count!\" FROM tag t
+LEFT JOIN movie_tag mt ON mt.tag_id = t.tag_id
+WHERE t.tag_name LIKE $1
+GROUP BY t.tag_id
+ORDER BY COUNT(mt.movie_id) DESC, t.tag_name
+LIMIT $2", prefix, quantity)
+            .fetch_all(&self.pool).await?;
 
-        Ok(country)
+        Ok(tags)
     }
 
-    pub async fn create_country_db(&self, country_name: String) -> Result<()> {
-        sqlx::query!("INSERT INTO country(country_name) VALUES($1)", country_name).execute(&self.pool).await?;
+    async fn add_movie_tag(&self, movie_id: i32, tag_name: String) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        Self::insert_tags(&mut tx, movie_id, &[tag_name]).await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
 
-    pub async fn get_country_id(&self, country_name: String) -> Result<Option<i32>> {
-        let country_id = sqlx::query_scalar!("SELECT country_id FROM country WHERE country_name = $1", country_name)
+    async fn delete_movie_tag(&self, movie_id: i32, tag_name: &str) -> Result<()> {
+        sqlx::query!("DELETE FROM movie_tag WHERE movie_id = $1 AND tag_id = (SELECT tag_id FROM tag WHERE tag_name = $2)",
+            movie_id, tag_name)
+            .execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn get_tag_id(&self, tag_name: &str) -> Result<Option<i32>> {
+        let tag_id = sqlx::query_scalar!("SELECT tag_id FROM tag WHERE tag_name = $1", tag_name)
             .fetch_optional(&self.pool).await?;
 
-        Ok(country_id)
+        Ok(tag_id)
     }
 
-    pub async fn get_genres(&self) -> Result<Vec<Genre>> {
-        let genres = sqlx::query_as!(Genre, "SELECT * FROM genre")
+    // moves every movie of the source tag to the target tag and drops the source
+    async fn merge_tags(&self, source_tag_id: i32, target_tag_id: i32) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!("INSERT INTO movie_tag(movie_id, tag_id) SELECT movie_id, $2 FROM movie_tag WHERE tag_id = $1
+ON CONFLICT DO NOTHING", source_tag_id, target_tag_id)
+            .execute(&mut tx).await?;
+
+        sqlx::query!("DELETE FROM movie_tag WHERE tag_id = $1", source_tag_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM tag WHERE tag_id = $1", source_tag_id).execute(&mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // franchises
+    async fn get_franchises(&self) -> Result<Vec<Franchise>> {
+        let franchises = sqlx::query_as!(Franchise, "SELECT * FROM franchise ORDER BY franchise_name")
             .fetch_all(&self.pool).await?;
 
-        Ok(genres)
+        Ok(franchises)
     }
 
-    pub async fn get_genre(&self, genre_id: i32) -> Result<Genre> {
-        let genres = sqlx::query_as!(Genre, "SELECT * FROM genre WHERE genre_id = $1", genre_id)
+    async fn get_franchise(&self, franchise_id: i32) -> Result<Franchise> {
+        let franchise = sqlx::query_as!(Franchise, "SELECT * FROM franchise WHERE franchise_id = $1", franchise_id)
+            .fetch_optional(&self.pool).await?;
+
+        franchise.ok_or(MovieServiceError::FranchiseNotFound)
+    }
+
+    async fn create_franchise(&self, franchise: &FranchiseConstructor) -> Result<i32> {
+        let franchise_id = sqlx::query_scalar!("INSERT INTO franchise(franchise_name, description) VALUES ($1, $2)
+RETURNING franchise_id", franchise.franchise_name, franchise.description)
             .fetch_one(&self.pool).await?;
 
-        Ok(genres)
+        Ok(franchise_id)
     }
-    
-    pub async fn create_genre_db(&self, genre_name: String) -> Result<()> {
-        sqlx::query!("INSERT INTO genre(genre_name) VALUES($1)", genre_name).execute(&self.pool).await?;
+
+    async fn get_franchise_movies(&self, franchise_id: i32, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let movies = sqlx::query_as!(BasicMovie, "SELECT 
+m.movie_id, m.distribution_title, m.image_url FROM franchise_movie fm
+INNER JOIN movie m ON m.movie_id = fm.movie_id
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE fm.franchise_id = $1 AND ($2::INTEGER IS NULL OR c.minimum_age <= $2)
+ORDER BY fm.position, m.movie_id", franchise_id, age_limit)
+            .fetch_all(&self.pool).await?;
+
+        Ok(movies)
+    }
+
+    async fn set_franchise_movie(&self, franchise_id: i32, entry: &OrderedEntryConstructor) -> Result<()> {
+        sqlx::query!("INSERT INTO franchise_movie(franchise_id, movie_id, position)
+VALUES ($1, $2, COALESCE($3, (SELECT COALESCE(MAX(position), 0) + 1 FROM franchise_movie WHERE franchise_id = $1)))
+ON CONFLICT (franchise_id, movie_id) DO UPDATE SET position = EXCLUDED.position",
+            franchise_id, entry.movie_id, entry.position)
+            .execute(&self.pool).await.map_err(map_missing_reference)?;
+
         Ok(())
     }
 
-    // classifications
-    pub async fn get_classifications_db(&self) -> Result<Vec<Classification>> {
-        let classifications = sqlx::query_as!(Classification, "SELECT * FROM classification").fetch_all(&self.pool).await?;
-        Ok(classifications)
+    async fn delete_franchise_movie(&self, franchise_id: i32, movie_id: i32) -> Result<()> {
+        let result = sqlx::query!("DELETE FROM franchise_movie WHERE franchise_id = $1 AND movie_id = $2", franchise_id, movie_id)
+            .execute(&self.pool).await?;
+
+        if result.rows_affected() == 0 {
+            return Err(MovieServiceError::MovieNotFound);
+        }
+
+        Ok(())
     }
 
-    pub async fn get_classification_db(&self, classification_id: i32) -> Result<Classification> {
-        let classification = sqlx::query_as!(Classification, "SELECT * FROM classification WHERE classification_id = $1", classification_id)
-        .fetch_one(&self.pool).await?;
+    // collections
+    async fn get_collections(&self) -> Result<Vec<Collection>> {
+        let collections = sqlx::query_as!(Collection, "SELECT * FROM collection ORDER BY created_at DESC")
+            .fetch_all(&self.pool).await?;
 
-        Ok(classification)
+        Ok(collections)
     }
 
-    pub async fn get_classification_id(&self, classification_name: String) -> Result<Option<i32>> {
-        let classification_id = sqlx::query_scalar!("SELECT classification_id FROM classification WHERE classification_name = $1", classification_name)
+    async fn get_collection(&self, collection_id: i32) -> Result<Collection> {
+        let collection = sqlx::query_as!(Collection, "SELECT * FROM collection WHERE collection_id = $1", collection_id)
             .fetch_optional(&self.pool).await?;
 
-        Ok(classification_id)
+        collection.ok_or(MovieServiceError::CollectionNotFound)
     }
 
-    pub async fn create_classification_db(&self, classification_name: String) -> Result<()> {
-        sqlx::query!("INSERT INTO classification(classification_name) VALUES($1)", classification_name)
-        .execute(&self.pool).await?;
-        Ok(())
+    async fn create_collection(&self, collection: &CollectionConstructor) -> Result<i32> {
+        let collection_id = sqlx::query_scalar!("INSERT INTO collection(title, description, cover_image_url) VALUES ($1, $2, $3)
+RETURNING collection_id", collection.title, collection.description, collection.cover_image_url)
+            .fetch_one(&self.pool).await?;
+
+        Ok(collection_id)
     }
 
-    pub async fn get_genre_id(&self, genre_name: String) -> Result<Option<i32>> {
-        let genre_id = sqlx::query_scalar!("SELECT genre_id FROM genre WHERE genre_name = $1", genre_name)
-            .fetch_optional(&self.pool).await?;
+    async fn update_collection(&self, collection_id: i32, collection: &CollectionConstructor) -> Result<()> {
+        let result = sqlx::query!("UPDATE collection SET title = $2, description = $3, cover_image_url = $4 WHERE collection_id = $1",
+            collection_id, collection.title, collection.description, collection.cover_image_url)
+            .execute(&self.pool).await?;
 
-        Ok(genre_id)
+        if result.rows_affected() == 0 {
+            return Err(MovieServiceError::CollectionNotFound);
+        }
+
+        Ok(())
     }
 
-    pub async fn delete_movie_db(&self, movie_id: i32) -> Result<()> {
+    async fn delete_collection(&self, collection_id: i32) -> Result<()> {
         let mut tx = self.pool.begin().await?;
 
-        sqlx::query!("DELETE FROM movie_country WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
-        sqlx::query!("DELETE FROM movie_genre WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
-        sqlx::query!("DELETE FROM movie WHERE movie_id = $1", movie_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM collection_movie WHERE collection_id = $1", collection_id).execute(&mut tx).await?;
+        let result = sqlx::query!("DELETE FROM collection WHERE collection_id = $1", collection_id).execute(&mut tx).await?;
+
+        // dropping the transaction rolls it back
+        if result.rows_affected() == 0 {
+            return Err(MovieServiceError::CollectionNotFound);
+        }
 
         tx.commit().await?;
 
         Ok(())
     }
 
-    pub async fn update_movie_db(&self, movie: Movie) -> Result<()> {
-        let classification_id = self.get_classification_id(movie.classification).await?;
-        let language_id = self.get_language_id(movie.original_language).await?;
-        let genre_id = self.get_genre_id(movie.genre).await?;
-        let country_id = self.get_country_id(movie.origin_country).await?;
+    async fn get_collection_movies(&self, collection_id: i32, age_limit: Option<i32>) -> Result<Vec<BasicMovie>> {
+        let movies = sqlx::query_as!(BasicMovie, "SELECT 
+m.movie_id, m.distribution_title, m.image_url FROM collection_movie cm
+INNER JOIN movie m ON m.movie_id = cm.movie_id
+INNER JOIN classification c ON c.classification_id = m.classification_id
+WHERE cm.collection_id = $1 AND ($2::INTEGER IS NULL OR c.minimum_age <= $2)
+ORDER BY cm.position, m.movie_id", collection_id, age_limit)
+            .fetch_all(&self.pool).await?;
 
-        let mut tx = self.pool.begin().await?;
+        Ok(movies)
+    }
 
-        sqlx::query!("UPDATE movie_genre SET genre_id = $2 WHERE movie_id = $1", movie.movie_id, genre_id)
-            .execute(&mut tx).await?;
+    async fn set_collection_movie(&self, collection_id: i32, entry: &OrderedEntryConstructor) -> Result<()> {
+        sqlx::query!("INSERT INTO collection_movie(collection_id, movie_id, position)
+VALUES ($1, $2, COALESCE($3, (SELECT COALESCE(MAX(position), 0) + 1 FROM collection_movie WHERE collection_id = $1)))
+ON CONFLICT (collection_id, movie_id) DO UPDATE SET position = EXCLUDED.position",
+            collection_id, entry.movie_id, entry.position)
+            .execute(&self.pool).await.map_err(map_missing_reference)?;
 
-        sqlx::query!("UPDATE movie_country SET country_id = $2 WHERE movie_id = $1", movie.movie_id, country_id)
-            .execute(&mut tx).await?;
+        Ok(())
+    }
 
-        sqlx::query!("UPDATE movie SET distribution_title = $1, original_title = $2, 
-        original_language_id = $3, has_spanish_subtitles = $4, production_year = $5, website_url = $6,
-        image_url = $7, duration_hours = $8, summary = $9, classification_id = $10 WHERE movie_id = $11", 
-        movie.distribution_title, movie.original_title, language_id, movie.has_spanish_subtitles, 
-        movie.production_year, movie.website_url, movie.image_url, movie.duration_hours, movie.summary,
-        classification_id, movie.movie_id).execute(&mut tx).await?;
+    async fn delete_collection_movie(&self, collection_id: i32, movie_id: i32) -> Result<()> {
+        let result = sqlx::query!("DELETE FROM collection_movie WHERE collection_id = $1 AND movie_id = $2", collection_id, movie_id)
+            .execute(&self.pool).await?;
 
-        tx.commit().await?;
+        if result.rows_affected() == 0 {
+            return Err(MovieServiceError::MovieNotFound);
+        }
 
         Ok(())
     }
 
-    pub async fn get_movie_search_db(&self, movie_name: String) -> Result<Vec<Movie>> {
-        let movie_name = format!("%{}%", movie_name);
-        let movies = sqlx::query_as!(Movie, "SELECT 
-m.movie_id, m.distribution_title, m.original_title, l.language_name AS original_language,
-m.has_spanish_subtitles, m.production_year, m.website_url, m.image_url, m.duration_hours,
-m.summary, c.classification_name AS classification, co.country_name AS origin_country,
-g.genre_name AS genre FROM movie m
-INNER JOIN language l ON l.language_id = m.original_language_id
-INNER JOIN classification c ON c.classification_id = m.classification_id
-INNER JOIN movie_genre mg ON mg.movie_id = m.movie_id
-INNER JOIN genre g ON g.genre_id = mg.genre_id
-INNER JOIN movie_country mc ON mc.movie_id = m.movie_id
-INNER JOIN country co ON co.country_id = mc.country_id
-WHERE distribution_title ILIKE $1 OR original_title ILIKE $1", movie_name).fetch_all(&self.pool).await?;
-        Ok(movies)
+    // stats
+    async fn get_catalog_stats(&self) -> Result<CatalogStats> {
+        let totals = sqlx::query!("SELECT 
+COUNT(*) AS \"total_movies!\",
+AVG(m.runtime_minutes)::DOUBLE PRECISION AS average_runtime_minutes,
+COUNT(*) FILTER (WHERE EXISTS (
+    SELECT 1 FROM movie_subtitle_language s
+    INNER JOIN language l ON l.language_id = s.language_id
+    WHERE s.movie_id = m.movie_id AND (l.language_code = 'es' OR l.language_name = 'Spanish')
+)) AS \"spanish_subtitled!\"
+FROM movie m")
+            .fetch_one(&self.pool).await?;
+
+        let by_genre = sqlx::query_as!(StatCount, "SELECT 
+g.genre_name AS \"label!\", COUNT(mg.movie_id) AS \"movie_count!\"
+FROM genre g
+LEFT JOIN movie_genre mg ON mg.genre_id = g.genre_id
+GROUP BY g.genre_id
+ORDER BY 2 DESC, 1")
+            .fetch_all(&self.pool).await?;
+
+        let by_country = sqlx::query_as!(StatCount, "SELECT 
+c.country_name AS \"label!\", COUNT(mc.movie_id) AS \"movie_count!\"
+FROM country c
+LEFT JOIN movie_country mc ON mc.country_id = c.country_id
+GROUP BY c.country_id
+ORDER BY 2 DESC, 1")
+            .fetch_all(&self.pool).await?;
+
+        let by_original_language = sqlx::query_as!(StatCount, "SELECT 
+l.language_name AS \"label!\", COUNT(m.movie_id) AS \"movie_count!\"
+FROM language l
+LEFT JOIN movie m ON m.original_language_id = l.language_id
+GROUP BY l.language_id
+ORDER BY 2 DESC, 1")
+            .fetch_all(&self.pool).await?;
+
+        let by_classification = sqlx::query_as!(StatCount, "SELECT 
+c.classification_name AS \"label!\", COUNT(m.movie_id) AS \"movie_count!\"
+FROM classification c
+LEFT JOIN movie m ON m.classification_id = c.classification_id
+GROUP BY c.classification_id
+ORDER BY 2 DESC, 1")
+            .fetch_all(&self.pool).await?;
+
+        let by_decade = sqlx::query_as!(DecadeCount, "SELECT 
+production_year / 10 * 10 AS \"decade!\", COUNT(*) AS \"movie_count!\"
+FROM movie
+GROUP BY 1
+ORDER BY 1")
+            .fetch_all(&self.pool).await?;
+
+        let monthly_growth = sqlx::query_as!(MonthlyGrowth, "SELECT 
+month AS \"month!\", movies_added AS \"movies_added!\",
+(SUM(movies_added) OVER (ORDER BY month))::BIGINT AS \"total_movies!\"
+FROM (
+    SELECT DATE_TRUNC('month', created_at) AS month, COUNT(*) AS movies_added
+    FROM movie
+    GROUP BY 1
+) g
+ORDER BY month")
+            .fetch_all(&self.pool).await?;
+
+        let spanish_subtitle_share = if totals.total_movies == 0 {
+            0.0
+        } else {
+            totals.spanish_subtitled as f64 / totals.total_movies as f64
+        };
+
+        Ok(CatalogStats {
+            total_movies: totals.total_movies,
+            average_runtime_minutes: totals.average_runtime_minutes,
+            spanish_subtitle_share,
+            by_genre,
+            by_country,
+            by_original_language,
+            by_classification,
+            by_decade,
+            monthly_growth,
+            computed_at: Utc::now(),
+        })
     }
 }
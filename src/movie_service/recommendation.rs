@@ -0,0 +1,210 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}, time::Duration};
+
+use tracing::{error, info};
+
+use super::repository::MovieRepository;
+
+// how much a client cares about a movie: the rating when there is one,
+// otherwise 5 for a favorite and 3 for a movie that was only watched
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub client_id: i32,
+    pub movie_id: i32,
+    pub strength: i32,
+}
+
+// interactions at least this strong count as liking the movie
+pub const LIKED_STRENGTH: i32 = 3;
+
+const NEIGHBOURS_PER_MOVIE: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct ItemSimilarities {
+    neighbours: HashMap<i32, Vec<(i32, f64)>>,
+    popular: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredMovie {
+    pub movie_id: i32,
+    pub score: f64,
+    // liked movie that contributed the most to the score, none for popularity picks
+    pub because_of: Option<i32>,
+}
+
+impl ItemSimilarities {
+    // item-item cosine similarity over the client interaction vectors
+    pub fn compute(interactions: &[Interaction]) -> Self {
+        let mut by_client: HashMap<i32, Vec<(i32, f64)>> = HashMap::new();
+        let mut norms: HashMap<i32, f64> = HashMap::new();
+        let mut clients_per_movie: HashMap<i32, usize> = HashMap::new();
+
+        for interaction in interactions {
+            let strength = f64::from(interaction.strength);
+
+            by_client.entry(interaction.client_id).or_default().push((interaction.movie_id, strength));
+            *norms.entry(interaction.movie_id).or_default() += strength * strength;
+            *clients_per_movie.entry(interaction.movie_id).or_default() += 1;
+        }
+
+        let mut dot_products: HashMap<(i32, i32), f64> = HashMap::new();
+
+        for movies in by_client.values() {
+            for (i, (movie_a, strength_a)) in movies.iter().enumerate() {
+                for (movie_b, strength_b) in movies.iter().skip(i + 1) {
+                    let key = (*movie_a.min(movie_b), *movie_a.max(movie_b));
+                    *dot_products.entry(key).or_default() += strength_a * strength_b;
+                }
+            }
+        }
+
+        let mut neighbours: HashMap<i32, Vec<(i32, f64)>> = HashMap::new();
+
+        for ((movie_a, movie_b), dot_product) in dot_products {
+            let similarity = dot_product / (norms[&movie_a].sqrt() * norms[&movie_b].sqrt());
+
+            neighbours.entry(movie_a).or_default().push((movie_b, similarity));
+            neighbours.entry(movie_b).or_default().push((movie_a, similarity));
+        }
+
+        for movie_neighbours in neighbours.values_mut() {
+            movie_neighbours.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            movie_neighbours.truncate(NEIGHBOURS_PER_MOVIE);
+        }
+
+        let mut popular: Vec<(i32, usize)> = clients_per_movie.into_iter().collect();
+        popular.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        Self {
+            neighbours,
+            popular: popular.into_iter().map(|(movie_id, _)| movie_id).collect(),
+        }
+    }
+
+    // client_interactions are the interactions of a single client, excluded movies are never returned
+    pub fn recommend(&self, client_interactions: &[Interaction], excluded: &[i32], quantity: usize) -> Vec<ScoredMovie> {
+        let mut scores: HashMap<i32, (f64, Option<(i32, f64)>)> = HashMap::new();
+
+        let liked = client_interactions.iter()
+            .filter(|interaction| interaction.strength >= LIKED_STRENGTH);
+
+        for interaction in liked {
+            let Some(movie_neighbours) = self.neighbours.get(&interaction.movie_id) else {
+                continue;
+            };
+
+            for (movie_id, similarity) in movie_neighbours {
+                let is_known = client_interactions.iter().any(|known| known.movie_id == *movie_id);
+
+                if is_known || excluded.contains(movie_id) {
+                    continue;
+                }
+
+                let contribution = similarity * f64::from(interaction.strength);
+                let (score, best) = scores.entry(*movie_id).or_insert((0.0, None));
+
+                *score += contribution;
+
+                if best.is_none_or(|(_, best_contribution)| contribution > best_contribution) {
+                    *best = Some((interaction.movie_id, contribution));
+                }
+            }
+        }
+
+        let mut recommendations: Vec<ScoredMovie> = scores.into_iter()
+            .map(|(movie_id, (score, best))| ScoredMovie {
+                movie_id,
+                score,
+                because_of: best.map(|(because_of, _)| because_of),
+            })
+            .collect();
+
+        recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.movie_id.cmp(&b.movie_id)));
+        recommendations.truncate(quantity);
+
+        // cold start, or not enough neighbours: fill up with what is popular
+        for movie_id in self.popular.iter() {
+            if recommendations.len() >= quantity {
+                break;
+            }
+
+            let is_taken = recommendations.iter().any(|recommendation| recommendation.movie_id == *movie_id);
+            let is_known = client_interactions.iter().any(|known| known.movie_id == *movie_id);
+
+            if !is_taken && !is_known && !excluded.contains(movie_id) {
+                recommendations.push(ScoredMovie { movie_id: *movie_id, score: 0.0, because_of: None });
+            }
+        }
+
+        recommendations
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RecommendationModel {
+    similarities: RwLock<Arc<ItemSimilarities>>,
+}
+
+impl RecommendationModel {
+    pub fn current(&self) -> Arc<ItemSimilarities> {
+        self.similarities.read().expect("recommendation model poisoned").clone()
+    }
+
+    pub fn replace(&self, similarities: ItemSimilarities) {
+        *self.similarities.write().expect("recommendation model poisoned") = Arc::new(similarities);
+    }
+}
+
+pub async fn refresh_model(db: &dyn MovieRepository, model: &RecommendationModel) -> super::error::Result<()> {
+    let interactions = db.get_interactions(None).await?;
+    let interaction_count = interactions.len();
+
+    // a failed computation keeps serving the previous model
+    let similarities = match tokio::task::spawn_blocking(move || ItemSimilarities::compute(&interactions)).await {
+        Ok(similarities) => similarities,
+        Err(err) => {
+            error!("Recommendation computation failed, keeping the previous model: {}", err);
+            return Ok(());
+        }
+    };
+
+    model.replace(similarities);
+
+    info!("Recommendation model refreshed from {} interactions", interaction_count);
+
+    Ok(())
+}
+
+pub fn spawn_refresh_job(db: Arc<dyn MovieRepository>, model: Arc<RecommendationModel>, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = refresh_model(db.as_ref(), &model).await {
+                error!("Error refreshing the recommendation model: {}", err);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_recommend_explains_and_excludes() {
+    let interaction = |client_id, movie_id, strength| Interaction { client_id, movie_id, strength };
+
+    let similarities = ItemSimilarities::compute(&[
+        interaction(1, 10, 5), interaction(1, 20, 5), interaction(1, 30, 4),
+        interaction(2, 10, 5), interaction(2, 20, 4),
+        interaction(3, 40, 3), interaction(4, 40, 3), interaction(5, 40, 3),
+    ]);
+
+    let client = [interaction(9, 10, 5)];
+
+    let recommendations = similarities.recommend(&client, &[30], 3);
+
+    assert_eq!(recommendations[0], ScoredMovie { movie_id: 20, score: recommendations[0].score, because_of: Some(10) });
+    assert_eq!(recommendations[1].movie_id, 40);
+    assert_eq!(recommendations[1].because_of, None);
+    assert!(recommendations.iter().all(|recommendation| recommendation.movie_id != 30 && recommendation.movie_id != 10));
+}
@@ -0,0 +1,157 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use super::domain::{BasicMovie, CatalogStats, Classification, Collection, CollectionConstructor, Country, Franchise, FranchiseConstructor, Genre, Language, Movie, MovieFilter, MovieTranslation, MovieTranslationConstructor, OrderedEntryConstructor, Tag};
+use super::engagement::EngagementCount;
+use super::error::Result;
+use super::movie_database::MovieDataDb;
+use super::recommendation::Interaction;
+use super::similarity::MovieFeatures;
+
+// everything the movie service stores: the catalog with its reference data and translations,
+// ratings and watch history, engagement, tags, franchises, collections and the catalog stats.
+// implemented by `MovieDb` on Postgres and by `InMemoryMovieRepository` in the tests.
+// an `age_limit` of None shows every movie
+#[async_trait]
+pub trait MovieRepository: Debug + Send + Sync {
+    async fn get_language_id(&self, language_name: String) -> Result<Option<i32>>;
+
+    // unknown names are left out, so callers compare the lengths
+    async fn get_language_ids(&self, language_names: &[String]) -> Result<Vec<i32>>;
+
+    async fn get_country_id(&self, country_name: String) -> Result<Option<i32>>;
+
+    async fn get_genre_id(&self, genre_name: String) -> Result<Option<i32>>;
+
+    async fn get_classification_id(&self, classification_name: String) -> Result<Option<i32>>;
+
+    async fn insert_movie(&self, movie: &MovieDataDb) -> Result<i32>;
+
+    async fn get_movie(&self, movie_id: i32) -> Result<Movie>;
+
+    async fn update_movie(&self, movie_id: i32, movie: &MovieDataDb) -> Result<()>;
+
+    async fn delete_movie(&self, movie_id: i32) -> Result<()>;
+
+    async fn get_movie_minimum_age(&self, movie_id: i32) -> Result<i32>;
+
+    // None when the client has neither a birth date nor a parental limit
+    async fn get_client_age_limit(&self, client_name: &str) -> Result<Option<i32>>;
+
+    async fn get_languages(&self) -> Result<Vec<Language>>;
+
+    async fn get_language(&self, language_id: i32) -> Result<Option<Language>>;
+
+    async fn get_movie_translations(&self, movie_id: i32) -> Result<Vec<MovieTranslation>>;
+
+    async fn get_preferred_translations(&self, languages: &[String], movie_ids: &[i32]) -> Result<Vec<MovieTranslation>>;
+
+    async fn upsert_movie_translation(&self, movie_id: i32, language_id: i32, translation: &MovieTranslationConstructor) -> Result<()>;
+
+    async fn delete_movie_translation(&self, movie_id: i32, language_id: i32) -> Result<()>;
+
+    async fn get_countries(&self) -> Result<Vec<Country>>;
+
+    async fn get_country(&self, country_id: i32) -> Result<Option<Country>>;
+
+    async fn get_genres(&self) -> Result<Vec<Genre>>;
+
+    async fn get_genre(&self, genre_id: i32) -> Result<Option<Genre>>;
+
+    async fn get_classifications(&self) -> Result<Vec<Classification>>;
+
+    async fn get_classification(&self, classification_id: i32) -> Result<Option<Classification>>;
+
+    async fn create_language(&self, language_name: String, language_code: Option<String>) -> Result<()>;
+
+    async fn create_country(&self, country_name: String) -> Result<()>;
+
+    async fn create_genre(&self, genre_name: String) -> Result<()>;
+
+    async fn create_classification(&self, classification_name: String, minimum_age: i32) -> Result<()>;
+
+    // pages and search
+    async fn get_basic_movie_page(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Result<Vec<BasicMovie>>;
+
+    async fn get_movie_page(&self, page: i64, quantity: i64, age_limit: Option<i32>, filter: &MovieFilter) -> Result<Vec<Movie>>;
+
+    async fn get_movie_search(&self, movie_name: String, age_limit: Option<i32>, tag_names: &[String]) -> Result<Vec<Movie>>;
+
+    // keeps the order of the given ids
+    async fn get_basic_movies_by_ids(&self, movie_ids: &[i32], age_limit: Option<i32>) -> Result<Vec<BasicMovie>>;
+
+    async fn get_movie_features(&self) -> Result<Vec<MovieFeatures>>;
+
+    // ratings, favorites and watch history, an unknown movie is MovieNotFound
+    async fn set_rating(&self, client_name: &str, movie_id: i32, rating: i32) -> Result<()>;
+
+    async fn delete_rating(&self, client_name: &str, movie_id: i32) -> Result<()>;
+
+    async fn add_favorite(&self, client_name: &str, movie_id: i32) -> Result<()>;
+
+    async fn delete_favorite(&self, client_name: &str, movie_id: i32) -> Result<()>;
+
+    async fn add_watched(&self, client_name: &str, movie_id: i32) -> Result<()>;
+
+    async fn get_favorite_movies(&self, client_name: &str, age_limit: Option<i32>) -> Result<Vec<BasicMovie>>;
+
+    async fn get_watched_movies(&self, client_name: &str, age_limit: Option<i32>) -> Result<Vec<BasicMovie>>;
+
+    async fn get_watched_movie_ids(&self, client_name: &str) -> Result<Vec<i32>>;
+
+    // every interaction, or only the ones of a client
+    async fn get_interactions(&self, client_name: Option<&str>) -> Result<Vec<Interaction>>;
+
+    // engagement
+    async fn add_engagement_counts(&self, counts: &[EngagementCount]) -> Result<()>;
+
+    async fn refresh_popularity_scores(&self) -> Result<()>;
+
+    async fn get_trending_movies(&self, window_hours: i32, quantity: i64, age_limit: Option<i32>) -> Result<Vec<BasicMovie>>;
+
+    // tags, names have to be normalized already
+    async fn get_tags_by_prefix(&self, prefix: &str, quantity: i64) -> Result<Vec<Tag>>;
+
+    async fn add_movie_tag(&self, movie_id: i32, tag_name: String) -> Result<()>;
+
+    async fn delete_movie_tag(&self, movie_id: i32, tag_name: &str) -> Result<()>;
+
+    async fn get_tag_id(&self, tag_name: &str) -> Result<Option<i32>>;
+
+    // moves every movie of the source tag to the target tag and drops the source
+    async fn merge_tags(&self, source_tag_id: i32, target_tag_id: i32) -> Result<()>;
+
+    // franchises
+    async fn get_franchises(&self) -> Result<Vec<Franchise>>;
+
+    async fn get_franchise(&self, franchise_id: i32) -> Result<Franchise>;
+
+    async fn create_franchise(&self, franchise: &FranchiseConstructor) -> Result<i32>;
+
+    async fn get_franchise_movies(&self, franchise_id: i32, age_limit: Option<i32>) -> Result<Vec<BasicMovie>>;
+
+    async fn set_franchise_movie(&self, franchise_id: i32, entry: &OrderedEntryConstructor) -> Result<()>;
+
+    async fn delete_franchise_movie(&self, franchise_id: i32, movie_id: i32) -> Result<()>;
+
+    // collections
+    async fn get_collections(&self) -> Result<Vec<Collection>>;
+
+    async fn get_collection(&self, collection_id: i32) -> Result<Collection>;
+
+    async fn create_collection(&self, collection: &CollectionConstructor) -> Result<i32>;
+
+    async fn update_collection(&self, collection_id: i32, collection: &CollectionConstructor) -> Result<()>;
+
+    async fn delete_collection(&self, collection_id: i32) -> Result<()>;
+
+    async fn get_collection_movies(&self, collection_id: i32, age_limit: Option<i32>) -> Result<Vec<BasicMovie>>;
+
+    async fn set_collection_movie(&self, collection_id: i32, entry: &OrderedEntryConstructor) -> Result<()>;
+
+    async fn delete_collection_movie(&self, collection_id: i32, movie_id: i32) -> Result<()>;
+
+    // stats
+    async fn get_catalog_stats(&self) -> Result<CatalogStats>;
+}
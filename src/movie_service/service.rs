@@ -1,10 +1,52 @@
-use super::domain::MovieConstructor;
-use super::movie_database::{MovieDataDb, MovieDb};
+use std::collections::HashMap;
+
+use super::domain::{is_valid_tag_name, normalize_tag_name, normalize_tag_names, BasicMovie, CollectionDetail, FranchiseDetail, Movie, MovieConstructor, MovieTranslation, MovieTranslationConstructor, Recommendation};
+use super::movie_database::{MovieDataDb, MovieReleaseDb};
+use super::recommendation::RecommendationModel;
+use super::repository::MovieRepository;
+use super::similarity::SimilarityCache;
 use super::error::{self, Result};
 
 
-pub async fn create_movie(database: MovieDb, movie_constructor: MovieConstructor) -> Result<()> {
+pub async fn create_movie(database: &dyn MovieRepository, movie_constructor: MovieConstructor) -> Result<i32> {
+    let movie = movie_data(database, movie_constructor).await?;
+
+    database.insert_movie(&movie).await
+}
+
+pub async fn update_movie(database: &dyn MovieRepository, movie: Movie) -> Result<()> {
+    let movie_id = movie.movie_id;
+
+    let movie_constructor = MovieConstructor {
+        distribution_title: movie.distribution_title,
+        original_title: movie.original_title,
+        original_language: movie.original_language,
+        subtitle_languages: movie.subtitle_languages,
+        audio_languages: movie.audio_languages,
+        production_year: movie.production_year,
+        website_url: movie.website_url,
+        image_url: movie.image_url,
+        runtime_minutes: movie.runtime_minutes,
+        summary: movie.summary,
+        tagline: movie.tagline,
+        budget: movie.budget,
+        budget_currency: movie.budget_currency,
+        box_office: movie.box_office,
+        box_office_currency: movie.box_office_currency,
+        classification: movie.classification,
+        origin_country: movie.origin_country,
+        genre: movie.genre,
+        tags: movie.tags,
+        releases: movie.releases.0,
+    };
 
+    let movie = movie_data(database, movie_constructor).await?;
+
+    database.update_movie(movie_id, &movie).await
+}
+
+// resolves every name of the movie to its id
+async fn movie_data(database: &dyn MovieRepository, movie_constructor: MovieConstructor) -> Result<MovieDataDb> {
     let original_language_id = database.get_language_id(movie_constructor.original_language)
         .await?.ok_or(error::MovieServiceError::InvalidLanguageName)?;
 
@@ -17,22 +59,533 @@ pub async fn create_movie(database: MovieDb, movie_constructor: MovieConstructor
     let classification_id = database.get_classification_id(movie_constructor.classification)
         .await?.ok_or(error::MovieServiceError::InvalidClassificationName)?;
 
-    let movie_database_constructor = MovieDataDb {
+    let subtitle_language_ids = get_language_ids(database, &movie_constructor.subtitle_languages).await?;
+
+    // without explicit audio tracks the movie is only available in its original language
+    let audio_language_ids = match movie_constructor.audio_languages.is_empty() {
+        true => vec![original_language_id],
+        false => get_language_ids(database, &movie_constructor.audio_languages).await?,
+    };
+
+    let tags = normalize_tag_names(&movie_constructor.tags);
+
+    if !tags.iter().all(|tag_name| is_valid_tag_name(tag_name)) {
+        return Err(error::MovieServiceError::InvalidTagName);
+    }
+
+    let mut releases = Vec::new();
+
+    for release in movie_constructor.releases {
+        let country_id = database.get_country_id(release.country)
+            .await?.ok_or(error::MovieServiceError::InvalidCountryName)?;
+
+        releases.push(MovieReleaseDb {
+            country_id,
+            release_date: release.release_date,
+            release_type: release.release_type.as_str().to_string()
+        });
+    }
+
+    Ok(MovieDataDb {
         distribution_title: movie_constructor.distribution_title,
         original_title: movie_constructor.original_title,
         original_language_id,
-        has_spanish_subtitles: movie_constructor.has_spanish_subtitles,
+        subtitle_language_ids,
+        audio_language_ids,
         production_year: movie_constructor.production_year,
         website_url: movie_constructor.website_url,
         image_url: movie_constructor.image_url,
-        duration_hours: movie_constructor.duration_hours,
+        runtime_minutes: movie_constructor.runtime_minutes,
         summary: movie_constructor.summary,
+        tagline: movie_constructor.tagline,
+        budget: movie_constructor.budget,
+        budget_currency: movie_constructor.budget_currency,
+        box_office: movie_constructor.box_office,
+        box_office_currency: movie_constructor.box_office_currency,
         origin_country_id,
         genre_id,
-        classification_id
+        classification_id,
+        releases,
+        tags
+    })
+}
+
+async fn get_language_ids(database: &dyn MovieRepository, language_names: &[String]) -> Result<Vec<i32>> {
+    // a repeated track is not an unknown language
+    let mut language_names = language_names.to_vec();
+    language_names.sort();
+    language_names.dedup();
+
+    let language_ids = database.get_language_ids(&language_names).await?;
+
+    if language_ids.len() != language_names.len() {
+        return Err(error::MovieServiceError::InvalidLanguageName);
+    }
+
+    Ok(language_ids)
+}
+
+pub async fn ensure_age_allowed(database: &dyn MovieRepository, movie_id: i32, client_name: &str) -> Result<()> {
+    let Some(age_limit) = database.get_client_age_limit(client_name).await? else {
+        return Ok(());
     };
 
-    database.insert_movie(&movie_database_constructor).await?;
+    let minimum_age = database.get_movie_minimum_age(movie_id).await?;
+
+    if minimum_age > age_limit {
+        return Err(error::MovieServiceError::AgeRestricted);
+    }
+
+    Ok(())
+}
+
+pub async fn get_movie(database: &dyn MovieRepository, movie_id: i32, client_name: &str, languages: &[String]) -> Result<Movie> {
+    ensure_age_allowed(database, movie_id, client_name).await?;
+
+    let mut movie = database.get_movie(movie_id).await?;
+
+    localize_movies(database, std::slice::from_mut(&mut movie), languages).await?;
+
+    Ok(movie)
+}
+
+// language preferences from `?lang=` first, then the Accept-Language header ordered by quality
+pub fn parse_language_preferences(lang: Option<&str>, accept_language: Option<&str>) -> Vec<String> {
+    let mut weighted: Vec<(String, f32)> = accept_language.unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.trim().split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse().ok())
+                .unwrap_or(1.0);
+
+            // q=0 means the language is not acceptable at all
+            (quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+
+    weighted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let tags = lang.map(|lang| lang.trim().to_lowercase()).into_iter()
+        .chain(weighted.into_iter().map(|(tag, _)| tag));
+
+    let mut languages = Vec::new();
+
+    for tag in tags {
+        let primary = tag.split('-').next().unwrap_or_default().to_string();
+
+        for candidate in [tag, primary] {
+            if !candidate.is_empty() && !languages.contains(&candidate) {
+                languages.push(candidate);
+            }
+        }
+    }
+
+    languages
+}
+
+async fn get_translations(database: &dyn MovieRepository, languages: &[String], movie_ids: &[i32]) -> Result<HashMap<i32, MovieTranslation>> {
+    if languages.is_empty() || movie_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let translations = database.get_preferred_translations(languages, movie_ids).await?
+        .into_iter()
+        .map(|translation| (translation.movie_id, translation))
+        .collect();
+
+    Ok(translations)
+}
+
+pub async fn localize_movies(database: &dyn MovieRepository, movies: &mut [Movie], languages: &[String]) -> Result<()> {
+    let movie_ids: Vec<i32> = movies.iter().map(|movie| movie.movie_id).collect();
+    let mut translations = get_translations(database, languages, &movie_ids).await?;
+
+    for movie in movies.iter_mut() {
+        if let Some(translation) = translations.remove(&movie.movie_id) {
+            movie.distribution_title = translation.title;
+            movie.summary = translation.summary.or(movie.summary.take());
+            movie.tagline = translation.tagline.or(movie.tagline.take());
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn localize_basic_movies(database: &dyn MovieRepository, movies: &mut [BasicMovie], languages: &[String]) -> Result<()> {
+    let movie_ids: Vec<i32> = movies.iter().map(|movie| movie.movie_id).collect();
+    let mut translations = get_translations(database, languages, &movie_ids).await?;
+
+    for movie in movies.iter_mut() {
+        if let Some(translation) = translations.remove(&movie.movie_id) {
+            movie.distribution_title = translation.title;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn set_movie_translation(database: &dyn MovieRepository, movie_id: i32, translation: MovieTranslationConstructor) -> Result<()> {
+    let language_id = database.get_language_id(translation.language.clone())
+        .await?.ok_or(error::MovieServiceError::InvalidLanguageName)?;
+
+    database.upsert_movie_translation(movie_id, language_id, &translation).await?;
+
+    Ok(())
+}
+
+pub async fn delete_movie_translation(database: &dyn MovieRepository, movie_id: i32, language_name: String) -> Result<()> {
+    let language_id = database.get_language_id(language_name)
+        .await?.ok_or(error::MovieServiceError::InvalidLanguageName)?;
+
+    database.delete_movie_translation(movie_id, language_id).await?;
+
+    Ok(())
+}
+
+pub async fn get_similar_movies(database: &dyn MovieRepository, cache: &SimilarityCache, movie_id: i32, quantity: usize,
+    age_limit: Option<i32>, languages: &[String]) -> Result<Vec<BasicMovie>> {
+    if cache.is_stale() {
+        cache.load(database.get_movie_features().await?);
+    }
+
+    let ranking = cache.ranking(movie_id);
+
+    // ask for a few more so movies hidden by the age limit don't leave the page short
+    let movie_ids: Vec<i32> = ranking.iter()
+        .take(quantity.saturating_mul(2))
+        .map(|(movie_id, _)| *movie_id)
+        .collect();
+
+    let mut movies = database.get_basic_movies_by_ids(&movie_ids, age_limit).await?;
+    movies.truncate(quantity);
+
+    localize_basic_movies(database, &mut movies, languages).await?;
+
+    Ok(movies)
+}
+
+pub async fn rate_movie(database: &dyn MovieRepository, client_name: &str, movie_id: i32, rating: i32) -> Result<()> {
+    if !(1..=5).contains(&rating) {
+        return Err(error::MovieServiceError::InvalidRating);
+    }
+
+    database.set_rating(client_name, movie_id, rating).await?;
+
+    Ok(())
+}
+
+pub async fn get_recommendations(database: &dyn MovieRepository, model: &RecommendationModel, client_name: &str, quantity: usize,
+    age_limit: Option<i32>, languages: &[String]) -> Result<Vec<Recommendation>> {
+    let client_interactions = database.get_interactions(Some(client_name)).await?;
+    let watched = database.get_watched_movie_ids(client_name).await?;
+
+    // a few more than needed, the age limit may still hide some of them
+    let scored = model.current().recommend(&client_interactions, &watched, quantity.saturating_mul(2));
+
+    let mut movie_ids: Vec<i32> = scored.iter().map(|scored_movie| scored_movie.movie_id).collect();
+    movie_ids.extend(scored.iter().filter_map(|scored_movie| scored_movie.because_of));
+
+    let mut movies = database.get_basic_movies_by_ids(&movie_ids, None).await?;
+    localize_basic_movies(database, &mut movies, languages).await?;
+
+    let allowed = database.get_basic_movies_by_ids(&movie_ids, age_limit).await?;
+
+    let titles: HashMap<i32, BasicMovie> = movies.into_iter()
+        .map(|movie| (movie.movie_id, movie))
+        .collect();
+
+    let recommendations = scored.into_iter()
+        .filter(|scored_movie| allowed.iter().any(|movie| movie.movie_id == scored_movie.movie_id))
+        .filter_map(|scored_movie| {
+            let movie = titles.get(&scored_movie.movie_id)?;
+
+            let reason = match scored_movie.because_of.and_then(|because_of| titles.get(&because_of)) {
+                Some(liked) => format!("Because you liked {}", liked.distribution_title),
+                None => "Popular with other viewers".to_string(),
+            };
+
+            Some(Recommendation {
+                movie_id: movie.movie_id,
+                distribution_title: movie.distribution_title.clone(),
+                image_url: movie.image_url.clone(),
+                reason,
+                because_of_movie_id: scored_movie.because_of,
+            })
+        })
+        .take(quantity)
+        .collect();
+
+    Ok(recommendations)
+}
+
+pub async fn add_movie_tag(database: &dyn MovieRepository, movie_id: i32, tag_name: &str) -> Result<()> {
+    let tag_name = normalize_tag_name(tag_name);
+
+    if !is_valid_tag_name(&tag_name) {
+        return Err(error::MovieServiceError::InvalidTagName);
+    }
+
+    database.add_movie_tag(movie_id, tag_name).await?;
+
+    Ok(())
+}
+
+pub async fn merge_tags(database: &dyn MovieRepository, source: &str, target: &str) -> Result<()> {
+    let source_tag_id = database.get_tag_id(&normalize_tag_name(source))
+        .await?.ok_or(error::MovieServiceError::InvalidTagName)?;
+
+    let target_tag_id = database.get_tag_id(&normalize_tag_name(target))
+        .await?.ok_or(error::MovieServiceError::InvalidTagName)?;
+
+    if source_tag_id != target_tag_id {
+        database.merge_tags(source_tag_id, target_tag_id).await?;
+    }
 
     Ok(())
 }
+
+pub async fn get_franchise(database: &dyn MovieRepository, franchise_id: i32, age_limit: Option<i32>, languages: &[String]) -> Result<FranchiseDetail> {
+    let franchise = database.get_franchise(franchise_id).await?;
+    let mut movies = database.get_franchise_movies(franchise_id, age_limit).await?;
+
+    localize_basic_movies(database, &mut movies, languages).await?;
+
+    Ok(FranchiseDetail {
+        franchise_id: franchise.franchise_id,
+        franchise_name: franchise.franchise_name,
+        description: franchise.description,
+        movies
+    })
+}
+
+pub async fn get_collection(database: &dyn MovieRepository, collection_id: i32, age_limit: Option<i32>, languages: &[String]) -> Result<CollectionDetail> {
+    let collection = database.get_collection(collection_id).await?;
+    let mut movies = database.get_collection_movies(collection_id, age_limit).await?;
+
+    localize_basic_movies(database, &mut movies, languages).await?;
+
+    Ok(CollectionDetail {
+        collection_id: collection.collection_id,
+        title: collection.title,
+        description: collection.description,
+        cover_image_url: collection.cover_image_url,
+        created_at: collection.created_at,
+        movies
+    })
+}
+
+#[test]
+fn test_language_preferences_order() {
+    let languages = parse_language_preferences(Some("fr"), Some("en;q=0.8, es-CO, es;q=0.9, de;q=0, *;q=0.1"));
+
+    assert_eq!(languages, vec!["fr", "es-co", "es", "en"]);
+}
+
+#[cfg(test)]
+use super::domain::{FranchiseConstructor, OrderedEntryConstructor};
+
+#[cfg(test)]
+fn test_repository() -> super::memory_repository::InMemoryMovieRepository {
+    super::memory_repository::InMemoryMovieRepository::with_catalog(&["English", "Spanish"], &["USA", "Mexico"], &["Drama"], &["G"])
+}
+
+#[cfg(test)]
+fn test_movie_constructor() -> MovieConstructor {
+    serde_json::from_value(serde_json::json!({
+        "distribution_title": "Roma",
+        "original_title": "Roma",
+        "original_language": "Spanish",
+        "subtitle_languages": ["English"],
+        "production_year": 2018,
+        "website_url": "https://example.com",
+        "image_url": "https://example.com/roma.jpg",
+        "runtime_minutes": 135,
+        "summary": null,
+        "tagline": null,
+        "budget": null,
+        "budget_currency": null,
+        "box_office": null,
+        "box_office_currency": null,
+        "classification": "G",
+        "origin_country": "Mexico",
+        "genre": "Drama",
+        "tags": [" Black and White "],
+        "releases": [{"country": "USA", "release_date": "2018-11-21", "release_type": "streaming"}]
+    })).expect("valid movie constructor")
+}
+
+#[tokio::test]
+async fn test_create_movie_resolves_names() {
+    let repository = test_repository();
+
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+    let movie = repository.get_movie(movie_id).await.unwrap();
+
+    assert_eq!((movie.original_language.as_str(), movie.origin_country.as_str()), ("Spanish", "Mexico"));
+    assert_eq!(movie.subtitle_languages, vec!["English"]);
+    assert_eq!(movie.audio_languages, vec!["Spanish"]);
+    assert_eq!(movie.tags, vec!["black and white"]);
+    assert_eq!(movie.releases.0[0].country, "USA");
+}
+
+#[tokio::test]
+async fn test_create_movie_rejects_unknown_names() {
+    let repository = test_repository();
+
+    let mut movie_constructor = test_movie_constructor();
+    movie_constructor.genre = "Western".to_string();
+    let result = create_movie(&repository, movie_constructor).await;
+    assert!(matches!(result, Err(error::MovieServiceError::InvalidGenreName)));
+
+    let mut movie_constructor = test_movie_constructor();
+    movie_constructor.subtitle_languages.push("Klingon".to_string());
+    let result = create_movie(&repository, movie_constructor).await;
+    assert!(matches!(result, Err(error::MovieServiceError::InvalidLanguageName)));
+
+    assert!(matches!(repository.get_movie(1).await, Err(error::MovieServiceError::MovieNotFound)));
+
+    let mut movie_constructor = test_movie_constructor();
+    movie_constructor.tags.push("x".repeat(51));
+    let result = create_movie(&repository, movie_constructor).await;
+    assert!(matches!(result, Err(error::MovieServiceError::InvalidTagName)));
+
+    // naming a track twice only stores it once
+    let mut movie_constructor = test_movie_constructor();
+    movie_constructor.subtitle_languages.push("English".to_string());
+    let movie_id = create_movie(&repository, movie_constructor).await.unwrap();
+    assert_eq!(repository.get_movie(movie_id).await.unwrap().subtitle_languages, vec!["English"]);
+}
+
+#[tokio::test]
+async fn test_update_movie() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    let mut movie = repository.get_movie(movie_id).await.unwrap();
+    movie.distribution_title = "Roma (2018)".to_string();
+    movie.origin_country = "USA".to_string();
+    update_movie(&repository, movie.clone()).await.unwrap();
+
+    let updated = repository.get_movie(movie_id).await.unwrap();
+    assert_eq!((updated.distribution_title.as_str(), updated.origin_country.as_str()), ("Roma (2018)", "USA"));
+
+    movie.movie_id = 99;
+    assert!(matches!(update_movie(&repository, movie).await, Err(error::MovieServiceError::MovieNotFound)));
+}
+
+#[tokio::test]
+async fn test_delete_missing_movie() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    repository.delete_movie(movie_id).await.unwrap();
+
+    assert!(matches!(repository.delete_movie(movie_id).await, Err(error::MovieServiceError::MovieNotFound)));
+    assert!(matches!(repository.get_movie(movie_id).await, Err(error::MovieServiceError::MovieNotFound)));
+}
+
+#[tokio::test]
+async fn test_get_movie_checks_age_and_localizes() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    let translation = |title: &str| MovieTranslationConstructor {
+        language: "English".to_string(), title: title.to_string(), summary: None, tagline: None
+    };
+    set_movie_translation(&repository, movie_id, translation("Rome")).await.unwrap();
+
+    let languages = parse_language_preferences(None, Some("French, English;q=0.5"));
+    let movie = get_movie(&repository, movie_id, "esteban", &languages).await.unwrap();
+    assert_eq!(movie.distribution_title, "Rome");
+
+    repository.set_minimum_age("G", 16);
+    repository.set_client_age_limit("esteban", 12);
+    let result = get_movie(&repository, movie_id, "esteban", &languages).await;
+    assert!(matches!(result, Err(error::MovieServiceError::AgeRestricted)));
+
+    let result = get_movie(&repository, 99, "esteban", &languages).await;
+    assert!(matches!(result, Err(error::MovieServiceError::MovieNotFound)));
+}
+
+#[tokio::test]
+async fn test_rate_movie_checks_the_range() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    let result = rate_movie(&repository, "esteban", movie_id, 6).await;
+    assert!(matches!(result, Err(error::MovieServiceError::InvalidRating)));
+
+    rate_movie(&repository, "esteban", movie_id, 4).await.unwrap();
+    repository.add_favorite("esteban", movie_id).await.unwrap();
+
+    // the rating wins over the favorite
+    let interactions = repository.get_interactions(Some("esteban")).await.unwrap();
+    assert_eq!(interactions.iter().map(|interaction| (interaction.movie_id, interaction.strength)).collect::<Vec<_>>(), vec![(movie_id, 4)]);
+}
+
+#[tokio::test]
+async fn test_merge_tags_moves_the_movies() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    add_movie_tag(&repository, movie_id, " Film  Noir ").await.unwrap();
+    assert_eq!(repository.get_movie(movie_id).await.unwrap().tags, vec!["black and white", "film noir"]);
+
+    merge_tags(&repository, "film noir", "Black and White").await.unwrap();
+    assert_eq!(repository.get_movie(movie_id).await.unwrap().tags, vec!["black and white"]);
+    assert_eq!(repository.get_tag_id("film noir").await.unwrap(), None);
+
+    let result = merge_tags(&repository, "film noir", "black and white").await;
+    assert!(matches!(result, Err(error::MovieServiceError::InvalidTagName)));
+
+    let result = add_movie_tag(&repository, movie_id + 1, "noir").await;
+    assert!(matches!(result, Err(error::MovieServiceError::MovieNotFound)));
+}
+
+#[tokio::test]
+async fn test_franchise_hides_movies_above_the_age_limit() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    let franchise = FranchiseConstructor { franchise_name: "Cuarón".to_string(), description: None };
+    let franchise_id = repository.create_franchise(&franchise).await.unwrap();
+    repository.set_franchise_movie(franchise_id, &OrderedEntryConstructor { movie_id, position: None }).await.unwrap();
+
+    repository.set_minimum_age("G", 16);
+
+    let franchise = get_franchise(&repository, franchise_id, Some(12), &[]).await.unwrap();
+    assert!(franchise.movies.is_empty());
+
+    let franchise = get_franchise(&repository, franchise_id, None, &[]).await.unwrap();
+    assert_eq!(franchise.movies.iter().map(|movie| movie.movie_id).collect::<Vec<_>>(), vec![movie_id]);
+
+    let result = get_franchise(&repository, franchise_id + 1, None, &[]).await;
+    assert!(matches!(result, Err(error::MovieServiceError::FranchiseNotFound)));
+}
+
+#[tokio::test]
+async fn test_watch_history_respects_the_age_limit() {
+    let repository = test_repository();
+    let movie_id = create_movie(&repository, test_movie_constructor()).await.unwrap();
+
+    repository.add_watched("esteban", movie_id).await.unwrap();
+    repository.add_favorite("esteban", movie_id).await.unwrap();
+    repository.set_minimum_age("G", 16);
+
+    assert!(repository.get_watched_movies("esteban", Some(12)).await.unwrap().is_empty());
+    assert!(repository.get_favorite_movies("esteban", Some(12)).await.unwrap().is_empty());
+    assert_eq!(repository.get_watched_movies("esteban", Some(16)).await.unwrap().len(), 1);
+
+    let missing_movie_id = movie_id + 1;
+    assert!(matches!(repository.add_watched("esteban", missing_movie_id).await, Err(error::MovieServiceError::MovieNotFound)));
+    assert!(matches!(repository.add_favorite("esteban", missing_movie_id).await, Err(error::MovieServiceError::MovieNotFound)));
+    assert!(matches!(rate_movie(&repository, "esteban", missing_movie_id, 3).await, Err(error::MovieServiceError::MovieNotFound)));
+}
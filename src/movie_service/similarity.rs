@@ -0,0 +1,192 @@
+use std::{collections::HashMap, env, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use tracing::warn;
+
+// everything the content based similarity looks at for a movie
+#[derive(Debug, Clone)]
+pub struct MovieFeatures {
+    pub movie_id: i32,
+    pub original_language_id: i32,
+    pub classification_id: i32,
+    pub production_year: i32,
+    pub genre_ids: Vec<i32>,
+    pub country_ids: Vec<i32>,
+    pub tag_ids: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimilarityWeights {
+    pub genre: f64,
+    pub country: f64,
+    pub language: f64,
+    pub classification: f64,
+    pub production_year: f64,
+    pub tag: f64,
+    // years apart at which the production year stops counting
+    pub year_range: f64,
+}
+
+impl Default for SimilarityWeights {
+    fn default() -> Self {
+        Self {
+            genre: 3.0,
+            country: 1.0,
+            language: 1.0,
+            classification: 0.5,
+            production_year: 1.0,
+            tag: 2.0,
+            year_range: 15.0,
+        }
+    }
+}
+
+impl SimilarityWeights {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            genre: env_f64("SIMILARITY_WEIGHT_GENRE", default.genre),
+            country: env_f64("SIMILARITY_WEIGHT_COUNTRY", default.country),
+            language: env_f64("SIMILARITY_WEIGHT_LANGUAGE", default.language),
+            classification: env_f64("SIMILARITY_WEIGHT_CLASSIFICATION", default.classification),
+            production_year: env_f64("SIMILARITY_WEIGHT_PRODUCTION_YEAR", default.production_year),
+            tag: env_f64("SIMILARITY_WEIGHT_TAG", default.tag),
+            year_range: year_range_from_env(default.year_range),
+        }
+    }
+}
+
+// the year score divides by the range, anything under a year would blow it up
+fn year_range_from_env(default: f64) -> f64 {
+    let year_range = env_f64("SIMILARITY_YEAR_RANGE", default);
+
+    if year_range.is_nan() || year_range < 1.0 {
+        warn!("SIMILARITY_YEAR_RANGE must be at least 1, using {}", default);
+        return default;
+    }
+
+    year_range
+}
+
+pub fn env_f64(name: &str, default: f64) -> f64 {
+    env::var(name).ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn jaccard(a: &[i32], b: &[i32]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let shared = a.iter().filter(|value| b.contains(value)).count();
+    let total = a.len() + b.len() - shared;
+
+    shared as f64 / total as f64
+}
+
+pub fn similarity(a: &MovieFeatures, b: &MovieFeatures, weights: &SimilarityWeights) -> f64 {
+    let years_apart = (a.production_year - b.production_year).abs() as f64;
+    let year_score = (1.0 - years_apart / weights.year_range).max(0.0);
+
+    weights.genre * jaccard(&a.genre_ids, &b.genre_ids)
+        + weights.country * jaccard(&a.country_ids, &b.country_ids)
+        + weights.tag * jaccard(&a.tag_ids, &b.tag_ids)
+        + weights.language * f64::from(u8::from(a.original_language_id == b.original_language_id))
+        + weights.classification * f64::from(u8::from(a.classification_id == b.classification_id))
+        + weights.production_year * year_score
+}
+
+// other movies by descending similarity, movies sharing nothing are left out
+pub fn rank_similar(movie_id: i32, features: &[MovieFeatures], weights: &SimilarityWeights) -> Vec<(i32, f64)> {
+    let Some(target) = features.iter().find(|movie| movie.movie_id == movie_id) else {
+        return Vec::new();
+    };
+
+    let mut ranking: Vec<(i32, f64)> = features.iter()
+        .filter(|movie| movie.movie_id != movie_id)
+        .map(|movie| (movie.movie_id, similarity(target, movie, weights)))
+        .filter(|(_, score)| *score > 0.0)
+        .collect();
+
+    ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    ranking
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    loaded_at: Option<Instant>,
+    features: Arc<Vec<MovieFeatures>>,
+    rankings: HashMap<i32, Arc<Vec<(i32, f64)>>>,
+}
+
+// features are reloaded from the database once they are older than the ttl,
+// rankings are computed on demand and kept until the next reload
+#[derive(Debug)]
+pub struct SimilarityCache {
+    weights: SimilarityWeights,
+    ttl: Duration,
+    state: Mutex<CacheState>,
+}
+
+impl SimilarityCache {
+    pub fn new(weights: SimilarityWeights, ttl: Duration) -> Self {
+        Self { weights, ttl, state: Mutex::default() }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        let state = self.state.lock().expect("similarity cache poisoned");
+
+        state.loaded_at.is_none_or(|loaded_at| loaded_at.elapsed() > self.ttl)
+    }
+
+    pub fn load(&self, features: Vec<MovieFeatures>) {
+        let mut state = self.state.lock().expect("similarity cache poisoned");
+
+        *state = CacheState {
+            loaded_at: Some(Instant::now()),
+            features: Arc::new(features),
+            rankings: HashMap::new(),
+        };
+    }
+
+    pub fn ranking(&self, movie_id: i32) -> Arc<Vec<(i32, f64)>> {
+        let mut state = self.state.lock().expect("similarity cache poisoned");
+
+        if let Some(ranking) = state.rankings.get(&movie_id) {
+            return ranking.clone();
+        }
+
+        let ranking = Arc::new(rank_similar(movie_id, &state.features, &self.weights));
+        state.rankings.insert(movie_id, ranking.clone());
+
+        ranking
+    }
+}
+
+#[test]
+fn test_rank_similar_prefers_shared_features() {
+    let movie = |movie_id, production_year, genre_ids: Vec<i32>, tag_ids: Vec<i32>| MovieFeatures {
+        movie_id,
+        original_language_id: 1,
+        classification_id: 1,
+        production_year,
+        genre_ids,
+        country_ids: vec![1],
+        tag_ids,
+    };
+
+    let features = vec![
+        movie(1, 2000, vec![1, 2], vec![7]),
+        movie(2, 2001, vec![1, 2], vec![7]),
+        movie(3, 2001, vec![3], vec![]),
+        movie(4, 1950, vec![1], vec![]),
+    ];
+
+    let ranking: Vec<i32> = rank_similar(1, &features, &SimilarityWeights::default())
+        .into_iter()
+        .map(|(movie_id, _)| movie_id)
+        .collect();
+
+    assert_eq!(ranking, vec![2, 4, 3]);
+}
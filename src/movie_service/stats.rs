@@ -0,0 +1,36 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use tokio::sync::Mutex;
+
+use super::domain::CatalogStats;
+use super::error::Result;
+use super::repository::MovieRepository;
+
+// the lock is held while the stats are recomputed, so concurrent requests
+// on a stale cache wait for a single computation instead of each running it
+#[derive(Debug)]
+pub struct StatsCache {
+    ttl: Duration,
+    state: Mutex<Option<(Instant, Arc<CatalogStats>)>>,
+}
+
+impl StatsCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, state: Mutex::default() }
+    }
+
+    pub async fn get(&self, db: &dyn MovieRepository) -> Result<Arc<CatalogStats>> {
+        let mut state = self.state.lock().await;
+
+        if let Some((computed_at, stats)) = state.as_ref() {
+            if computed_at.elapsed() <= self.ttl {
+                return Ok(stats.clone());
+            }
+        }
+
+        let stats = Arc::new(db.get_catalog_stats().await?);
+        *state = Some((Instant::now(), stats.clone()));
+
+        Ok(stats)
+    }
+}
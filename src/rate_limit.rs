@@ -0,0 +1,301 @@
+use std::{collections::HashMap, env, net::{IpAddr, SocketAddr}, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use axum::{extract::{ConnectInfo, Request, State}, http::{header, HeaderMap, HeaderValue, StatusCode}, middleware::Next,
+    response::{IntoResponse, Response}, Extension};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::auth_middleware::ClientInfo;
+use crate::client_address::TrustedProxies;
+
+// buckets hold a minute of requests and refill in a minute, so an idle bucket is full after that
+const BUCKET_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub per_minute: u32
+}
+
+impl Quota {
+    fn capacity(&self) -> f64 {
+        self.per_minute as f64
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        self.per_minute as f64 / BUCKET_WINDOW.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quotas {
+    anonymous: Quota,
+    client: Quota,
+    admin: Quota
+}
+
+impl Quotas {
+    fn from_env() -> Self {
+        let quota = |name: &str, default: u32| Quota {
+            per_minute: env::var(name).ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|per_minute| *per_minute > 0)
+                .unwrap_or(default)
+        };
+
+        Self {
+            anonymous: quota("RATE_LIMIT_ANONYMOUS_PER_MINUTE", 60),
+            client: quota("RATE_LIMIT_CLIENT_PER_MINUTE", 300),
+            admin: quota("RATE_LIMIT_ADMIN_PER_MINUTE", 1200),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    // left in the bucket after this request
+    pub tokens: f64
+}
+
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    // takes a token from the bucket of the key when there is one
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, sqlx::Error>;
+
+    // forgets the buckets nobody took from for a whole window, returns how many
+    async fn remove_idle(&self) -> Result<u64, sqlx::Error>;
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant
+}
+
+// buckets of this instance only
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>
+}
+
+impl MemoryRateLimitStore {
+    fn take_at(&self, key: &str, quota: Quota, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: quota.capacity(), updated: now });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.refill_per_second()).min(quota.capacity());
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Decision { allowed, tokens: bucket.tokens }
+    }
+
+    // idle buckets are full again, forgetting them changes nothing
+    fn remove_idle_at(&self, now: Instant) -> u64 {
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+        let before = buckets.len();
+
+        buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < BUCKET_WINDOW);
+
+        (before - buckets.len()) as u64
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, sqlx::Error> {
+        Ok(self.take_at(key, quota, Instant::now()))
+    }
+
+    async fn remove_idle(&self) -> Result<u64, sqlx::Error> {
+        Ok(self.remove_idle_at(Instant::now()))
+    }
+}
+
+// buckets shared by every instance, refilled and taken from in a single statement
+pub struct PgRateLimitStore {
+    pool: PgPool
+}
+
+#[async_trait]
+impl RateLimitStore for PgRateLimitStore {
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, sqlx::Error> {
+        let bucket = sqlx::query!(
+            r#"INSERT INTO rate_limit_bucket AS bucket(bucket_key, tokens, last_allowed, updated_at)
+VALUES ($1, $2::FLOAT8 - 1, TRUE, NOW())
+ON CONFLICT (bucket_key) DO UPDATE SET
+    tokens = CASE
+        WHEN LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM NOW() - bucket.updated_at)::FLOAT8 * $3::FLOAT8) >= 1
+        THEN LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM NOW() - bucket.updated_at)::FLOAT8 * $3::FLOAT8) - 1
+        ELSE LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM NOW() - bucket.updated_at)::FLOAT8 * $3::FLOAT8)
+    END,
+    last_allowed = LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM NOW() - bucket.updated_at)::FLOAT8 * $3::FLOAT8) >= 1,
+    updated_at = NOW()
+RETURNING tokens, last_allowed"#,
+            key, quota.capacity(), quota.refill_per_second())
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(Decision { allowed: bucket.last_allowed, tokens: bucket.tokens })
+    }
+
+    async fn remove_idle(&self) -> Result<u64, sqlx::Error> {
+        let removed = sqlx::query!("DELETE FROM rate_limit_bucket WHERE updated_at < NOW() - make_interval(secs => $1)",
+            BUCKET_WINDOW.as_secs_f64())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(removed.rows_affected())
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    quotas: Quotas,
+    trusted_proxies: Arc<TrustedProxies>
+}
+
+impl RateLimiter {
+    // RATE_LIMIT_STORE=postgres for deployments with several instances
+    pub fn from_env(db_pool: PgPool) -> Self {
+        let store: Arc<dyn RateLimitStore> = match env::var("RATE_LIMIT_STORE").as_deref() {
+            Ok("postgres") => Arc::new(PgRateLimitStore { pool: db_pool }),
+            _ => Arc::new(MemoryRateLimitStore::default()),
+        };
+
+        spawn_cleanup_job(store.clone());
+
+        Self { store, quotas: Quotas::from_env(), trusted_proxies: Arc::new(TrustedProxies::from_env()) }
+    }
+
+    // behind a proxy every anonymous request comes from its address, so the forwarded one is used
+    fn anonymous_key(&self, headers: &HeaderMap, peer: IpAddr) -> String {
+        format!("ip:{}", self.trusted_proxies.client_ip(headers, peer))
+    }
+}
+
+// every window, so neither the map of this instance nor the shared table grows with every address ever seen
+fn spawn_cleanup_job(store: Arc<dyn RateLimitStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BUCKET_WINDOW);
+
+        loop {
+            interval.tick().await;
+
+            match store.remove_idle().await {
+                Ok(0) => {}
+                Ok(removed) => info!("Removed {} idle rate limit buckets", removed),
+                Err(err) => error!("Error removing idle rate limit buckets: {}", err),
+            }
+        }
+    });
+}
+
+// layered after `auth_middleware` so authenticated requests are limited per client,
+// anonymous requests are limited per address
+pub async fn rate_limit_middleware(
+    State(rate_limiter): State<RateLimiter>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    client_info: Option<Extension<ClientInfo>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next
+) -> Response {
+    let (key, quota) = match client_info {
+        Some(Extension(client_info)) if client_info.is_admin => (format!("client:{}", client_info.client_name), rate_limiter.quotas.admin),
+        Some(Extension(client_info)) => (format!("client:{}", client_info.client_name), rate_limiter.quotas.client),
+        None => (rate_limiter.anonymous_key(&headers, address.ip()), rate_limiter.quotas.anonymous),
+    };
+
+    // an unavailable shared store shouldn't take the whole API down
+    let decision = match rate_limiter.store.take(&key, quota).await {
+        Ok(decision) => decision,
+        Err(err) => {
+            error!("Error checking the rate limit of {}: {}", key, err);
+            return next.run(request).await;
+        }
+    };
+
+    if !decision.allowed {
+        let retry_after = ((1.0 - decision.tokens) / quota.refill_per_second()).ceil().max(1.0) as u64;
+
+        let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+        insert_rate_limit_headers(response.headers_mut(), quota, decision);
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    insert_rate_limit_headers(response.headers_mut(), quota, decision);
+
+    response
+}
+
+// RateLimit header fields draft, the reset is the time until the bucket is full again
+fn insert_rate_limit_headers(headers: &mut HeaderMap, quota: Quota, decision: Decision) {
+    let remaining = decision.tokens.floor().max(0.0) as u32;
+    let reset = ((quota.capacity() - decision.tokens) / quota.refill_per_second()).ceil().max(0.0) as u64;
+
+    headers.insert("ratelimit-limit", HeaderValue::from(quota.per_minute));
+    headers.insert("ratelimit-remaining", HeaderValue::from(remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(reset));
+    headers.insert("ratelimit-policy", HeaderValue::from_str(&format!("{};w={}", quota.per_minute, BUCKET_WINDOW.as_secs()))
+        .expect("valid header value"));
+}
+
+#[test]
+fn test_memory_token_bucket() {
+    let store = MemoryRateLimitStore::default();
+    let quota = Quota { per_minute: 3 };
+    let now = Instant::now();
+
+    for remaining in [2.0, 1.0, 0.0] {
+        assert_eq!(store.take_at("client:esteban", quota, now), Decision { allowed: true, tokens: remaining });
+    }
+    assert!(!store.take_at("client:esteban", quota, now).allowed);
+
+    // other keys have their own bucket
+    assert!(store.take_at("ip:10.0.0.1", quota, now).allowed);
+
+    // one token every 20 seconds
+    assert!(store.take_at("client:esteban", quota, now + Duration::from_secs(20)).allowed);
+    assert!(!store.take_at("client:esteban", quota, now + Duration::from_secs(21)).allowed);
+
+    // never more than the capacity
+    let decision = store.take_at("client:esteban", quota, now + Duration::from_secs(600));
+    assert_eq!(decision, Decision { allowed: true, tokens: 2.0 });
+
+    // only the bucket of the address went a whole window without requests
+    assert_eq!(store.remove_idle_at(now + Duration::from_secs(600)), 1);
+    assert_eq!(store.buckets.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_anonymous_key_behind_a_trusted_proxy() {
+    let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+    let rate_limiter = RateLimiter {
+        store: Arc::new(MemoryRateLimitStore::default()),
+        quotas: Quotas::from_env(),
+        trusted_proxies: Arc::new(TrustedProxies::new(vec![proxy]))
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "203.0.113.7".parse().unwrap());
+
+    // clients behind the proxy get a bucket each instead of sharing the one of the proxy
+    assert_eq!(rate_limiter.anonymous_key(&headers, proxy), "ip:203.0.113.7");
+    assert_eq!(rate_limiter.anonymous_key(&HeaderMap::new(), proxy), "ip:10.0.0.1");
+
+    // anyone else can't choose their bucket
+    assert_eq!(rate_limiter.anonymous_key(&headers, "198.51.100.4".parse().unwrap()), "ip:198.51.100.4");
+}
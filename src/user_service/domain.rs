@@ -1,6 +1,148 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 
+#[derive(Debug, Clone)]
 pub struct Client {
     pub client_id: i32,
     pub client_name: String,
-    pub encrypted_password: String
+    pub encrypted_password: String,
+    pub birth_date: Option<NaiveDate>,
+    pub session_version: i32,
+    pub email: Option<String>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    pub failed_login_count: i32,
+    pub locked_until: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub api_key_id: i32,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub preferred_language: Option<String>,
+    pub favorite_genres: Vec<String>,
+    pub created_at: DateTime<Utc>
+}
+
+// fields left out of an update are kept, the outer option is whether the field was sent
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProfileUpdate {
+    #[serde(default, deserialize_with = "sent")]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "sent")]
+    pub avatar_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "sent")]
+    pub preferred_language: Option<Option<String>>,
+    #[serde(default)]
+    pub favorite_genres: Option<Vec<String>>
+}
+
+// a field sent as null clears it, unlike a field left out
+fn sent<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientSession {
+    pub session_id: i32,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    // whether the listing request was made with this session
+    pub current: bool
+}
+
+// a movie the client rated, marked as favorite or watched, the rating only for rated ones
+#[derive(Debug, Clone, Serialize)]
+pub struct MovieActivity {
+    pub movie_id: i32,
+    pub distribution_title: String,
+    pub rating: Option<i32>,
+    pub at: DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkedIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub linked_at: DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OwnedOAuthApp {
+    pub public_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>
+}
+
+// everything stored about a client besides the account and profile,
+// there are no reviews, lists or orders in the schema yet, they join here when they exist
+#[derive(Debug, Clone, Serialize)]
+pub struct PersonalData {
+    // set on child accounts
+    pub parent_client_id: Option<i32>,
+    pub parental_age_limit: Option<i32>,
+    pub ratings: Vec<MovieActivity>,
+    pub favorites: Vec<MovieActivity>,
+    pub watched: Vec<MovieActivity>,
+    // revoked and expired ones included
+    pub sessions: Vec<ClientSession>,
+    pub api_keys: Vec<ApiKey>,
+    pub linked_identities: Vec<LinkedIdentity>,
+    pub oauth_apps: Vec<OwnedOAuthApp>
+}
+
+// where a login comes from, kept with its session
+#[derive(Debug, Clone, Default)]
+pub struct SessionOrigin {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthApp {
+    pub app_id: i32,
+    pub public_id: String,
+    pub secret_hash: Option<String>,
+    pub owner_id: i32,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorizationCode {
+    pub app_id: i32,
+    pub client_id: i32,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub code_challenge: String,
+    pub expires_at: DateTime<Utc>
+}
+
+#[derive(Debug, Clone)]
+pub struct OidcLoginState {
+    pub nonce: String,
+    pub code_verifier: String,
+    // the callback has to come from the browser holding the secret behind it
+    pub binding_hash: String,
+    pub expires_at: DateTime<Utc>
 }
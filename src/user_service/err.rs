@@ -11,9 +11,123 @@ pub enum UserServiceError {
     #[error("Error hashing password")]
     BcryptError(#[from] bcrypt::BcryptError),
 
+    #[error("Error hashing password")]
+    Argon2Error(#[from] argon2::password_hash::Error),
+
     #[error("Error generating JWT token")]
     JsonWebTokenError(#[from] jsonwebtoken::errors::Error),
 
     #[error("Invalid password")]
-    InvalidPassword(String)
+    InvalidPassword(String),
+
+    #[error("Too many failed logins, retry in {0} seconds")]
+    TooManyLoginAttempts(u64),
+
+    #[error("Invalid API key request")]
+    InvalidApiKeyRequest,
+
+    #[error("API key not found")]
+    ApiKeyNotFound,
+
+    #[error("Session not found")]
+    SessionNotFound,
+
+    #[error("OAuth application not found")]
+    OAuthAppNotFound,
+
+    #[error("Invalid OAuth application registration")]
+    InvalidOAuthApp,
+
+    #[error("Invalid OAuth request")]
+    InvalidOAuthRequest,
+
+    #[error("Invalid OAuth client credentials")]
+    InvalidOAuthClient,
+
+    #[error("Invalid or expired OAuth grant")]
+    InvalidGrant,
+
+    #[error("Invalid OAuth scope")]
+    InvalidScope,
+
+    #[error("Unsupported OAuth grant type")]
+    UnsupportedGrantType,
+
+    #[error("Invalid or expired OpenID Connect login state")]
+    InvalidOidcState,
+
+    #[error("Invalid ID token")]
+    InvalidIdToken,
+
+    #[error("Unexpected OpenID Connect provider response")]
+    OidcProviderError,
+
+    #[error("Error calling the OpenID Connect provider")]
+    OidcHttpError(#[from] reqwest::Error),
+
+    #[error("Invalid profile")]
+    InvalidProfile,
+
+    #[error("Invalid child account")]
+    InvalidChildAccount,
+
+    #[error("The child account is linked to another parent")]
+    ChildAlreadyLinked,
+
+    #[error("Client name already taken")]
+    ClientNameTaken,
+
+    #[error("Client name reserved for deleted accounts")]
+    ReservedClientName,
+
+    #[error("Email already taken")]
+    EmailTaken,
+
+    #[error("Invalid email address")]
+    InvalidEmail,
+
+    #[error("The client has no email address")]
+    MissingEmail,
+
+    #[error("Email already verified")]
+    EmailAlreadyVerified,
+
+    #[error("Invalid or expired email verification token")]
+    InvalidVerificationToken,
+
+    #[error("Client not found")]
+    ClientNotFound,
+
+    #[error("Invalid or expired password reset token")]
+    InvalidResetToken,
+
+    #[error("Invalid TOTP secret")]
+    InvalidTotpSecret,
+
+    #[error("Error rendering the QR code")]
+    QrCodeError(#[from] qrcode::types::QrError),
+
+    #[error("Invalid two factor code")]
+    InvalidTwoFactorCode,
+
+    #[error("Two factor authentication already enabled")]
+    TwoFactorAlreadyEnabled,
+
+    #[error("Two factor authentication not enrolled")]
+    TwoFactorNotEnrolled,
+
+    #[error("Invalid or expired login challenge")]
+    InvalidChallenge,
+
+    #[error("Error writing mail")]
+    MailIoError(#[from] std::io::Error),
+
+    #[error("Error building mail")]
+    MailBuildError(#[from] lettre::error::Error),
+
+    #[error("Invalid mail address")]
+    MailAddressError(#[from] lettre::address::AddressError),
+
+    #[error("Error sending mail over SMTP")]
+    SmtpError(#[from] lettre::transport::smtp::Error)
 }
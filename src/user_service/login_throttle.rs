@@ -0,0 +1,83 @@
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::{Duration, Instant}};
+
+use tracing::warn;
+
+// failures allowed from one address before it has to wait, every further one doubles the wait
+const FREE_FAILURES: u32 = 10;
+const BASE_DELAY_SECONDS: u64 = 1;
+const MAX_DELAY_SECONDS: u64 = 15 * 60;
+// failures are forgotten after a quiet period
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+struct AddressFailures {
+    count: u32,
+    last_failure: Instant,
+}
+
+// per address throttling of the login endpoints, kept in memory so it is per instance
+#[derive(Debug, Default)]
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<IpAddr, AddressFailures>>,
+}
+
+impl LoginThrottle {
+    fn failures(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, AddressFailures>> {
+        self.failures.lock().expect("login throttle poisoned")
+    }
+
+    // time left before the address may try again
+    pub fn retry_after(&self, address: IpAddr, now: Instant) -> Option<Duration> {
+        let failures = self.failures();
+        let address_failures = failures.get(&address)?;
+
+        let blocked_until = address_failures.last_failure + delay(address_failures.count);
+
+        blocked_until.checked_duration_since(now).filter(|wait| !wait.is_zero())
+    }
+
+    pub fn record_failure(&self, address: IpAddr, now: Instant) {
+        let mut failures = self.failures();
+        failures.retain(|_, address_failures| now.duration_since(address_failures.last_failure) < FORGET_AFTER);
+
+        let address_failures = failures.entry(address).or_insert(AddressFailures { count: 0, last_failure: now });
+        address_failures.count += 1;
+        address_failures.last_failure = now;
+
+        if address_failures.count > FREE_FAILURES {
+            warn!("Logins from {} blocked for {:?} after {} failures", address, delay(address_failures.count), address_failures.count);
+        }
+    }
+}
+
+fn delay(count: u32) -> Duration {
+    if count <= FREE_FAILURES {
+        return Duration::ZERO;
+    }
+
+    let doublings = (count - FREE_FAILURES - 1).min(20);
+
+    Duration::from_secs((BASE_DELAY_SECONDS << doublings).min(MAX_DELAY_SECONDS))
+}
+
+#[test]
+fn test_login_throttle_backoff() {
+    let throttle = LoginThrottle::default();
+    let address: IpAddr = "10.0.0.1".parse().unwrap();
+    let now = Instant::now();
+
+    for _ in 0..FREE_FAILURES {
+        throttle.record_failure(address, now);
+    }
+    assert_eq!(throttle.retry_after(address, now), None);
+
+    throttle.record_failure(address, now);
+    assert_eq!(throttle.retry_after(address, now), Some(Duration::from_secs(1)));
+
+    throttle.record_failure(address, now);
+    throttle.record_failure(address, now);
+    assert_eq!(throttle.retry_after(address, now), Some(Duration::from_secs(4)));
+    assert_eq!(throttle.retry_after(address, now + Duration::from_secs(4)), None);
+
+    assert_eq!(throttle.retry_after("10.0.0.2".parse().unwrap(), now), None);
+}
@@ -0,0 +1,113 @@
+use std::{env, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::{message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::info;
+
+use super::err::Result;
+
+#[derive(Debug, Clone)]
+pub struct MailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: MailMessage) -> Result<()>;
+}
+
+// SMTP when SMTP_HOST is set, the log mailer otherwise
+pub fn mailer_from_env() -> Result<Arc<dyn Mailer>> {
+    match env::var("SMTP_HOST") {
+        Ok(host) => Ok(Arc::new(SmtpMailer::from_env(&host)?)),
+        Err(_) => Ok(Arc::new(LogMailer::from_env())),
+    }
+}
+
+// STARTTLS relay, SMTP_PORT defaults to 587 and the credentials are optional
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox
+}
+
+impl SmtpMailer {
+    pub fn from_env(host: &str) -> Result<Self> {
+        let port = env::var("SMTP_PORT").ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(587);
+
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?.port(port);
+
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+
+        let from = env::var("MAIL_FROM").unwrap_or_else(|_| "no-reply@localhost".to_string()).parse()?;
+
+        Ok(Self { transport: transport.build(), from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: MailMessage) -> Result<()> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(message.to.parse()?)
+            .subject(message.subject)
+            .body(message.body)?;
+
+        self.transport.send(email).await?;
+
+        Ok(())
+    }
+}
+
+// development mailer: every message goes to a file in MAIL_OUTBOX_DIR,
+// or to the log when the variable is not set
+pub struct LogMailer {
+    outbox_dir: Option<PathBuf>
+}
+
+impl LogMailer {
+    pub fn from_env() -> Self {
+        Self { outbox_dir: env::var("MAIL_OUTBOX_DIR").ok().map(PathBuf::from) }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, message: MailMessage) -> Result<()> {
+        let Some(outbox_dir) = &self.outbox_dir else {
+            info!("Mail to {}: {}\n{}", message.to, message.subject, message.body);
+            return Ok(());
+        };
+
+        let file_name = format!("{}-{}.txt", Utc::now().format("%Y%m%dT%H%M%S%.f"), message.to);
+        let content = format!("To: {}\nSubject: {}\n\n{}\n", message.to, message.subject, message.body);
+
+        tokio::fs::create_dir_all(outbox_dir).await?;
+        tokio::fs::write(outbox_dir.join(file_name), content).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryMailer {
+    pub sent: std::sync::Mutex<Vec<MailMessage>>
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, message: MailMessage) -> Result<()> {
+        self.sent.lock().expect("memory mailer poisoned").push(message);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,670 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::domain::{ApiKey, AuthorizationCode, Client, ClientSession, LinkedIdentity, OAuthApp, OidcLoginState, OwnedOAuthApp, PersonalData,
+    Profile, ProfileUpdate, SessionOrigin};
+use super::err::{Result, UserServiceError};
+use super::repository::ClientRepository;
+
+#[derive(Debug, Clone)]
+struct StoredClient {
+    client: Client,
+    parent_client_id: Option<i32>,
+    parental_age_limit: Option<i32>,
+    totp_last_step: Option<i64>,
+    last_failed_login: Option<DateTime<Utc>>,
+    // hash and whether it was used
+    backup_codes: Vec<(String, bool)>,
+    profile: Profile,
+    deleted: bool,
+}
+
+#[derive(Debug, Clone)]
+struct StoredResetToken {
+    token_hash: String,
+    client_id: i32,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+#[derive(Debug, Clone)]
+struct StoredVerificationToken {
+    token_hash: String,
+    client_id: i32,
+    email: String,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+#[derive(Default)]
+pub struct InMemoryClientRepository {
+    clients: Mutex<Vec<StoredClient>>,
+    reset_tokens: Mutex<Vec<StoredResetToken>>,
+    verification_tokens: Mutex<Vec<StoredVerificationToken>>,
+    // owner and key, the hash is only looked up by the auth middleware
+    api_keys: Mutex<Vec<(i32, ApiKey)>>,
+    // owner and session
+    sessions: Mutex<Vec<(i32, ClientSession)>>,
+    // app and when it was registered
+    oauth_apps: Mutex<Vec<(OAuthApp, DateTime<Utc>)>>,
+    // code hash, code and whether it was used
+    authorization_codes: Mutex<Vec<(String, AuthorizationCode, bool)>>,
+    // state hash, state and whether it was used
+    oidc_login_states: Mutex<Vec<(String, OidcLoginState, bool)>>,
+    // owner and identity
+    identities: Mutex<Vec<(i32, LinkedIdentity)>>,
+}
+
+// a plain client with the defaults of a fresh registration, tests override the fields they need
+pub fn test_client(client_name: &str) -> Client {
+    Client {
+        client_id: 0,
+        client_name: client_name.to_string(),
+        encrypted_password: String::new(),
+        birth_date: None,
+        session_version: 0,
+        email: None,
+        email_verified_at: None,
+        totp_secret: None,
+        totp_enabled_at: None,
+        failed_login_count: 0,
+        locked_until: None
+    }
+}
+
+// whether a client other than `client_id` already verified the address
+fn email_verified_elsewhere(clients: &[StoredClient], email: &str, client_id: i32) -> bool {
+    clients.iter().any(|stored| stored.client.client_id != client_id
+        && stored.client.email.as_deref() == Some(email) && stored.client.email_verified_at.is_some())
+}
+
+impl InMemoryClientRepository {
+    fn clients(&self) -> std::sync::MutexGuard<'_, Vec<StoredClient>> {
+        self.clients.lock().expect("in memory repository poisoned")
+    }
+
+    fn api_keys(&self) -> std::sync::MutexGuard<'_, Vec<(i32, ApiKey)>> {
+        self.api_keys.lock().expect("in memory repository poisoned")
+    }
+
+    // what the auth middleware records on Postgres when a key is used
+    pub fn set_api_key_last_used(&self, api_key_id: i32, last_used_at: DateTime<Utc>) {
+        if let Some((_, api_key)) = self.api_keys().iter_mut().find(|(_, api_key)| api_key.api_key_id == api_key_id) {
+            api_key.last_used_at = Some(last_used_at);
+        }
+    }
+}
+
+#[async_trait]
+impl ClientRepository for InMemoryClientRepository {
+    async fn add_client(&self, client: &Client) -> Result<i32> {
+        let mut clients = self.clients();
+
+        if clients.iter().any(|stored| stored.client.client_name == client.client_name) {
+            return Err(UserServiceError::ClientNameTaken);
+        }
+
+        if client.email.as_deref().is_some_and(|email| email_verified_elsewhere(&clients, email, 0)) {
+            return Err(UserServiceError::EmailTaken);
+        }
+
+        let client_id = clients.len() as i32 + 1;
+
+        clients.push(StoredClient {
+            client: Client { client_id, ..client.clone() },
+            parent_client_id: None,
+            parental_age_limit: None,
+            totp_last_step: None,
+            last_failed_login: None,
+            backup_codes: Vec::new(),
+            profile: Profile {
+                display_name: None,
+                avatar_url: None,
+                preferred_language: None,
+                favorite_genres: Vec::new(),
+                created_at: Utc::now()
+            },
+            deleted: false,
+        });
+
+        Ok(client_id)
+    }
+
+    async fn get_client(&self, client_name: &str) -> Result<Client> {
+        self.clients().iter()
+            .find(|stored| stored.client.client_name == client_name && !stored.deleted)
+            .map(|stored| stored.client.clone())
+            .ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn get_client_by_id(&self, client_id: i32) -> Result<Client> {
+        self.clients().iter()
+            .find(|stored| stored.client.client_id == client_id && !stored.deleted)
+            .map(|stored| stored.client.clone())
+            .ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn get_profile(&self, client_id: i32) -> Result<Profile> {
+        self.clients().iter()
+            .find(|stored| stored.client.client_id == client_id && !stored.deleted)
+            .map(|stored| stored.profile.clone())
+            .ok_or(UserServiceError::ClientNotFound)
+    }
+
+    // every language and genre is known here
+    async fn update_profile(&self, client_id: i32, profile_update: &ProfileUpdate) -> Result<()> {
+        let mut clients = self.clients();
+        let stored = clients.iter_mut().find(|stored| stored.client.client_id == client_id)
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        if let Some(display_name) = &profile_update.display_name {
+            stored.profile.display_name = display_name.clone();
+        }
+
+        if let Some(avatar_url) = &profile_update.avatar_url {
+            stored.profile.avatar_url = avatar_url.clone();
+        }
+
+        if let Some(preferred_language) = &profile_update.preferred_language {
+            stored.profile.preferred_language = preferred_language.clone();
+        }
+
+        if let Some(favorite_genres) = &profile_update.favorite_genres {
+            stored.profile.favorite_genres = favorite_genres.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_client(&self, client_id: i32) -> Result<()> {
+        self.sessions.lock().expect("in memory repository poisoned").retain(|(owner_id, _)| *owner_id != client_id);
+        self.api_keys().retain(|(owner_id, _)| *owner_id != client_id);
+        self.identities.lock().expect("in memory repository poisoned").retain(|(owner_id, _)| *owner_id != client_id);
+
+        let mut clients = self.clients();
+
+        for stored in clients.iter_mut().filter(|stored| stored.parent_client_id == Some(client_id)) {
+            stored.parent_client_id = None;
+            stored.parental_age_limit = None;
+        }
+
+        let stored = clients.iter_mut().find(|stored| stored.client.client_id == client_id)
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        stored.client = Client {
+            client_id,
+            client_name: format!("deleted-{}", client_id),
+            encrypted_password: String::new(),
+            birth_date: None,
+            session_version: stored.client.session_version + 1,
+            email: None,
+            email_verified_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            failed_login_count: 0,
+            locked_until: None
+        };
+        stored.profile.display_name = None;
+        stored.profile.avatar_url = None;
+        stored.profile.preferred_language = None;
+        stored.profile.favorite_genres.clear();
+        stored.backup_codes.clear();
+        stored.deleted = true;
+
+        Ok(())
+    }
+
+    // the movie tables aren't part of this repository
+    async fn get_personal_data(&self, client_id: i32) -> Result<PersonalData> {
+        let api_keys = self.get_api_keys(client_id).await?;
+
+        let (parent_client_id, parental_age_limit) = self.clients().iter()
+            .find(|stored| stored.client.client_id == client_id)
+            .map(|stored| (stored.parent_client_id, stored.parental_age_limit))
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        Ok(PersonalData {
+            parent_client_id,
+            parental_age_limit,
+            ratings: Vec::new(),
+            favorites: Vec::new(),
+            watched: Vec::new(),
+            sessions: self.sessions.lock().expect("in memory repository poisoned").iter()
+                .filter(|(owner_id, _)| *owner_id == client_id)
+                .map(|(_, session)| session.clone())
+                .collect(),
+            api_keys,
+            linked_identities: self.identities.lock().expect("in memory repository poisoned").iter()
+                .filter(|(owner_id, _)| *owner_id == client_id)
+                .map(|(_, identity)| identity.clone())
+                .collect(),
+            oauth_apps: self.oauth_apps.lock().expect("in memory repository poisoned").iter()
+                .filter(|(oauth_app, _)| oauth_app.owner_id == client_id)
+                .map(|(oauth_app, created_at)| OwnedOAuthApp {
+                    public_id: oauth_app.public_id.clone(),
+                    name: oauth_app.name.clone(),
+                    redirect_uris: oauth_app.redirect_uris.clone(),
+                    scopes: oauth_app.scopes.clone(),
+                    created_at: *created_at
+                })
+                .collect()
+        })
+    }
+
+    async fn get_stale_unverified_clients(&self, inactive_since: DateTime<Utc>) -> Result<Vec<i32>> {
+        let sessions = self.sessions.lock().expect("in memory repository poisoned");
+        let api_keys = self.api_keys();
+
+        Ok(self.clients().iter()
+            .filter(|stored| stored.client.email.is_some() && stored.client.email_verified_at.is_none() && !stored.deleted
+                && stored.profile.created_at < inactive_since)
+            .filter(|stored| !sessions.iter()
+                .any(|(owner_id, session)| *owner_id == stored.client.client_id && session.last_seen_at >= inactive_since))
+            .filter(|stored| !api_keys.iter()
+                .any(|(owner_id, api_key)| *owner_id == stored.client.client_id
+                    && api_key.last_used_at.is_some_and(|last_used_at| last_used_at >= inactive_since)))
+            .map(|stored| stored.client.client_id)
+            .collect())
+    }
+
+    async fn delete_sessions_ended_before(&self, ended_before: DateTime<Utc>) -> Result<u64> {
+        let mut sessions = self.sessions.lock().expect("in memory repository poisoned");
+        let count = sessions.len();
+
+        sessions.retain(|(_, session)| session.expires_at >= ended_before
+            && session.revoked_at.is_none_or(|revoked_at| revoked_at >= ended_before));
+
+        Ok((count - sessions.len()) as u64)
+    }
+
+    async fn set_encrypted_password(&self, client_id: i32, verified_password: &str, encrypted_password: &str) -> Result<()> {
+        if let Some(stored) = self.clients().iter_mut()
+            .find(|stored| stored.client.client_id == client_id && stored.client.encrypted_password == verified_password) {
+            stored.client.encrypted_password = encrypted_password.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn update_password(&self, client_id: i32, encrypted_password: &str) -> Result<i32> {
+        let mut clients = self.clients();
+        let stored = clients.iter_mut().find(|stored| stored.client.client_id == client_id)
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        stored.client.encrypted_password = encrypted_password.to_string();
+        stored.client.session_version += 1;
+
+        for (_, session) in self.sessions.lock().expect("in memory repository poisoned").iter_mut()
+            .filter(|(owner_id, session)| *owner_id == client_id && session.revoked_at.is_none()) {
+            session.revoked_at = Some(Utc::now());
+        }
+
+        for token in self.reset_tokens.lock().expect("in memory repository poisoned").iter_mut()
+            .filter(|token| token.client_id == client_id) {
+            token.used = true;
+        }
+
+        Ok(stored.client.session_version)
+    }
+
+    async fn add_password_reset_token(&self, client_id: i32, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        self.reset_tokens.lock().expect("in memory repository poisoned").push(StoredResetToken {
+            token_hash: token_hash.to_string(),
+            client_id,
+            expires_at,
+            used: false,
+        });
+
+        Ok(())
+    }
+
+    async fn use_password_reset_token(&self, token_hash: &str) -> Result<Option<i32>> {
+        let mut reset_tokens = self.reset_tokens.lock().expect("in memory repository poisoned");
+
+        let token = reset_tokens.iter_mut()
+            .find(|token| token.token_hash == token_hash && !token.used && token.expires_at > Utc::now());
+
+        Ok(token.map(|token| {
+            token.used = true;
+            token.client_id
+        }))
+    }
+
+    async fn set_email(&self, client_id: i32, email: &str) -> Result<()> {
+        let mut clients = self.clients();
+
+        if email_verified_elsewhere(&clients, email, client_id) {
+            return Err(UserServiceError::EmailTaken);
+        }
+
+        if let Some(stored) = clients.iter_mut().find(|stored| stored.client.client_id == client_id) {
+            stored.client.email = Some(email.to_string());
+            stored.client.email_verified_at = None;
+        }
+
+        Ok(())
+    }
+
+    async fn add_email_verification_token(&self, client_id: i32, email: &str, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        self.verification_tokens.lock().expect("in memory repository poisoned").push(StoredVerificationToken {
+            token_hash: token_hash.to_string(),
+            client_id,
+            email: email.to_string(),
+            expires_at,
+            used: false,
+        });
+
+        Ok(())
+    }
+
+    async fn verify_email(&self, token_hash: &str) -> Result<bool> {
+        let mut verification_tokens = self.verification_tokens.lock().expect("in memory repository poisoned");
+
+        let Some(token) = verification_tokens.iter_mut()
+            .find(|token| token.token_hash == token_hash && !token.used && token.expires_at > Utc::now()) else {
+            return Ok(false);
+        };
+
+        let mut clients = self.clients();
+
+        let index = clients.iter()
+            .position(|stored| stored.client.client_id == token.client_id && stored.client.email.as_deref() == Some(token.email.as_str()));
+
+        if index.is_some() && email_verified_elsewhere(&clients, &token.email, token.client_id) {
+            return Err(UserServiceError::EmailTaken);
+        }
+
+        token.used = true;
+
+        Ok(index.map(|index| clients[index].client.email_verified_at = Some(Utc::now())).is_some())
+    }
+
+    async fn record_failed_login(&self, client_id: i32, forget_before: DateTime<Utc>) -> Result<i32> {
+        let mut clients = self.clients();
+        let stored = clients.iter_mut()
+            .find(|stored| stored.client.client_id == client_id)
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        stored.client.failed_login_count = match stored.last_failed_login {
+            Some(last_failed_login) if last_failed_login >= forget_before => stored.client.failed_login_count + 1,
+            _ => 1,
+        };
+        stored.last_failed_login = Some(Utc::now());
+
+        Ok(stored.client.failed_login_count)
+    }
+
+    async fn lock_client(&self, client_id: i32, locked_until: DateTime<Utc>) -> Result<()> {
+        if let Some(stored) = self.clients().iter_mut().find(|stored| stored.client.client_id == client_id) {
+            stored.client.locked_until = Some(locked_until);
+        }
+
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, client_id: i32) -> Result<()> {
+        if let Some(stored) = self.clients().iter_mut().find(|stored| stored.client.client_id == client_id) {
+            stored.client.failed_login_count = 0;
+            stored.client.locked_until = None;
+        }
+
+        Ok(())
+    }
+
+    async fn set_totp_secret(&self, client_id: i32, totp_secret: &str) -> Result<()> {
+        if let Some(stored) = self.clients().iter_mut()
+            .find(|stored| stored.client.client_id == client_id && stored.client.totp_enabled_at.is_none()) {
+            stored.client.totp_secret = Some(totp_secret.to_string());
+        }
+
+        Ok(())
+    }
+
+    async fn enable_totp(&self, client_id: i32, backup_code_hashes: &[String]) -> Result<()> {
+        if let Some(stored) = self.clients().iter_mut().find(|stored| stored.client.client_id == client_id) {
+            stored.client.totp_enabled_at = Some(Utc::now());
+            stored.backup_codes = backup_code_hashes.iter().map(|code_hash| (code_hash.clone(), false)).collect();
+        }
+
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, client_id: i32, step: i64) -> Result<bool> {
+        let mut clients = self.clients();
+
+        let Some(stored) = clients.iter_mut().find(|stored| stored.client.client_id == client_id) else {
+            return Ok(false);
+        };
+
+        if stored.totp_last_step.is_some_and(|last_step| last_step >= step) {
+            return Ok(false);
+        }
+
+        stored.totp_last_step = Some(step);
+
+        Ok(true)
+    }
+
+    async fn use_backup_code(&self, client_id: i32, code_hash: &str) -> Result<bool> {
+        let mut clients = self.clients();
+
+        let backup_code = clients.iter_mut()
+            .find(|stored| stored.client.client_id == client_id)
+            .and_then(|stored| stored.backup_codes.iter_mut().find(|(hash, used)| hash == code_hash && !used));
+
+        Ok(backup_code.map(|(_, used)| *used = true).is_some())
+    }
+
+    async fn add_api_key(&self, client_id: i32, name: &str, key_prefix: &str, _key_hash: &str, scopes: &[String],
+        expires_at: Option<DateTime<Utc>>) -> Result<ApiKey> {
+        let mut api_keys = self.api_keys();
+
+        let api_key = ApiKey {
+            api_key_id: api_keys.len() as i32 + 1,
+            name: name.to_string(),
+            key_prefix: key_prefix.to_string(),
+            scopes: scopes.to_vec(),
+            created_at: Utc::now(),
+            last_used_at: None,
+            expires_at,
+            revoked_at: None,
+        };
+
+        api_keys.push((client_id, api_key.clone()));
+
+        Ok(api_key)
+    }
+
+    async fn get_api_keys(&self, client_id: i32) -> Result<Vec<ApiKey>> {
+        Ok(self.api_keys().iter()
+            .filter(|(owner_id, _)| *owner_id == client_id)
+            .map(|(_, api_key)| api_key.clone())
+            .rev()
+            .collect())
+    }
+
+    async fn revoke_api_key(&self, client_id: i32, api_key_id: i32) -> Result<bool> {
+        let mut api_keys = self.api_keys();
+
+        let api_key = api_keys.iter_mut()
+            .find(|(owner_id, api_key)| *owner_id == client_id && api_key.api_key_id == api_key_id && api_key.revoked_at.is_none());
+
+        Ok(api_key.map(|(_, api_key)| api_key.revoked_at = Some(Utc::now())).is_some())
+    }
+
+    async fn add_session(&self, client_id: i32, origin: &SessionOrigin, expires_at: DateTime<Utc>) -> Result<i32> {
+        let mut sessions = self.sessions.lock().expect("in memory repository poisoned");
+        let session_id = sessions.iter().map(|(_, session)| session.session_id).max().unwrap_or(0) + 1;
+
+        sessions.push((client_id, ClientSession {
+            session_id,
+            user_agent: origin.user_agent.clone(),
+            ip_address: origin.ip_address.clone(),
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            expires_at,
+            revoked_at: None,
+            current: false
+        }));
+
+        Ok(session_id)
+    }
+
+    async fn get_active_sessions(&self, client_id: i32) -> Result<Vec<ClientSession>> {
+        Ok(self.sessions.lock().expect("in memory repository poisoned").iter()
+            .filter(|(owner_id, session)| *owner_id == client_id && session.revoked_at.is_none() && session.expires_at > Utc::now())
+            .rev()
+            .map(|(_, session)| session.clone())
+            .collect())
+    }
+
+    async fn revoke_session(&self, client_id: i32, session_id: i32) -> Result<bool> {
+        let mut sessions = self.sessions.lock().expect("in memory repository poisoned");
+
+        let session = sessions.iter_mut()
+            .find(|(owner_id, session)| *owner_id == client_id && session.session_id == session_id && session.revoked_at.is_none());
+
+        Ok(session.map(|(_, session)| session.revoked_at = Some(Utc::now())).is_some())
+    }
+
+    async fn add_oauth_app(&self, owner_id: i32, public_id: &str, secret_hash: Option<&str>, name: &str, redirect_uris: &[String],
+        scopes: &[String]) -> Result<OAuthApp> {
+        let mut oauth_apps = self.oauth_apps.lock().expect("in memory repository poisoned");
+
+        let oauth_app = OAuthApp {
+            app_id: oauth_apps.len() as i32 + 1,
+            public_id: public_id.to_string(),
+            secret_hash: secret_hash.map(str::to_string),
+            owner_id,
+            name: name.to_string(),
+            redirect_uris: redirect_uris.to_vec(),
+            scopes: scopes.to_vec(),
+        };
+
+        oauth_apps.push((oauth_app.clone(), Utc::now()));
+
+        Ok(oauth_app)
+    }
+
+    async fn get_oauth_app(&self, public_id: &str) -> Result<OAuthApp> {
+        self.oauth_apps.lock().expect("in memory repository poisoned").iter()
+            .find(|(oauth_app, _)| oauth_app.public_id == public_id)
+            .map(|(oauth_app, _)| oauth_app.clone())
+            .ok_or(UserServiceError::OAuthAppNotFound)
+    }
+
+    async fn add_authorization_code(&self, code_hash: &str, authorization_code: &AuthorizationCode) -> Result<()> {
+        self.authorization_codes.lock().expect("in memory repository poisoned")
+            .push((code_hash.to_string(), authorization_code.clone(), false));
+
+        Ok(())
+    }
+
+    async fn use_authorization_code(&self, code_hash: &str) -> Result<Option<AuthorizationCode>> {
+        let mut authorization_codes = self.authorization_codes.lock().expect("in memory repository poisoned");
+
+        let stored = authorization_codes.iter_mut()
+            .find(|(hash, code, used)| hash == code_hash && !used && code.expires_at > Utc::now());
+
+        Ok(stored.map(|(_, code, used)| {
+            *used = true;
+            code.clone()
+        }))
+    }
+
+    async fn add_oidc_login_state(&self, state_hash: &str, login_state: &OidcLoginState) -> Result<()> {
+        self.oidc_login_states.lock().expect("in memory repository poisoned")
+            .push((state_hash.to_string(), login_state.clone(), false));
+
+        Ok(())
+    }
+
+    async fn use_oidc_login_state(&self, state_hash: &str) -> Result<Option<OidcLoginState>> {
+        let mut login_states = self.oidc_login_states.lock().expect("in memory repository poisoned");
+
+        let stored = login_states.iter_mut()
+            .find(|(hash, login_state, used)| hash == state_hash && !used && login_state.expires_at > Utc::now());
+
+        Ok(stored.map(|(_, login_state, used)| {
+            *used = true;
+            login_state.clone()
+        }))
+    }
+
+    async fn get_client_by_identity(&self, issuer: &str, subject: &str) -> Result<Client> {
+        let client_id = self.identities.lock().expect("in memory repository poisoned").iter()
+            .find(|(_, identity)| identity.issuer == issuer && identity.subject == subject)
+            .map(|(client_id, _)| *client_id)
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        self.get_client_by_id(client_id).await
+    }
+
+    async fn get_client_by_verified_email(&self, email: &str) -> Result<Client> {
+        self.clients().iter()
+            .find(|stored| stored.client.email.as_deref() == Some(email) && stored.client.email_verified_at.is_some())
+            .map(|stored| stored.client.clone())
+            .ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn link_identity(&self, client_id: i32, issuer: &str, subject: &str) -> Result<()> {
+        let mut identities = self.identities.lock().expect("in memory repository poisoned");
+
+        if !identities.iter().any(|(_, identity)| identity.issuer == issuer && identity.subject == subject) {
+            identities.push((client_id, LinkedIdentity { issuer: issuer.to_string(), subject: subject.to_string(), linked_at: Utc::now() }));
+        }
+
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, client_id: i32) -> Result<()> {
+        let mut clients = self.clients();
+
+        let Some(index) = clients.iter().position(|stored| stored.client.client_id == client_id && stored.client.email.is_some()) else {
+            return Ok(());
+        };
+
+        if email_verified_elsewhere(&clients, clients[index].client.email.as_deref().unwrap_or_default(), client_id) {
+            return Err(UserServiceError::EmailTaken);
+        }
+
+        clients[index].client.email_verified_at = Some(Utc::now());
+
+        Ok(())
+    }
+
+    async fn set_parent(&self, child_id: i32, parent_id: i32, age_limit: Option<i32>) -> Result<bool> {
+        let mut clients = self.clients();
+
+        let child = clients.iter_mut()
+            .find(|stored| stored.client.client_id == child_id && stored.parent_client_id.is_none_or(|linked_id| linked_id == parent_id));
+
+        Ok(child.map(|child| {
+            child.parent_client_id = Some(parent_id);
+            child.parental_age_limit = age_limit;
+        }).is_some())
+    }
+
+    async fn set_parental_age_limit(&self, parent_name: &str, child_name: &str, age_limit: Option<i32>) -> Result<bool> {
+        let mut clients = self.clients();
+
+        let Some(parent_id) = clients.iter()
+            .find(|stored| stored.client.client_name == parent_name)
+            .map(|stored| stored.client.client_id) else {
+            return Ok(false);
+        };
+
+        let child = clients.iter_mut()
+            .find(|stored| stored.client.client_name == child_name && stored.parent_client_id == Some(parent_id));
+
+        match child {
+            Some(child) => {
+                child.parental_age_limit = age_limit;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -1,67 +1,779 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::{get, post}, Json, Router};
-use serde::Serialize;
-use serde_json::json;
-use service::ClientService;
+use std::{net::{IpAddr, SocketAddr}, sync::Arc, time::Instant};
+
+use axum::{extract::{ConnectInfo, Path, Query, State}, http::{header, HeaderMap, StatusCode}, middleware, response::{AppendHeaders, IntoResponse, Response},
+    routing::{delete, get, post, put}, Extension, Form, Json, Router};
+use serde::{Deserialize, Serialize};
+use domain::{ProfileUpdate, SessionOrigin};
+use err::UserServiceError;
+use login_throttle::LoginThrottle;
+use mailer::Mailer;
+use oidc::{OidcCallback, OidcConfig, OidcLoginService, OidcProvider};
+use oauth::{AuthorizationDecision, AuthorizationRequest, IntrospectionRequest, OAuthAppRegistration, OAuthService, TokenRequest};
+use password_hasher::{Argon2Hasher, PasswordHasher};
+use repository::ClientRepository;
+use service::{AccountDeletion, ApiKeyRequest, ChildLinkInfo, ClientService, EmailChange, EmailVerification, LoginOutcome, PasswordChange, PasswordReset,
+    PasswordResetRequest, TotpConfirmation, TwoFactorLogin};
 use sqlx::PgPool;
-use token_provider::TokenProvider;
+use token_provider::{TokenProvider, ACCESS_TOKEN_SECONDS};
 use tracing::error;
 use user_database::ClientDb;
+
+use crate::auth_middleware::{self, AuthState, ClientInfo, CSRF_COOKIE, SESSION_COOKIE};
+use crate::client_address::TrustedProxies;
+use crate::rate_limit::{self, RateLimiter};
+
 mod domain;
+mod login_throttle;
+mod mailer;
+#[cfg(test)]
+mod memory_repository;
+mod oauth;
+mod oidc;
+mod password_hasher;
+mod repository;
+mod retention;
 mod user_database;
 mod service;
 mod err;
 pub mod token_provider;
+mod two_factor;
+
+// ties an OpenID Connect login to the browser that started it
+const OIDC_BINDING_COOKIE: &str = "movies_oidc_binding";
 
 #[derive(Debug, Serialize)]
 struct AuthResponse {
     token: String
 }
 
+// cookie sessions only get the CSRF token, the access token stays out of reach of scripts
+#[derive(Debug, Serialize)]
+struct CsrfResponse {
+    csrf_token: String
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SessionKind {
+    #[default]
+    Bearer,
+    Cookie
+}
+
+// `?session=cookie` on the login routes opts in to a browser session
+#[derive(Debug, Deserialize)]
+struct SessionQuery {
+    #[serde(default)]
+    session: SessionKind
+}
+
+// RFC 6749 error body of the token and introspection endpoints
+#[derive(Debug, Serialize)]
+struct OAuthErrorResponse {
+    error: &'static str
+}
+
+#[derive(Debug, Serialize)]
+struct BackupCodesResponse {
+    backup_codes: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct AgeLimitInfo {
+    age_limit: Option<i32>
+}
+
 #[derive(Clone)]
 struct UserServiceState {
-    db_pool: PgPool,
+    client_repository: Arc<dyn ClientRepository>,
+    password_hasher: Arc<dyn PasswordHasher>,
+    mailer: Arc<dyn Mailer>,
+    login_throttle: Arc<LoginThrottle>,
+    trusted_proxies: Arc<TrustedProxies>,
+    // only when an OpenID Connect provider is configured
+    oidc_provider: Option<Arc<OidcProvider>>,
     token_key: String
 }
 
-pub fn get_router(db_pool: PgPool, token_key: String) -> Router {
-    Router::new()
+impl UserServiceState {
+    fn client_service(self) -> ClientService {
+        ClientService::new(self.client_repository, self.password_hasher, self.mailer, TokenProvider::new(self.token_key))
+    }
+
+    fn oauth_service(self) -> OAuthService {
+        OAuthService::new(self.client_repository, TokenProvider::new(self.token_key))
+    }
+
+    fn oidc_login_service(self) -> Option<OidcLoginService> {
+        let provider = self.oidc_provider?;
+
+        Some(OidcLoginService::new(self.client_repository, self.password_hasher, TokenProvider::new(self.token_key), provider))
+    }
+}
+
+pub fn get_router(db_pool: PgPool, token_key: String, rate_limiter: RateLimiter) -> Router {
+    let auth_state = AuthState {
+        token_key: token_key.clone(),
+        db_pool: db_pool.clone(),
+        allow_scoped_access: false
+    };
+
+    // linking children is limited to verified accounts
+    let verified_router = Router::new()
+        .route("/children", post(link_child))
+        .route("/children/:childName", put(set_child_age_limit))
+        .route_layer(middleware::from_fn(auth_middleware::require_verified_email));
+
+    // erasure requests made outside of the API
+    let admin_router = Router::new()
+        .route("/admin/clients/:clientName/anonymize", post(anonymize_client))
+        .route_layer(middleware::from_fn(auth_middleware::require_admin));
+
+    let authenticated_router = Router::new()
+        .route("/logout", post(logout))
+        .route("/me", get(get_profile).patch(update_profile).delete(delete_account))
+        .route("/me/export", get(export_personal_data))
+        .route("/password", put(change_password))
+        .route("/email", put(set_email))
+        .route("/email/verify/resend", post(resend_email_verification))
+        .route("/2fa/enroll", post(enroll_totp))
+        .route("/2fa/confirm", post(confirm_totp))
+        .route("/api-keys", post(create_api_key).get(list_api_keys))
+        .route("/api-keys/:apiKeyId", delete(revoke_api_key))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:sessionId", delete(revoke_session))
+        .route("/oauth/apps", post(register_oauth_app))
+        .route("/oauth/authorize", get(oauth_consent_prompt).post(oauth_authorize))
+        .merge(verified_router)
+        .merge(admin_router)
+        .route_layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::rate_limit_middleware))
+        .route_layer(middleware::from_fn_with_state(auth_state, auth_middleware::auth_middleware));
+
+    let mailer = mailer::mailer_from_env().expect("valid mail configuration");
+
+    // layered separately so authenticated routes aren't limited per address too
+    let public_router = Router::new()
         .route("/", get(health_check))
         .route("/register", post(register_client))
         .route("/login", post(login_client))
+        .route("/login/2fa", post(complete_two_factor_login))
+        .route("/password/reset", post(request_password_reset))
+        .route("/password/reset/confirm", post(reset_password))
+        .route("/email/verify", post(verify_email))
+        .route("/oauth/token", post(oauth_token))
+        .route("/oauth/introspect", post(oauth_introspect))
+        .route("/oidc/login", get(start_oidc_login))
+        .route("/oidc/callback", post(complete_oidc_login))
+        .route_layer(middleware::from_fn_with_state(rate_limiter, rate_limit::rate_limit_middleware));
+
+    let client_repository: Arc<dyn ClientRepository> = Arc::new(ClientDb::new(db_pool));
+    retention::spawn_purge_job(client_repository.clone(), retention::RetentionPolicy::from_env());
+
+    Router::new()
+        .merge(public_router)
+        .merge(authenticated_router)
         .with_state(UserServiceState {
-            db_pool,
+            client_repository,
+            password_hasher: Arc::new(Argon2Hasher::from_env()),
+            mailer,
+            login_throttle: Arc::new(LoginThrottle::default()),
+            trusted_proxies: Arc::new(TrustedProxies::from_env()),
+            oidc_provider: OidcConfig::from_env().map(|config| Arc::new(OidcProvider::new(config))),
             token_key
         })
 }
 
-async fn login_client(State(state): State<UserServiceState>, Json(client_info): Json<service::ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
-    let client_db = ClientDb::new(state.db_pool);
-    let token_provider = TokenProvider::new(state.token_key);
+fn too_many_requests(retry_after_seconds: u64) -> Response {
+    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after_seconds.to_string())]).into_response()
+}
+
+fn throttled(login_throttle: &LoginThrottle, client_ip: IpAddr) -> Option<Response> {
+    login_throttle.retry_after(client_ip, Instant::now())
+        .map(|retry_after| too_many_requests(retry_after.as_secs_f64().ceil() as u64))
+}
+
+// the user agent is cut to the length stored
+fn session_origin(headers: &HeaderMap, client_ip: IpAddr) -> SessionOrigin {
+    let user_agent = headers.get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .map(|user_agent| user_agent.chars().take(255).collect());
+
+    SessionOrigin { user_agent, ip_address: Some(client_ip.to_string()) }
+}
+
+fn session_cookies(token: &str, csrf_token: &str, max_age: i64) -> AppendHeaders<[(header::HeaderName, String); 2]> {
+    AppendHeaders([
+        (header::SET_COOKIE, format!("{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Strict", SESSION_COOKIE, token, max_age)),
+        // readable by the frontend, it sends it back in the CSRF header
+        (header::SET_COOKIE, format!("{}={}; Path=/; Max-Age={}; Secure; SameSite=Strict", CSRF_COOKIE, csrf_token, max_age)),
+    ])
+}
+
+// HttpOnly, the frontend never needs it, the browser sends it back with the callback
+fn oidc_binding_cookie(binding: &str, max_age: i64) -> AppendHeaders<[(header::HeaderName, String); 1]> {
+    AppendHeaders([
+        (header::SET_COOKIE, format!("{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Strict", OIDC_BINDING_COOKIE, binding, max_age)),
+    ])
+}
+
+fn token_response(token: String, session: SessionKind) -> Response {
+    match session {
+        SessionKind::Bearer => (StatusCode::OK, Json(AuthResponse { token })).into_response(),
+        SessionKind::Cookie => {
+            let (csrf_token, _) = service::new_token();
+
+            (StatusCode::OK, session_cookies(&token, &csrf_token, ACCESS_TOKEN_SECONDS), Json(CsrfResponse { csrf_token })).into_response()
+        }
+    }
+}
+
+// the token stops working with its session, the cookies are only cleared for tidiness
+async fn logout(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    // scoped tokens have no session, they expire on their own
+    if let Some(session_id) = client_info.session_id {
+        match service.revoke_session(&client_info.client_name, session_id).await {
+            Ok(()) | Err(UserServiceError::SessionNotFound) => {}
+            Err(err) => {
+                error!("Error logging out the client: {}", err);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    Ok((StatusCode::NO_CONTENT, session_cookies("", "", 0)))
+}
+
+async fn login_client(State(state): State<UserServiceState>, ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Query(session_query): Query<SessionQuery>, headers: HeaderMap, Json(client_info): Json<service::ClientInfo>) -> Result<Response, StatusCode> {
+    let client_ip = state.trusted_proxies.client_ip(&headers, address.ip());
+
+    if let Some(response) = throttled(&state.login_throttle, client_ip) {
+        return Ok(response);
+    }
+
+    let login_throttle = state.login_throttle.clone();
+    let service = state.client_service();
 
-    let service = ClientService::new(client_db, token_provider);
+    let result = service.login_client(client_info, session_origin(&headers, client_ip)).await;
 
-    let token = service.login_client(client_info)
+    if let Err(UserServiceError::InvalidPassword(_)) = result {
+        login_throttle.record_failure(client_ip, Instant::now());
+    }
+
+    let login_outcome = match result {
+        Err(UserServiceError::TooManyLoginAttempts(retry_after)) => return Ok(too_many_requests(retry_after)),
+        result => result.map_err(|err| match err {
+            UserServiceError::InvalidPassword(_) => StatusCode::UNAUTHORIZED,
+            err => {
+                error!("Error logging in the client: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?,
+    };
+
+    match login_outcome {
+        LoginOutcome::Token { token } => Ok(token_response(token, session_query.session)),
+        challenge => Ok((StatusCode::OK, Json(challenge)).into_response()),
+    }
+}
+
+async fn complete_two_factor_login(State(state): State<UserServiceState>, ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Query(session_query): Query<SessionQuery>, headers: HeaderMap, Json(two_factor_login): Json<TwoFactorLogin>) -> Result<Response, StatusCode> {
+    let client_ip = state.trusted_proxies.client_ip(&headers, address.ip());
+
+    if let Some(response) = throttled(&state.login_throttle, client_ip) {
+        return Ok(response);
+    }
+
+    let login_throttle = state.login_throttle.clone();
+    let service = state.client_service();
+
+    let result = service.complete_two_factor_login(two_factor_login, session_origin(&headers, client_ip)).await;
+
+    if let Err(UserServiceError::InvalidChallenge | UserServiceError::InvalidTwoFactorCode) = result {
+        login_throttle.record_failure(client_ip, Instant::now());
+    }
+
+    let token = match result {
+        Err(UserServiceError::TooManyLoginAttempts(retry_after)) => return Ok(too_many_requests(retry_after)),
+        result => result.map_err(|err| match err {
+            UserServiceError::InvalidChallenge | UserServiceError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+            err => {
+                error!("Error completing the two-factor login: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?,
+    };
+
+    Ok(token_response(token, session_query.session))
+}
+
+async fn enroll_totp(State(state): State<UserServiceState>,
+    Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let enrollment = service.enroll_totp(&client_info.client_name)
+        .await.map_err(|err| match err {
+            UserServiceError::TwoFactorAlreadyEnabled => StatusCode::CONFLICT,
+            err => {
+                error!("Error enrolling two-factor authentication: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(enrollment)))
+}
+
+async fn confirm_totp(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(totp_confirmation): Json<TotpConfirmation>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let backup_codes = service.confirm_totp(&client_info.client_name, totp_confirmation)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+            UserServiceError::TwoFactorNotEnrolled => StatusCode::BAD_REQUEST,
+            UserServiceError::TwoFactorAlreadyEnabled => StatusCode::CONFLICT,
+            err => {
+                error!("Error confirming two-factor authentication: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(BackupCodesResponse { backup_codes })))
+}
+
+async fn register_client(State(state): State<UserServiceState>, ConnectInfo(address): ConnectInfo<SocketAddr>, headers: HeaderMap,
+    Json(client_info): Json<service::ClientInfo>)  -> Result<impl IntoResponse, StatusCode> {
+    let client_ip = state.trusted_proxies.client_ip(&headers, address.ip());
+    let service = state.client_service();
+
+    let token = service.register_client(client_info, session_origin(&headers, client_ip))
+        .await.map_err(|err| match err {
+            UserServiceError::ClientNameTaken | UserServiceError::EmailTaken => StatusCode::CONFLICT,
+            UserServiceError::InvalidEmail | UserServiceError::ReservedClientName => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error registering client: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    
+    Ok((StatusCode::OK, Json(AuthResponse { token })))
+}
+
+async fn change_password(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>, Query(session_query): Query<SessionQuery>, headers: HeaderMap,
+    Json(password_change): Json<PasswordChange>) -> Result<Response, StatusCode> {
+    let client_ip = state.trusted_proxies.client_ip(&headers, address.ip());
+    let service = state.client_service();
+
+    let token = service.change_password(&client_info.client_name, password_change, session_origin(&headers, client_ip))
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidPassword(_) => StatusCode::UNAUTHORIZED,
+            err => {
+                error!("Error changing the client password: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(token_response(token, session_query.session))
+}
+
+async fn request_password_reset(State(state): State<UserServiceState>,
+    Json(reset_request): Json<PasswordResetRequest>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.request_password_reset(reset_request)
         .await.map_err(|err| {
-            error!("Error logging in the client: {}", err);
+            error!("Error requesting a password reset: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
-    
-    Ok((StatusCode::OK, Json(json!({ "token": token }))))
+
+    Ok(StatusCode::OK)
+}
+
+async fn reset_password(State(state): State<UserServiceState>, Json(password_reset): Json<PasswordReset>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.reset_password(password_reset)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidResetToken => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error resetting the client password: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
 }
 
-async fn register_client(State(state): State<UserServiceState>, Json(client_info): Json<service::ClientInfo>)  -> Result<impl IntoResponse, StatusCode> {
-    let client_db = ClientDb::new(state.db_pool);
-    let token_provider = TokenProvider::new(state.token_key);
+async fn set_email(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(email_change): Json<EmailChange>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.set_email(&client_info.client_name, email_change)
+        .await.map_err(|err| match err {
+            UserServiceError::EmailTaken => StatusCode::CONFLICT,
+            UserServiceError::InvalidEmail => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error setting the client email: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
 
-    let service = ClientService::new(client_db, token_provider);
+    Ok(StatusCode::OK)
+}
+
+async fn resend_email_verification(State(state): State<UserServiceState>,
+    Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.resend_email_verification(&client_info.client_name)
+        .await.map_err(|err| match err {
+            UserServiceError::MissingEmail => StatusCode::BAD_REQUEST,
+            UserServiceError::EmailAlreadyVerified => StatusCode::CONFLICT,
+            err => {
+                error!("Error resending the email verification: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
 
-    let token = service.register_client(client_info)
+    Ok(StatusCode::OK)
+}
+
+async fn verify_email(State(state): State<UserServiceState>,
+    Json(email_verification): Json<EmailVerification>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.verify_email(email_verification)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidVerificationToken => StatusCode::BAD_REQUEST,
+            UserServiceError::EmailTaken => StatusCode::CONFLICT,
+            err => {
+                error!("Error verifying the client email: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn create_api_key(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(api_key_request): Json<ApiKeyRequest>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let created_api_key = service.create_api_key(&client_info.client_name, api_key_request)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidApiKeyRequest => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error creating the API key: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(created_api_key)))
+}
+
+async fn list_api_keys(State(state): State<UserServiceState>,
+    Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let api_keys = service.list_api_keys(&client_info.client_name)
         .await.map_err(|err| {
-            error!("Error registering client: {}", err);
+            error!("Error listing the API keys: {}", err);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
-    
-    Ok((StatusCode::OK, Json(json!({ "token": token }))))
+
+    Ok((StatusCode::OK, Json(api_keys)))
+}
+
+async fn revoke_api_key(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(api_key_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.revoke_api_key(&client_info.client_name, api_key_id)
+        .await.map_err(|err| match err {
+            UserServiceError::ApiKeyNotFound => StatusCode::NOT_FOUND,
+            err => {
+                error!("Error revoking the API key: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn get_profile(State(state): State<UserServiceState>,
+    Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let profile = service.get_profile(&client_info.client_name)
+        .await.map_err(|err| {
+            error!("Error getting the client profile: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+async fn update_profile(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(profile_update): Json<ProfileUpdate>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let profile = service.update_profile(&client_info.client_name, profile_update)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidProfile => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error updating the client profile: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+async fn delete_account(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(account_deletion): Json<AccountDeletion>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.delete_account(&client_info.client_name, account_deletion)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidPassword(_) => StatusCode::UNAUTHORIZED,
+            err => {
+                error!("Error deleting the client account: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    // a browser session ends with the account
+    Ok((StatusCode::OK, session_cookies("", "", 0)))
+}
+
+async fn export_personal_data(State(state): State<UserServiceState>,
+    Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let export = service.export_personal_data(&client_info.client_name)
+        .await.map_err(|err| {
+            error!("Error exporting the personal data: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let headers = [
+        (header::CONTENT_DISPOSITION, "attachment; filename=\"personal-data.json\""),
+        (header::CACHE_CONTROL, "no-store"),
+    ];
+
+    Ok((StatusCode::OK, headers, Json(export)))
+}
+
+// admin
+async fn anonymize_client(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(client_name): Path<String>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.anonymize_client(&client_info.client_name, &client_name)
+        .await.map_err(|err| match err {
+            UserServiceError::ClientNotFound => StatusCode::NOT_FOUND,
+            err => {
+                error!("Error anonymizing the client: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn list_sessions(State(state): State<UserServiceState>,
+    Extension(client_info): Extension<ClientInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    let sessions = service.list_sessions(&client_info.client_name, client_info.session_id)
+        .await.map_err(|err| {
+            error!("Error listing the sessions: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((StatusCode::OK, Json(sessions)))
+}
+
+async fn revoke_session(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(session_id): Path<i32>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.revoke_session(&client_info.client_name, session_id)
+        .await.map_err(|err| match err {
+            UserServiceError::SessionNotFound => StatusCode::NOT_FOUND,
+            err => {
+                error!("Error revoking the session: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn register_oauth_app(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(registration): Json<OAuthAppRegistration>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.oauth_service();
+
+    let oauth_app = service.register_app(&client_info.client_name, registration)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidOAuthApp => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error registering the OAuth application: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(oauth_app)))
+}
+
+async fn oauth_consent_prompt(State(state): State<UserServiceState>,
+    Query(authorization_request): Query<AuthorizationRequest>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.oauth_service();
+
+    let consent_prompt = service.consent_prompt(&authorization_request)
+        .await.map_err(|err| match err {
+            UserServiceError::OAuthAppNotFound | UserServiceError::InvalidOAuthRequest | UserServiceError::InvalidScope => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error preparing the OAuth consent: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(consent_prompt)))
+}
+
+async fn oauth_authorize(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(decision): Json<AuthorizationDecision>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.oauth_service();
+
+    let redirect = service.authorize(&client_info.client_name, decision)
+        .await.map_err(|err| match err {
+            UserServiceError::OAuthAppNotFound | UserServiceError::InvalidOAuthRequest | UserServiceError::InvalidScope => StatusCode::BAD_REQUEST,
+            err => {
+                error!("Error authorizing the OAuth application: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok((StatusCode::OK, Json(redirect)))
+}
+
+fn oauth_error(err: UserServiceError) -> Response {
+    let (status, error) = match err {
+        UserServiceError::InvalidOAuthClient => (StatusCode::UNAUTHORIZED, "invalid_client"),
+        UserServiceError::InvalidGrant => (StatusCode::BAD_REQUEST, "invalid_grant"),
+        UserServiceError::InvalidScope => (StatusCode::BAD_REQUEST, "invalid_scope"),
+        UserServiceError::UnsupportedGrantType => (StatusCode::BAD_REQUEST, "unsupported_grant_type"),
+        UserServiceError::InvalidOAuthRequest => (StatusCode::BAD_REQUEST, "invalid_request"),
+        err => {
+            error!("Error in the OAuth token endpoint: {}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    (status, Json(OAuthErrorResponse { error })).into_response()
+}
+
+async fn oauth_token(State(state): State<UserServiceState>, Form(token_request): Form<TokenRequest>) -> Result<impl IntoResponse, Response> {
+    let service = state.oauth_service();
+
+    let token = service.issue_token(token_request)
+        .await.map_err(oauth_error)?;
+
+    Ok((StatusCode::OK, [(header::CACHE_CONTROL, "no-store")], Json(token)))
+}
+
+async fn oauth_introspect(State(state): State<UserServiceState>,
+    Form(introspection_request): Form<IntrospectionRequest>) -> Result<impl IntoResponse, Response> {
+    let service = state.oauth_service();
+
+    let introspection = service.introspect(introspection_request)
+        .await.map_err(oauth_error)?;
+
+    Ok((StatusCode::OK, Json(introspection)))
+}
+
+async fn start_oidc_login(State(state): State<UserServiceState>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.oidc_login_service().ok_or(StatusCode::NOT_FOUND)?;
+
+    let (login_start, binding) = service.start_login()
+        .await.map_err(|err| {
+            error!("Error starting an OpenID Connect login: {}", err);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    Ok((StatusCode::OK, [(header::CACHE_CONTROL, "no-store")], oidc_binding_cookie(&binding, oidc::LOGIN_STATE_MINUTES * 60),
+        Json(login_start)))
+}
+
+async fn complete_oidc_login(State(state): State<UserServiceState>, ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Query(session_query): Query<SessionQuery>, headers: HeaderMap, Json(callback): Json<OidcCallback>) -> Result<Response, StatusCode> {
+    let client_ip = state.trusted_proxies.client_ip(&headers, address.ip());
+    let service = state.oidc_login_service().ok_or(StatusCode::NOT_FOUND)?;
+
+    let binding = auth_middleware::cookie(&headers, OIDC_BINDING_COOKIE);
+    let result = service.complete_login(callback, binding, session_origin(&headers, client_ip)).await;
+
+    let login_outcome = match result {
+        Err(UserServiceError::TooManyLoginAttempts(retry_after)) => return Ok(too_many_requests(retry_after)),
+        result => result.map_err(|err| match err {
+            UserServiceError::InvalidOidcState | UserServiceError::InvalidIdToken => StatusCode::UNAUTHORIZED,
+            UserServiceError::OidcProviderError | UserServiceError::OidcHttpError(_) => {
+                error!("Error from the OpenID Connect provider: {}", err);
+                StatusCode::BAD_GATEWAY
+            }
+            err => {
+                error!("Error completing an OpenID Connect login: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?,
+    };
+
+    // the binding is single use like the state
+    let response = match login_outcome {
+        LoginOutcome::Token { token } => token_response(token, session_query.session),
+        challenge => (StatusCode::OK, Json(challenge)).into_response(),
+    };
+
+    Ok((oidc_binding_cookie("", 0), response).into_response())
+}
+
+async fn link_child(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Json(child_info): Json<ChildLinkInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.link_child(&client_info.client_name, child_info)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidPassword(_) => StatusCode::UNAUTHORIZED,
+            UserServiceError::InvalidChildAccount | UserServiceError::ClientNotFound => StatusCode::BAD_REQUEST,
+            UserServiceError::ChildAlreadyLinked => StatusCode::CONFLICT,
+            err => {
+                error!("Error linking child account: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn set_child_age_limit(State(state): State<UserServiceState>, Extension(client_info): Extension<ClientInfo>,
+    Path(child_name): Path<String>, Json(age_limit_info): Json<AgeLimitInfo>) -> Result<impl IntoResponse, StatusCode> {
+    let service = state.client_service();
+
+    service.set_child_age_limit(&client_info.client_name, &child_name, age_limit_info.age_limit)
+        .await.map_err(|err| match err {
+            UserServiceError::InvalidChildAccount | UserServiceError::ClientNotFound => StatusCode::NOT_FOUND,
+            err => {
+                error!("Error setting child age limit: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(StatusCode::OK)
 }
 
 async fn health_check() -> &'static str {
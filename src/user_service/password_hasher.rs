@@ -0,0 +1,128 @@
+use std::env;
+
+use argon2::{password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString}, Algorithm, Argon2, Params, Version};
+use rand_core::OsRng;
+use tracing::warn;
+
+use super::err::Result;
+
+pub trait PasswordHasher: Send + Sync {
+    fn hash(&self, password: &str) -> Result<String>;
+
+    // understands every algorithm that was ever used to store a password
+    fn verify(&self, password: &str, hashed_password: &str) -> Result<bool>;
+
+    // whether a hash that just verified should be replaced by a fresh one
+    fn needs_rehash(&self, hashed_password: &str) -> bool;
+}
+
+// argon2id for new hashes, bcrypt hashes from before the switch are still verified
+pub struct Argon2Hasher {
+    params: Params
+}
+
+impl Argon2Hasher {
+    // parameters argon2 refuses fall back to its defaults, which are the same as ours
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+        let params = Params::new(memory_kib, iterations, parallelism, None).unwrap_or_else(|err| {
+            warn!("Invalid argon2 parameters ({} KiB, {} iterations, {} lanes), using the defaults: {}",
+                memory_kib, iterations, parallelism, err);
+            Params::default()
+        });
+
+        Self { params }
+    }
+
+    // defaults follow the OWASP recommendation of 19 MiB, 2 iterations and 1 lane
+    pub fn from_env() -> Self {
+        let env_u32 = |name: &str, default: u32| env::var(name).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default);
+
+        Self::new(
+            env_u32("ARGON2_MEMORY_KIB", 19 * 1024),
+            env_u32("ARGON2_ITERATIONS", 2),
+            env_u32("ARGON2_PARALLELISM", 1)
+        )
+    }
+
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl PasswordHasher for Argon2Hasher {
+    fn hash(&self, password: &str) -> Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+
+        Ok(self.argon2().hash_password(password.as_bytes(), &salt)?.to_string())
+    }
+
+    fn verify(&self, password: &str, hashed_password: &str) -> Result<bool> {
+        if hashed_password.starts_with("$2") {
+            return Ok(bcrypt::verify(password, hashed_password)?);
+        }
+
+        let parsed_hash = PasswordHash::new(hashed_password)?;
+
+        match self.argon2().verify_password(password.as_bytes(), &parsed_hash) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn needs_rehash(&self, hashed_password: &str) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(hashed_password) else {
+            return true;
+        };
+
+        let Ok(params) = Params::try_from(&parsed_hash) else {
+            return true;
+        };
+
+        parsed_hash.algorithm != Algorithm::Argon2id.ident()
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
+// no key stretching at all, only meant to keep the tests fast
+#[cfg(test)]
+pub struct FastHasher;
+
+#[cfg(test)]
+impl PasswordHasher for FastHasher {
+    fn hash(&self, password: &str) -> Result<String> {
+        Ok(format!("fast${}", password))
+    }
+
+    fn verify(&self, password: &str, hashed_password: &str) -> Result<bool> {
+        Ok(hashed_password.strip_prefix("fast$") == Some(password))
+    }
+
+    fn needs_rehash(&self, _hashed_password: &str) -> bool {
+        false
+    }
+}
+
+#[test]
+fn test_argon2_rehash_detection() {
+    let hasher = Argon2Hasher::new(64, 1, 1);
+
+    let hashed_password = hasher.hash("secret").unwrap();
+    assert!(hashed_password.starts_with("$argon2id$"));
+    assert!(hasher.verify("secret", &hashed_password).unwrap());
+    assert!(!hasher.verify("wrong", &hashed_password).unwrap());
+    assert!(!hasher.needs_rehash(&hashed_password));
+
+    assert!(Argon2Hasher::new(128, 1, 1).needs_rehash(&hashed_password));
+
+    let bcrypt_hash = bcrypt::hash("secret", bcrypt::DEFAULT_COST - 8).unwrap();
+    assert!(hasher.verify("secret", &bcrypt_hash).unwrap());
+    assert!(hasher.needs_rehash(&bcrypt_hash));
+
+    // zero iterations is rejected by argon2, the defaults apply
+    assert_eq!(Argon2Hasher::new(64, 0, 1).params.t_cost(), Params::default().t_cost());
+}
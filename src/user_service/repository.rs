@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::domain::{ApiKey, AuthorizationCode, Client, ClientSession, OAuthApp, OidcLoginState, PersonalData, Profile, ProfileUpdate,
+    SessionOrigin};
+use super::err::Result;
+
+// client storage used by `ClientService`, implemented by `ClientDb` on Postgres
+// and by `InMemoryClientRepository` in the tests
+#[async_trait]
+pub trait ClientRepository: Send + Sync {
+    // fails with `ClientNameTaken` when the name is registered or `EmailTaken` when another client verified the email,
+    // returns the new id
+    async fn add_client(&self, client: &Client) -> Result<i32>;
+
+    async fn get_client(&self, client_name: &str) -> Result<Client>;
+
+    async fn get_client_by_id(&self, client_id: i32) -> Result<Client>;
+
+    async fn get_profile(&self, client_id: i32) -> Result<Profile>;
+
+    // fails with `InvalidProfile` for unknown languages or genres
+    async fn update_profile(&self, client_id: i32, profile_update: &ProfileUpdate) -> Result<()>;
+
+    // anonymizes the client and its ratings, everything else tied to it is deleted
+    async fn delete_client(&self, client_id: i32) -> Result<()>;
+
+    async fn get_personal_data(&self, client_id: i32) -> Result<PersonalData>;
+
+    // accounts created before the date with an email they never verified, not used since through a session
+    // nor an API key, admins excluded
+    async fn get_stale_unverified_clients(&self, inactive_since: DateTime<Utc>) -> Result<Vec<i32>>;
+
+    // sessions that ended before the date, returns how many were deleted
+    async fn delete_sessions_ended_before(&self, ended_before: DateTime<Utc>) -> Result<u64>;
+
+    // only replaces the hash that was just verified, a password changed in between is kept
+    async fn set_encrypted_password(&self, client_id: i32, verified_password: &str, encrypted_password: &str) -> Result<()>;
+
+    // also bumps the session version, revokes every session and uses up every outstanding
+    // reset token, returns the new version
+    async fn update_password(&self, client_id: i32, encrypted_password: &str) -> Result<i32>;
+
+    async fn add_password_reset_token(&self, client_id: i32, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()>;
+
+    // marks the token as used, returns its client when it was unused and not expired
+    async fn use_password_reset_token(&self, token_hash: &str) -> Result<Option<i32>>;
+
+    // the new address starts unverified, fails with `EmailTaken` when another client verified it
+    async fn set_email(&self, client_id: i32, email: &str) -> Result<()>;
+
+    async fn add_email_verification_token(&self, client_id: i32, email: &str, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()>;
+
+    // marks the token as used and the email as verified, false when the token is unknown,
+    // used, expired or was sent to an address the client no longer has,
+    // fails with `EmailTaken` when another client verified the address first
+    async fn verify_email(&self, token_hash: &str) -> Result<bool>;
+
+    // returns the new number of consecutive failures, the count starts over when the last one was before `forget_before`
+    async fn record_failed_login(&self, client_id: i32, forget_before: DateTime<Utc>) -> Result<i32>;
+
+    async fn lock_client(&self, client_id: i32, locked_until: DateTime<Utc>) -> Result<()>;
+
+    async fn reset_failed_logins(&self, client_id: i32) -> Result<()>;
+
+    // pending until `enable_totp`, replaces a previous pending secret
+    async fn set_totp_secret(&self, client_id: i32, totp_secret: &str) -> Result<()>;
+
+    // replaces every previous backup code
+    async fn enable_totp(&self, client_id: i32, backup_code_hashes: &[String]) -> Result<()>;
+
+    // false when the step is not newer than the last accepted one
+    async fn use_totp_step(&self, client_id: i32, step: i64) -> Result<bool>;
+
+    async fn use_backup_code(&self, client_id: i32, code_hash: &str) -> Result<bool>;
+
+    async fn add_api_key(&self, client_id: i32, name: &str, key_prefix: &str, key_hash: &str, scopes: &[String],
+        expires_at: Option<DateTime<Utc>>) -> Result<ApiKey>;
+
+    // revoked and expired keys included
+    async fn get_api_keys(&self, client_id: i32) -> Result<Vec<ApiKey>>;
+
+    // false when the client has no such active key
+    async fn revoke_api_key(&self, client_id: i32, api_key_id: i32) -> Result<bool>;
+
+    async fn add_session(&self, client_id: i32, origin: &SessionOrigin, expires_at: DateTime<Utc>) -> Result<i32>;
+
+    // revoked and expired sessions left out, the most recently seen first
+    async fn get_active_sessions(&self, client_id: i32) -> Result<Vec<ClientSession>>;
+
+    // false when the client has no such active session
+    async fn revoke_session(&self, client_id: i32, session_id: i32) -> Result<bool>;
+
+    async fn add_oauth_app(&self, owner_id: i32, public_id: &str, secret_hash: Option<&str>, name: &str, redirect_uris: &[String],
+        scopes: &[String]) -> Result<OAuthApp>;
+
+    async fn get_oauth_app(&self, public_id: &str) -> Result<OAuthApp>;
+
+    async fn add_authorization_code(&self, code_hash: &str, authorization_code: &AuthorizationCode) -> Result<()>;
+
+    // marks the code as used, none when it is unknown, expired or used already
+    async fn use_authorization_code(&self, code_hash: &str) -> Result<Option<AuthorizationCode>>;
+
+    async fn add_oidc_login_state(&self, state_hash: &str, login_state: &OidcLoginState) -> Result<()>;
+
+    // marks the state as used, none when it is unknown, expired or used already
+    async fn use_oidc_login_state(&self, state_hash: &str) -> Result<Option<OidcLoginState>>;
+
+    async fn get_client_by_identity(&self, issuer: &str, subject: &str) -> Result<Client>;
+
+    async fn get_client_by_verified_email(&self, email: &str) -> Result<Client>;
+
+    async fn link_identity(&self, client_id: i32, issuer: &str, subject: &str) -> Result<()>;
+
+    // fails with `EmailTaken` when another client verified the address first
+    async fn mark_email_verified(&self, client_id: i32) -> Result<()>;
+
+    // returns false when the child is already linked to another parent
+    async fn set_parent(&self, child_id: i32, parent_id: i32, age_limit: Option<i32>) -> Result<bool>;
+
+    // returns false when the child is not linked to the given parent
+    async fn set_parental_age_limit(&self, parent_name: &str, child_name: &str, age_limit: Option<i32>) -> Result<bool>;
+}
@@ -0,0 +1,119 @@
+use std::{env, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+
+use super::err::Result;
+use super::repository::ClientRepository;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    // none keeps unverified accounts forever
+    pub unverified_account_days: Option<i64>,
+    pub ended_session_days: i64,
+    pub purge_every: Duration
+}
+
+impl RetentionPolicy {
+    // RETENTION_UNVERIFIED_ACCOUNT_DAYS enables the account purge, ended sessions are kept
+    // RETENTION_SESSION_DAYS, 90 by default
+    pub fn from_env() -> Self {
+        let positive = |name: &str| env::var(name).ok()
+            .and_then(|value| value.parse::<i64>().ok())
+            .filter(|value| *value > 0);
+
+        Self {
+            unverified_account_days: positive("RETENTION_UNVERIFIED_ACCOUNT_DAYS"),
+            ended_session_days: positive("RETENTION_SESSION_DAYS").unwrap_or(90),
+            purge_every: Duration::from_secs(positive("RETENTION_PURGE_SECONDS").unwrap_or(24 * 60 * 60) as u64)
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PurgeReport {
+    pub deleted_accounts: usize,
+    // logged and retried on the next run
+    pub failed_accounts: usize,
+    pub deleted_sessions: u64
+}
+
+// accounts go first, the sessions they are judged by may be old enough to be purged too,
+// an account that can't be deleted keeps neither the others nor the sessions from being purged
+pub async fn purge(client_db: &dyn ClientRepository, policy: &RetentionPolicy, now: DateTime<Utc>) -> Result<PurgeReport> {
+    let mut report = PurgeReport::default();
+
+    if let Some(days) = policy.unverified_account_days {
+        match client_db.get_stale_unverified_clients(now - chrono::Duration::days(days)).await {
+            Ok(client_ids) => for client_id in client_ids {
+                match client_db.delete_client(client_id).await {
+                    Ok(()) => report.deleted_accounts += 1,
+                    Err(err) => {
+                        error!("Error purging the unverified client {}: {}", client_id, err);
+                        report.failed_accounts += 1;
+                    }
+                }
+            },
+            Err(err) => error!("Error looking up the unverified clients to purge: {}", err),
+        }
+    }
+
+    report.deleted_sessions = client_db.delete_sessions_ended_before(now - chrono::Duration::days(policy.ended_session_days)).await?;
+
+    Ok(report)
+}
+
+pub fn spawn_purge_job(client_db: Arc<dyn ClientRepository>, policy: RetentionPolicy) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.purge_every);
+
+        loop {
+            interval.tick().await;
+
+            match purge(client_db.as_ref(), &policy, Utc::now()).await {
+                Ok(report) => info!("Retention purge deleted {} accounts and {} sessions, {} accounts failed", report.deleted_accounts,
+                    report.deleted_sessions, report.failed_accounts),
+                Err(err) => error!("Error purging expired personal data: {}", err),
+            }
+        }
+    });
+}
+
+#[tokio::test]
+async fn test_retention_purge() {
+    use super::domain::{Client, SessionOrigin};
+    use super::memory_repository::{test_client, InMemoryClientRepository};
+
+    let client_db = InMemoryClientRepository::default();
+    let policy = RetentionPolicy { unverified_account_days: Some(30), ended_session_days: 90, purge_every: Duration::from_secs(60) };
+
+    let new_client = |client_name: &str| Client {
+        email: Some(format!("{}@example.com", client_name)),
+        ..test_client(client_name)
+    };
+
+    let unverified_id = client_db.add_client(&new_client("unverified")).await.unwrap();
+    let verified_id = client_db.add_client(&new_client("verified")).await.unwrap();
+    client_db.mark_email_verified(verified_id).await.unwrap();
+    client_db.add_session(verified_id, &SessionOrigin::default(), Utc::now() + chrono::Duration::hours(1)).await.unwrap();
+
+    // without an email there is nothing to verify, a used API key counts as activity
+    let without_email_id = client_db.add_client(&test_client("without-email")).await.unwrap();
+    let pipeline_id = client_db.add_client(&new_client("pipeline")).await.unwrap();
+    let api_key = client_db.add_api_key(pipeline_id, "pipeline", "mk_12345678", "hash", &[], None).await.unwrap();
+
+    let now = Utc::now();
+    client_db.set_api_key_last_used(api_key.api_key_id, now + chrono::Duration::days(20));
+    assert_eq!(purge(&client_db, &policy, now).await.unwrap(), PurgeReport::default());
+
+    let report = purge(&client_db, &policy, now + chrono::Duration::days(31)).await.unwrap();
+    assert_eq!(report, PurgeReport { deleted_accounts: 1, failed_accounts: 0, deleted_sessions: 0 });
+    assert!(client_db.get_client_by_id(unverified_id).await.is_err());
+    assert!(client_db.get_client_by_id(verified_id).await.is_ok());
+    assert!(client_db.get_client_by_id(without_email_id).await.is_ok());
+    assert!(client_db.get_client_by_id(pipeline_id).await.is_ok());
+
+    let report = purge(&client_db, &policy, now + chrono::Duration::days(91)).await.unwrap();
+    assert_eq!(report, PurgeReport { deleted_accounts: 1, failed_accounts: 0, deleted_sessions: 1 });
+    assert!(client_db.get_client_by_id(pipeline_id).await.is_err());
+}
@@ -1,57 +1,1153 @@
-use bcrypt::{hash, verify, DEFAULT_COST};
-use serde::Deserialize;
-use super::domain::Client;
+use std::sync::{Arc, OnceLock};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use super::domain::{ApiKey, Client, ClientSession, PersonalData, Profile, ProfileUpdate, SessionOrigin};
 use super::err::{Result, UserServiceError};
-use super::token_provider::TokenProvider;
-use super::user_database::ClientDb;
+use super::mailer::{MailMessage, Mailer};
+use super::password_hasher::PasswordHasher;
+use super::repository::ClientRepository;
+use super::token_provider::{TokenProvider, ACCESS_TOKEN_SECONDS};
+use super::two_factor::{self, TotpEnrollment};
+use tracing::{info, warn};
+
+use crate::auth_middleware::{MOVIES_READ_SCOPE, MOVIES_WRITE_SCOPE};
 
+const PASSWORD_RESET_MINUTES: i64 = 30;
+const EMAIL_VERIFICATION_HOURS: i64 = 24;
+// failed logins before the account is locked, every further failure doubles the lock
+const LOGIN_LOCKOUT_THRESHOLD: i32 = 5;
+const LOGIN_LOCKOUT_BASE_SECONDS: i64 = 30;
+const LOGIN_LOCKOUT_MAX_SECONDS: i64 = 60 * 60;
+// failures are forgotten after a quiet day, longer than the longest lock so waiting one out doesn't reset the count
+const LOGIN_FAILURES_FORGET_HOURS: i64 = 24;
+const API_KEY_SCOPES: [&str; 2] = [MOVIES_READ_SCOPE, MOVIES_WRITE_SCOPE];
+// keys look like mk_<64 hex characters>, the prefix shown is mk_ and 8 of them
+const API_KEY_PREFIX: &str = "mk_";
+const API_KEY_SHOWN_LENGTH: usize = 11;
+const DISPLAY_NAME_MAX_LENGTH: usize = 50;
+const AVATAR_URL_MAX_LENGTH: usize = 500;
+const MAX_FAVORITE_GENRES: usize = 10;
+// deleted accounts are renamed to deleted-<client_id>, so nobody may register such a name
+const DELETED_CLIENT_PREFIX: &str = "deleted-";
 
 pub struct ClientService {
-    client_db: ClientDb,
-    token_provider: TokenProvider
+    client_db: Arc<dyn ClientRepository>,
+    password_hasher: Arc<dyn PasswordHasher>,
+    mailer: Arc<dyn Mailer>,
+    token_provider: TokenProvider,
+    // verified against for unknown names, hashed on first use with the current parameters
+    dummy_hash: OnceLock<String>
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ClientInfo {
     pub client_name: String,
+    pub password: String,
+    #[serde(default)]
+    pub birth_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub email: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub client_name: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordReset {
+    pub token: String,
+    pub new_password: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailChange {
+    pub email: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailVerification {
+    pub token: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpConfirmation {
+    pub code: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLogin {
+    pub challenge_token: String,
+    // a code from the authenticator app or an unused backup code
+    pub code: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Token { token: String },
+    Challenge { challenge_token: String, two_factor_required: bool }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    // only returned on creation
+    pub key: String
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientProfile {
+    pub client_name: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub birth_date: Option<NaiveDate>,
+    pub two_factor_enabled: bool,
+    #[serde(flatten)]
+    pub profile: Profile
+}
+
+#[derive(Debug, Serialize)]
+pub struct PersonalDataExport {
+    pub exported_at: DateTime<Utc>,
+    pub account: ClientProfile,
+    #[serde(flatten)]
+    pub personal_data: PersonalData
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountDeletion {
     pub password: String
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ChildLinkInfo {
+    pub client_name: String,
+    pub password: String,
+    pub age_limit: Option<i32>
+}
+
 impl ClientService {
 
-    pub fn new(client_db: ClientDb, token_provider: TokenProvider) -> Self {
-        Self { client_db , token_provider }
+    pub fn new(client_db: Arc<dyn ClientRepository>, password_hasher: Arc<dyn PasswordHasher>, mailer: Arc<dyn Mailer>,
+        token_provider: TokenProvider) -> Self {
+        Self { client_db, password_hasher, mailer, token_provider, dummy_hash: OnceLock::new() }
     }
 
-    pub async fn register_client(&self, client_info: ClientInfo) -> Result<String> {
-        let hashed_password = hash(client_info.password, DEFAULT_COST)?;
+    fn dummy_hash(&self) -> Result<&str> {
+        if let Some(hashed_password) = self.dummy_hash.get() {
+            return Ok(hashed_password);
+        }
+
+        let hashed_password = self.password_hasher.hash("dummy password")?;
+
+        Ok(self.dummy_hash.get_or_init(|| hashed_password))
+    }
+
+    pub async fn register_client(&self, client_info: ClientInfo, origin: SessionOrigin) -> Result<String> {
+        if is_reserved_client_name(&client_info.client_name) {
+            return Err(UserServiceError::ReservedClientName);
+        }
+
+        let email = client_info.email.as_deref().map(normalize_email).transpose()?;
+        let hashed_password = self.password_hasher.hash(&client_info.password)?;
 
         let client = Client {
             client_id: 0,
             client_name: client_info.client_name,
-            encrypted_password: hashed_password
+            encrypted_password: hashed_password,
+            birth_date: client_info.birth_date,
+            session_version: 0,
+            email,
+            email_verified_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            failed_login_count: 0,
+            locked_until: None
         };
 
-        self.client_db.add_client(&client)
+        let client_id = self.client_db.add_client(&client)
             .await?;
 
-        let token = self.token_provider.generate_token(client.client_name)?;
+        // the account exists already, a lost mail can be sent again through the resend endpoint
+        if let Some(email) = &client.email {
+            if let Err(err) = self.send_email_verification(client_id, email).await {
+                warn!("Error sending the verification mail of {}: {}", client.client_name, err);
+            }
+        }
+
+        let token = start_session(self.client_db.as_ref(), &self.token_provider, client_id, client.client_name, client.session_version,
+            &origin).await?;
 
         Ok(token)
     }
 
-    pub async fn login_client(&self, client_info: ClientInfo) -> Result<String> {
-        let hashed_password = self.client_db.get_encrypted_password(&client_info.client_name)
-            .await?;
+    pub async fn login_client(&self, client_info: ClientInfo, origin: SessionOrigin) -> Result<LoginOutcome> {
+        // an unknown name fails like a wrong password and costs the same verification, so names can't be probed
+        let client = match self.client_db.get_client(&client_info.client_name).await {
+            Ok(client) => client,
+            Err(UserServiceError::ClientNotFound) => {
+                self.password_hasher.verify(&client_info.password, self.dummy_hash()?)?;
+                return Err(UserServiceError::InvalidPassword(client_info.password));
+            },
+            Err(err) => return Err(err),
+        };
+
+        // checked before the password, a locked account doesn't cost a hash verification
+        check_not_locked(&client)?;
 
-        let correct = verify(&client_info.password, &hashed_password)?;
+        let correct = self.password_hasher.verify(&client_info.password, &client.encrypted_password)?;
 
         if !correct {
+            self.record_failed_login(&client).await?;
             return Err(UserServiceError::InvalidPassword(client_info.password));
         }
 
-        let token = self.token_provider.generate_token(client_info.client_name)?;
+        // the plain password is only available here, so old hashes are upgraded on login
+        if self.password_hasher.needs_rehash(&client.encrypted_password) {
+            let upgraded = match self.password_hasher.hash(&client_info.password) {
+                Ok(hashed_password) => self.client_db.set_encrypted_password(client.client_id, &client.encrypted_password, &hashed_password).await,
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = upgraded {
+                warn!("Error upgrading the password hash of {}: {}", client.client_name, err);
+            }
+        }
+
+        // with two factors the counter is only reset once the second one is right too,
+        // otherwise the password alone would allow unlimited code guesses
+        if client.totp_enabled_at.is_some() {
+            let challenge_token = self.token_provider.generate_challenge_token(client.client_name, client.session_version)?;
+
+            return Ok(LoginOutcome::Challenge { challenge_token, two_factor_required: true });
+        }
+
+        self.reset_failed_logins(&client).await?;
+
+        let token = start_session(self.client_db.as_ref(), &self.token_provider, client.client_id, client.client_name,
+            client.session_version, &origin).await?;
+
+        Ok(LoginOutcome::Token { token })
+    }
+
+    pub async fn complete_two_factor_login(&self, two_factor_login: TwoFactorLogin, origin: SessionOrigin) -> Result<String> {
+        let claims = self.token_provider.verify_challenge_token(&two_factor_login.challenge_token)?;
+
+        let client = match self.client_db.get_client(&claims.sub).await {
+            Ok(client) => client,
+            Err(UserServiceError::ClientNotFound) => return Err(UserServiceError::InvalidChallenge),
+            Err(err) => return Err(err),
+        };
+
+        // a password change in between invalidates pending challenges too
+        if client.session_version != claims.ver {
+            return Err(UserServiceError::InvalidChallenge);
+        }
+
+        check_not_locked(&client)?;
+
+        let Some(totp_secret) = client.totp_secret.as_deref().filter(|_| client.totp_enabled_at.is_some()) else {
+            return Err(UserServiceError::InvalidChallenge);
+        };
+
+        let step = two_factor::matching_step(totp_secret, &client.client_name, &two_factor_login.code, Utc::now().timestamp() as u64)?;
+
+        let accepted = match step {
+            Some(step) => self.client_db.use_totp_step(client.client_id, step).await?,
+            None => {
+                let code_hash = hash_token(&two_factor::normalize_backup_code(&two_factor_login.code));
+                let used = self.client_db.use_backup_code(client.client_id, &code_hash).await?;
+
+                if used {
+                    info!("Backup code used by {}", client.client_name);
+                }
+
+                used
+            }
+        };
+
+        if !accepted {
+            self.record_failed_login(&client).await?;
+            return Err(UserServiceError::InvalidTwoFactorCode);
+        }
+
+        self.reset_failed_logins(&client).await?;
+
+        let token = start_session(self.client_db.as_ref(), &self.token_provider, client.client_id, client.client_name,
+            client.session_version, &origin).await?;
 
         Ok(token)
     }
+
+    async fn record_failed_login(&self, client: &Client) -> Result<()> {
+        let forget_before = Utc::now() - Duration::hours(LOGIN_FAILURES_FORGET_HOURS);
+        let failed_login_count = self.client_db.record_failed_login(client.client_id, forget_before).await?;
+
+        if failed_login_count < LOGIN_LOCKOUT_THRESHOLD {
+            return Ok(());
+        }
+
+        let lock_seconds = lockout_seconds(failed_login_count);
+        self.client_db.lock_client(client.client_id, Utc::now() + Duration::seconds(lock_seconds)).await?;
+        warn!("Client {} locked for {} seconds after {} failed logins", client.client_name, lock_seconds, failed_login_count);
+
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, client: &Client) -> Result<()> {
+        if client.failed_login_count == 0 && client.locked_until.is_none() {
+            return Ok(());
+        }
+
+        self.client_db.reset_failed_logins(client.client_id).await?;
+
+        if client.locked_until.is_some() {
+            info!("Client {} unlocked by a successful login", client.client_name);
+        }
+
+        Ok(())
+    }
+
+    // a new secret on every call until the client confirms one
+    pub async fn enroll_totp(&self, client_name: &str) -> Result<TotpEnrollment> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        if client.totp_enabled_at.is_some() {
+            return Err(UserServiceError::TwoFactorAlreadyEnabled);
+        }
+
+        let totp_secret = two_factor::new_secret();
+        self.client_db.set_totp_secret(client.client_id, &totp_secret).await?;
+
+        two_factor::enrollment(&totp_secret, &client.client_name)
+    }
+
+    // the backup codes are only returned here, just their hashes are stored
+    pub async fn confirm_totp(&self, client_name: &str, totp_confirmation: TotpConfirmation) -> Result<Vec<String>> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        if client.totp_enabled_at.is_some() {
+            return Err(UserServiceError::TwoFactorAlreadyEnabled);
+        }
+
+        let totp_secret = client.totp_secret.ok_or(UserServiceError::TwoFactorNotEnrolled)?;
+
+        let step = two_factor::matching_step(&totp_secret, &client.client_name, &totp_confirmation.code, Utc::now().timestamp() as u64)?
+            .ok_or(UserServiceError::InvalidTwoFactorCode)?;
+
+        if !self.client_db.use_totp_step(client.client_id, step).await? {
+            return Err(UserServiceError::InvalidTwoFactorCode);
+        }
+
+        let backup_codes = two_factor::new_backup_codes();
+        let backup_code_hashes: Vec<String> = backup_codes.iter()
+            .map(|code| hash_token(&two_factor::normalize_backup_code(code)))
+            .collect();
+
+        self.client_db.enable_totp(client.client_id, &backup_code_hashes).await?;
+        info!("Two-factor authentication enabled for {}", client.client_name);
+
+        Ok(backup_codes)
+    }
+
+    pub async fn create_api_key(&self, client_name: &str, api_key_request: ApiKeyRequest) -> Result<CreatedApiKey> {
+        let name = api_key_request.name.trim();
+        let mut scopes = api_key_request.scopes;
+        scopes.sort();
+        scopes.dedup();
+
+        let is_valid = !name.is_empty() && name.len() <= 100
+            && !scopes.is_empty() && scopes.iter().all(|scope| API_KEY_SCOPES.contains(&scope.as_str()))
+            && api_key_request.expires_at.is_none_or(|expires_at| expires_at > Utc::now());
+
+        if !is_valid {
+            return Err(UserServiceError::InvalidApiKeyRequest);
+        }
+
+        let client = self.client_db.get_client(client_name).await?;
+
+        let (token, _) = new_token();
+        let key = format!("{}{}", API_KEY_PREFIX, token);
+
+        let api_key = self.client_db.add_api_key(client.client_id, name, &key[..API_KEY_SHOWN_LENGTH], &hash_token(&key),
+            &scopes, api_key_request.expires_at).await?;
+        info!("API key {} created by {}", api_key.api_key_id, client.client_name);
+
+        Ok(CreatedApiKey { api_key, key })
+    }
+
+    pub async fn list_api_keys(&self, client_name: &str) -> Result<Vec<ApiKey>> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        self.client_db.get_api_keys(client.client_id).await
+    }
+
+    pub async fn revoke_api_key(&self, client_name: &str, api_key_id: i32) -> Result<()> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        if !self.client_db.revoke_api_key(client.client_id, api_key_id).await? {
+            return Err(UserServiceError::ApiKeyNotFound);
+        }
+
+        info!("API key {} revoked by {}", api_key_id, client.client_name);
+
+        Ok(())
+    }
+
+    pub async fn get_profile(&self, client_name: &str) -> Result<ClientProfile> {
+        let client = self.client_db.get_client(client_name).await?;
+        let profile = self.client_db.get_profile(client.client_id).await?;
+
+        Ok(ClientProfile {
+            client_name: client.client_name,
+            email: client.email,
+            email_verified: client.email_verified_at.is_some(),
+            birth_date: client.birth_date,
+            two_factor_enabled: client.totp_enabled_at.is_some(),
+            profile
+        })
+    }
+
+    pub async fn update_profile(&self, client_name: &str, profile_update: ProfileUpdate) -> Result<ClientProfile> {
+        let profile_update = normalize_profile_update(profile_update)?;
+        let client = self.client_db.get_client(client_name).await?;
+
+        self.client_db.update_profile(client.client_id, &profile_update).await?;
+
+        self.get_profile(client_name).await
+    }
+
+    // the password is asked again, a stolen session alone can't delete the account
+    pub async fn delete_account(&self, client_name: &str, account_deletion: AccountDeletion) -> Result<()> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        let correct = self.password_hasher.verify(&account_deletion.password, &client.encrypted_password)?;
+
+        if !correct {
+            return Err(UserServiceError::InvalidPassword(account_deletion.password));
+        }
+
+        self.client_db.delete_client(client.client_id).await?;
+        info!("Client {} deleted their account", client.client_id);
+
+        Ok(())
+    }
+
+    pub async fn export_personal_data(&self, client_name: &str) -> Result<PersonalDataExport> {
+        let account = self.get_profile(client_name).await?;
+        let client = self.client_db.get_client(client_name).await?;
+
+        let personal_data = self.client_db.get_personal_data(client.client_id).await?;
+        info!("Personal data of client {} exported", client.client_id);
+
+        Ok(PersonalDataExport { exported_at: Utc::now(), account, personal_data })
+    }
+
+    // for erasure requests made outside of the API, the admin checks who asks.
+    // the same erasure as deleting an account: what points to the person is removed and the
+    // row is kept as a nameless tombstone, so the ratings it gave stay in the movie averages
+    pub async fn anonymize_client(&self, admin_name: &str, client_name: &str) -> Result<()> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        self.client_db.delete_client(client.client_id).await?;
+        info!("Client {} anonymized by {}", client.client_id, admin_name);
+
+        Ok(())
+    }
+
+    // the session the request was made with is marked as the current one
+    pub async fn list_sessions(&self, client_name: &str, current_session_id: Option<i32>) -> Result<Vec<ClientSession>> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        let mut sessions = self.client_db.get_active_sessions(client.client_id).await?;
+
+        for session in &mut sessions {
+            session.current = Some(session.session_id) == current_session_id;
+        }
+
+        Ok(sessions)
+    }
+
+    pub async fn revoke_session(&self, client_name: &str, session_id: i32) -> Result<()> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        if !self.client_db.revoke_session(client.client_id, session_id).await? {
+            return Err(UserServiceError::SessionNotFound);
+        }
+
+        info!("Session {} revoked by {}", session_id, client.client_name);
+
+        Ok(())
+    }
+
+    // every other session is logged out, the returned token replaces the current one
+    pub async fn change_password(&self, client_name: &str, password_change: PasswordChange, origin: SessionOrigin) -> Result<String> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        let correct = self.password_hasher.verify(&password_change.current_password, &client.encrypted_password)?;
+
+        if !correct {
+            return Err(UserServiceError::InvalidPassword(password_change.current_password));
+        }
+
+        let hashed_password = self.password_hasher.hash(&password_change.new_password)?;
+        let session_version = self.client_db.update_password(client.client_id, &hashed_password).await?;
+
+        let token = start_session(self.client_db.as_ref(), &self.token_provider, client.client_id, client.client_name, session_version,
+            &origin).await?;
+
+        Ok(token)
+    }
+
+    // succeeds for unknown names too, so the endpoint can't be used to probe them
+    pub async fn request_password_reset(&self, reset_request: PasswordResetRequest) -> Result<()> {
+        let client = match self.client_db.get_client(&reset_request.client_name).await {
+            Ok(client) => client,
+            Err(UserServiceError::ClientNotFound) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        // resets only go to verified addresses
+        let (Some(email), Some(_)) = (client.email, client.email_verified_at) else {
+            info!("Password reset requested for {} without a verified email", client.client_name);
+            return Ok(());
+        };
+
+        let (token, token_hash) = new_token();
+
+        let expires_at = Utc::now() + Duration::minutes(PASSWORD_RESET_MINUTES);
+        self.client_db.add_password_reset_token(client.client_id, &token_hash, expires_at).await?;
+
+        self.mailer.send(MailMessage {
+            to: email,
+            subject: "Password reset".to_string(),
+            body: format!("Use this token to choose a new password, it expires in {} minutes:\n\n{}", PASSWORD_RESET_MINUTES, token)
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn reset_password(&self, password_reset: PasswordReset) -> Result<()> {
+        let client_id = self.client_db.use_password_reset_token(&hash_token(&password_reset.token))
+            .await?
+            .ok_or(UserServiceError::InvalidResetToken)?;
+
+        let hashed_password = self.password_hasher.hash(&password_reset.new_password)?;
+        self.client_db.update_password(client_id, &hashed_password).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_email(&self, client_name: &str, email_change: EmailChange) -> Result<()> {
+        let email = normalize_email(&email_change.email)?;
+        let client = self.client_db.get_client(client_name).await?;
+
+        self.client_db.set_email(client.client_id, &email).await?;
+        self.send_email_verification(client.client_id, &email).await
+    }
+
+    pub async fn resend_email_verification(&self, client_name: &str) -> Result<()> {
+        let client = self.client_db.get_client(client_name).await?;
+
+        let email = client.email.ok_or(UserServiceError::MissingEmail)?;
+
+        if client.email_verified_at.is_some() {
+            return Err(UserServiceError::EmailAlreadyVerified);
+        }
+
+        self.send_email_verification(client.client_id, &email).await
+    }
+
+    pub async fn verify_email(&self, email_verification: EmailVerification) -> Result<()> {
+        let verified = self.client_db.verify_email(&hash_token(&email_verification.token)).await?;
+
+        if !verified {
+            return Err(UserServiceError::InvalidVerificationToken);
+        }
+
+        Ok(())
+    }
+
+    async fn send_email_verification(&self, client_id: i32, email: &str) -> Result<()> {
+        let (token, token_hash) = new_token();
+
+        let expires_at = Utc::now() + Duration::hours(EMAIL_VERIFICATION_HOURS);
+        self.client_db.add_email_verification_token(client_id, email, &token_hash, expires_at).await?;
+
+        self.mailer.send(MailMessage {
+            to: email.to_string(),
+            subject: "Verify your email".to_string(),
+            body: format!("Use this token to verify your email address, it expires in {} hours:\n\n{}", EMAIL_VERIFICATION_HOURS, token)
+        }).await
+    }
+
+    // the child password proves the parent controls the account being linked
+    pub async fn link_child(&self, parent_name: &str, child_info: ChildLinkInfo) -> Result<()> {
+        if child_info.client_name == parent_name {
+            return Err(UserServiceError::InvalidChildAccount);
+        }
+
+        let parent = self.client_db.get_client(parent_name).await?;
+        let child = self.client_db.get_client(&child_info.client_name).await?;
+
+        let correct = self.password_hasher.verify(&child_info.password, &child.encrypted_password)?;
+
+        if !correct {
+            return Err(UserServiceError::InvalidPassword(child_info.password));
+        }
+
+        // only the current parent can change the link, knowing the child's password isn't enough
+        if !self.client_db.set_parent(child.client_id, parent.client_id, child_info.age_limit).await? {
+            return Err(UserServiceError::ChildAlreadyLinked);
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_child_age_limit(&self, parent_name: &str, child_name: &str, age_limit: Option<i32>) -> Result<()> {
+        let updated = self.client_db.set_parental_age_limit(parent_name, child_name, age_limit).await?;
+
+        if !updated {
+            return Err(UserServiceError::InvalidChildAccount);
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn is_reserved_client_name(client_name: &str) -> bool {
+    client_name.to_ascii_lowercase().starts_with(DELETED_CLIENT_PREFIX)
+}
+
+pub(super) fn check_not_locked(client: &Client) -> Result<()> {
+    match client.locked_until {
+        Some(locked_until) if locked_until > Utc::now() => {
+            let retry_after = (locked_until - Utc::now()).num_seconds().max(1);
+            Err(UserServiceError::TooManyLoginAttempts(retry_after as u64))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn lockout_seconds(failed_login_count: i32) -> i64 {
+    let doublings = (failed_login_count - LOGIN_LOCKOUT_THRESHOLD).clamp(0, 16) as u32;
+
+    (LOGIN_LOCKOUT_BASE_SECONDS << doublings).min(LOGIN_LOCKOUT_MAX_SECONDS)
+}
+
+// blank values clear the field
+fn normalize_profile_update(profile_update: ProfileUpdate) -> Result<ProfileUpdate> {
+    let clean = |value: Option<Option<String>>| value.map(|value| value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty()));
+
+    let display_name = clean(profile_update.display_name);
+    let avatar_url = clean(profile_update.avatar_url);
+
+    if display_name.as_ref().and_then(Option::as_ref).is_some_and(|display_name| display_name.chars().count() > DISPLAY_NAME_MAX_LENGTH) {
+        return Err(UserServiceError::InvalidProfile);
+    }
+
+    let is_valid_avatar_url = |avatar_url: &String| avatar_url.len() <= AVATAR_URL_MAX_LENGTH
+        && avatar_url.starts_with("https://") && !avatar_url.contains(char::is_whitespace);
+
+    if avatar_url.as_ref().and_then(Option::as_ref).is_some_and(|avatar_url| !is_valid_avatar_url(avatar_url)) {
+        return Err(UserServiceError::InvalidProfile);
+    }
+
+    let favorite_genres = match profile_update.favorite_genres {
+        Some(genres) => {
+            let mut genres: Vec<String> = genres.iter().map(|genre| genre.trim().to_string()).collect();
+            genres.sort();
+            genres.dedup();
+
+            if genres.len() > MAX_FAVORITE_GENRES || genres.iter().any(String::is_empty) {
+                return Err(UserServiceError::InvalidProfile);
+            }
+
+            Some(genres)
+        }
+        None => None,
+    };
+
+    Ok(ProfileUpdate {
+        display_name,
+        avatar_url,
+        preferred_language: clean(profile_update.preferred_language),
+        favorite_genres
+    })
+}
+
+// every full login gets its own session, its access token carries the session id
+pub(super) async fn start_session(client_db: &dyn ClientRepository, token_provider: &TokenProvider, client_id: i32, client_name: String,
+    session_version: i32, origin: &SessionOrigin) -> Result<String> {
+    let expires_at = Utc::now() + Duration::seconds(ACCESS_TOKEN_SECONDS);
+    let session_id = client_db.add_session(client_id, origin, expires_at).await?;
+
+    token_provider.generate_token(client_name, session_version, session_id)
+}
+
+// random token for the client and its sha-256, which is the only part stored
+pub(super) fn new_token() -> (String, String) {
+    let mut token_bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut token_bytes);
+
+    let token = hex::encode(token_bytes);
+    let token_hash = hash_token(&token);
+
+    (token, token_hash)
+}
+
+pub(super) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// only the shape is checked here, the verification mail proves the address works
+pub(super) fn normalize_email(email: &str) -> Result<String> {
+    let email = email.trim().to_lowercase();
+
+    let is_valid = match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.') && !domain.contains('@')
+            && !email.contains(char::is_whitespace) && email.len() <= 254,
+        None => false,
+    };
+
+    if !is_valid {
+        return Err(UserServiceError::InvalidEmail);
+    }
+
+    Ok(email)
+}
+
+#[cfg(test)]
+fn test_service() -> ClientService {
+    test_service_with_mailer().0
+}
+
+// for the tests that read the links out of the sent mails
+#[cfg(test)]
+fn test_service_with_mailer() -> (ClientService, Arc<super::mailer::MemoryMailer>) {
+    let mailer = Arc::new(super::mailer::MemoryMailer::default());
+
+    let service = ClientService::new(
+        Arc::new(super::memory_repository::InMemoryClientRepository::default()),
+        Arc::new(super::password_hasher::FastHasher),
+        mailer.clone(),
+        TokenProvider::new("test-key".to_string())
+    );
+
+    (service, mailer)
+}
+
+#[cfg(test)]
+fn test_client_info(client_name: &str, password: &str) -> ClientInfo {
+    ClientInfo { client_name: client_name.to_string(), password: password.to_string(), birth_date: None, email: None }
+}
+
+#[tokio::test]
+async fn test_register_then_login() {
+    let service = test_service();
+
+    let token = service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+    assert_eq!(service.token_provider.verify_token(&token).unwrap().claims.sub, "esteban");
+
+    let LoginOutcome::Token { token } = service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap() else {
+        panic!("login without two-factor authentication returns a token");
+    };
+    assert_eq!(service.token_provider.verify_token(&token).unwrap().claims.sub, "esteban");
+}
+
+#[tokio::test]
+async fn test_register_duplicate_name() {
+    let service = test_service();
+
+    service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+    let result = service.register_client(test_client_info("esteban", "other"), SessionOrigin::default()).await;
+
+    assert!(matches!(result, Err(UserServiceError::ClientNameTaken)));
+}
+
+#[tokio::test]
+async fn test_login_rejects_wrong_password_and_unknown_client() {
+    let service = test_service();
+
+    service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let result = service.login_client(test_client_info("esteban", "wrong"), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidPassword(_))));
+
+    let result = service.login_client(test_client_info("nobody", "secret"), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidPassword(_))));
+    // the unknown name still went through a verification
+    assert!(service.dummy_hash.get().is_some());
+}
+
+#[tokio::test]
+async fn test_login_upgrades_bcrypt_hash() {
+    let repository = Arc::new(super::memory_repository::InMemoryClientRepository::default());
+    let hasher = Arc::new(super::password_hasher::Argon2Hasher::new(64, 1, 1));
+    let mailer = Arc::new(super::mailer::MemoryMailer::default());
+    let service = ClientService::new(repository.clone(), hasher, mailer, TokenProvider::new("test-key".to_string()));
+
+    let bcrypt_hash = bcrypt::hash("secret", bcrypt::DEFAULT_COST - 8).unwrap();
+    repository.add_client(&Client {
+        encrypted_password: bcrypt_hash.clone(),
+        ..super::memory_repository::test_client("esteban")
+    }).await.unwrap();
+
+    service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let client = repository.get_client("esteban").await.unwrap();
+    assert!(client.encrypted_password.starts_with("$argon2id$"));
+
+    // a rehash racing a password change doesn't overwrite the new password
+    repository.set_encrypted_password(client.client_id, &bcrypt_hash, "stale").await.unwrap();
+    assert_eq!(repository.get_client("esteban").await.unwrap().encrypted_password, client.encrypted_password);
+
+    service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_change_password_revokes_sessions() {
+    let service = test_service();
+
+    let old_token = service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let result = service.change_password("esteban", PasswordChange {
+        current_password: "wrong".to_string(),
+        new_password: "new-secret".to_string()
+    }, SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidPassword(_))));
+
+    let new_token = service.change_password("esteban", PasswordChange {
+        current_password: "secret".to_string(),
+        new_password: "new-secret".to_string()
+    }, SessionOrigin::default()).await.unwrap();
+
+    let old_version = service.token_provider.verify_token(&old_token).unwrap().claims.ver;
+    let new_version = service.token_provider.verify_token(&new_token).unwrap().claims.ver;
+    assert_eq!(service.client_db.get_client("esteban").await.unwrap().session_version, new_version);
+    assert_ne!(old_version, new_version);
+
+    assert!(service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.is_err());
+    service.login_client(test_client_info("esteban", "new-secret"), SessionOrigin::default()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_password_reset_token_is_single_use() {
+    let (service, mailer) = test_service_with_mailer();
+
+    let last_token = || mailer.sent.lock().unwrap().last().unwrap().body.lines().last().unwrap().to_string();
+
+    service.register_client(ClientInfo {
+        email: Some("esteban@example.com".to_string()),
+        ..test_client_info("esteban", "secret")
+    }, SessionOrigin::default()).await.unwrap();
+    service.verify_email(EmailVerification { token: last_token() }).await.unwrap();
+
+    service.request_password_reset(PasswordResetRequest { client_name: "nobody".to_string() }).await.unwrap();
+    service.request_password_reset(PasswordResetRequest { client_name: "esteban".to_string() }).await.unwrap();
+
+    assert_eq!(mailer.sent.lock().unwrap().len(), 2);
+    let token = last_token();
+
+    let reset = |token: &str| PasswordReset { token: token.to_string(), new_password: "new-secret".to_string() };
+
+    service.reset_password(reset(&token)).await.unwrap();
+    assert!(matches!(service.reset_password(reset(&token)).await, Err(UserServiceError::InvalidResetToken)));
+    assert!(matches!(service.reset_password(reset("made-up")).await, Err(UserServiceError::InvalidResetToken)));
+
+    service.login_client(test_client_info("esteban", "new-secret"), SessionOrigin::default()).await.unwrap();
+
+    // changing the password uses up the links that are still in the inbox
+    service.request_password_reset(PasswordResetRequest { client_name: "esteban".to_string() }).await.unwrap();
+    let token = last_token();
+    service.change_password("esteban", PasswordChange {
+        current_password: "new-secret".to_string(), new_password: "newer-secret".to_string()
+    }, SessionOrigin::default()).await.unwrap();
+    assert!(matches!(service.reset_password(reset(&token)).await, Err(UserServiceError::InvalidResetToken)));
+}
+
+#[tokio::test]
+async fn test_email_verification() {
+    let (service, mailer) = test_service_with_mailer();
+
+    let with_email = |client_name: &str, email: &str| ClientInfo {
+        email: Some(email.to_string()),
+        ..test_client_info(client_name, "secret")
+    };
+
+    let result = service.register_client(with_email("esteban", "not-an-email"), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidEmail)));
+
+    service.register_client(with_email("esteban", " Esteban@Example.com "), SessionOrigin::default()).await.unwrap();
+    let first_token = mailer.sent.lock().unwrap()[0].body.lines().last().unwrap().to_string();
+    assert_eq!(mailer.sent.lock().unwrap()[0].to, "esteban@example.com");
+
+    // an unverified address can be claimed by another registration, whoever verifies it first keeps it
+    service.register_client(with_email("other", "esteban@example.com"), SessionOrigin::default()).await.unwrap();
+    let other_token = mailer.sent.lock().unwrap()[1].body.lines().last().unwrap().to_string();
+    service.verify_email(EmailVerification { token: other_token }).await.unwrap();
+
+    let result = service.verify_email(EmailVerification { token: first_token.clone() }).await;
+    assert!(matches!(result, Err(UserServiceError::EmailTaken)));
+    let result = service.register_client(with_email("third", "esteban@example.com"), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::EmailTaken)));
+
+    let result = service.set_email("esteban", EmailChange { email: "ESTEBAN@example.com".to_string() }).await;
+    assert!(matches!(result, Err(UserServiceError::EmailTaken)));
+
+    // a token sent to a previous address can't verify the new one
+    service.set_email("esteban", EmailChange { email: "new@example.com".to_string() }).await.unwrap();
+    let result = service.verify_email(EmailVerification { token: first_token }).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidVerificationToken)));
+
+    service.resend_email_verification("esteban").await.unwrap();
+    let token = mailer.sent.lock().unwrap().last().unwrap().body.lines().last().unwrap().to_string();
+    service.verify_email(EmailVerification { token }).await.unwrap();
+
+    let result = service.resend_email_verification("esteban").await;
+    assert!(matches!(result, Err(UserServiceError::EmailAlreadyVerified)));
+}
+
+#[tokio::test]
+async fn test_two_factor_login() {
+    let service = test_service();
+
+    service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let result = service.confirm_totp("esteban", TotpConfirmation { code: "000000".to_string() }).await;
+    assert!(matches!(result, Err(UserServiceError::TwoFactorNotEnrolled)));
+
+    let enrollment = service.enroll_totp("esteban").await.unwrap();
+    assert!(enrollment.provisioning_uri.starts_with("otpauth://totp/"));
+
+    let code_at = |unix_time: u64| {
+        let secret = totp_rs::Secret::Encoded(enrollment.secret.clone()).to_bytes().unwrap();
+        totp_rs::TOTP::new(totp_rs::Algorithm::SHA1, 6, 1, 30, secret, None, "esteban".to_string()).unwrap().generate(unix_time)
+    };
+    let now = Utc::now().timestamp() as u64;
+
+    let backup_codes = service.confirm_totp("esteban", TotpConfirmation { code: code_at(now) }).await.unwrap();
+    assert_eq!(backup_codes.len(), 10);
+
+    let LoginOutcome::Challenge { challenge_token, .. } = service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap() else {
+        panic!("login with two-factor authentication returns a challenge");
+    };
+
+    // the challenge is not an access token
+    assert!(service.token_provider.verify_token(&challenge_token).is_err());
+
+    let login = |code: &str| TwoFactorLogin { challenge_token: challenge_token.clone(), code: code.to_string() };
+
+    // the code used for the confirmation can't be replayed
+    let result = service.complete_two_factor_login(login(&code_at(now)), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidTwoFactorCode)));
+
+    let token = service.complete_two_factor_login(login(&code_at(now + 30)), SessionOrigin::default()).await.unwrap();
+    assert_eq!(service.token_provider.verify_token(&token).unwrap().claims.sub, "esteban");
+
+    let backup_code = backup_codes[0].to_uppercase();
+    service.complete_two_factor_login(login(&backup_code), SessionOrigin::default()).await.unwrap();
+    let result = service.complete_two_factor_login(login(&backup_code), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidTwoFactorCode)));
+
+    let result = service.enroll_totp("esteban").await;
+    assert!(matches!(result, Err(UserServiceError::TwoFactorAlreadyEnabled)));
+}
+
+#[tokio::test]
+async fn test_login_lockout() {
+    let service = test_service();
+
+    service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    for _ in 0..LOGIN_LOCKOUT_THRESHOLD {
+        let result = service.login_client(test_client_info("esteban", "wrong"), SessionOrigin::default()).await;
+        assert!(matches!(result, Err(UserServiceError::InvalidPassword(_))));
+    }
+
+    // even the right password is refused while locked
+    let result = service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::TooManyLoginAttempts(retry_after)) if retry_after <= 30));
+
+    let client = service.client_db.get_client("esteban").await.unwrap();
+    assert_eq!(client.failed_login_count, LOGIN_LOCKOUT_THRESHOLD);
+
+    // a failure after the quiet window starts the count over
+    let failed_login_count = service.client_db.record_failed_login(client.client_id, Utc::now() + Duration::seconds(1)).await.unwrap();
+    assert_eq!(failed_login_count, 1);
+
+    // once the lock expires a successful login resets the counter
+    service.client_db.lock_client(client.client_id, Utc::now() - Duration::seconds(1)).await.unwrap();
+    service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let client = service.client_db.get_client("esteban").await.unwrap();
+    assert_eq!((client.failed_login_count, client.locked_until), (0, None));
+}
+
+#[test]
+fn test_lockout_backoff() {
+    assert_eq!(lockout_seconds(LOGIN_LOCKOUT_THRESHOLD), 30);
+    assert_eq!(lockout_seconds(LOGIN_LOCKOUT_THRESHOLD + 2), 120);
+    assert_eq!(lockout_seconds(LOGIN_LOCKOUT_THRESHOLD + 40), LOGIN_LOCKOUT_MAX_SECONDS);
+}
+
+#[tokio::test]
+async fn test_api_keys() {
+    let service = test_service();
+
+    service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let api_key_request = |scopes: &[&str], expires_at: Option<DateTime<Utc>>| ApiKeyRequest {
+        name: "pipeline".to_string(),
+        scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        expires_at
+    };
+
+    for invalid_request in [api_key_request(&[], None), api_key_request(&["admin"], None),
+        api_key_request(&[MOVIES_READ_SCOPE], Some(Utc::now() - Duration::days(1)))] {
+        let result = service.create_api_key("esteban", invalid_request).await;
+        assert!(matches!(result, Err(UserServiceError::InvalidApiKeyRequest)));
+    }
+
+    let created = service.create_api_key("esteban", api_key_request(&[MOVIES_READ_SCOPE, MOVIES_READ_SCOPE], None)).await.unwrap();
+    assert!(created.key.starts_with(&created.api_key.key_prefix));
+    assert_eq!(created.api_key.scopes, vec![MOVIES_READ_SCOPE]);
+
+    service.revoke_api_key("esteban", created.api_key.api_key_id).await.unwrap();
+    let result = service.revoke_api_key("esteban", created.api_key.api_key_id).await;
+    assert!(matches!(result, Err(UserServiceError::ApiKeyNotFound)));
+
+    let api_keys = service.list_api_keys("esteban").await.unwrap();
+    assert!(api_keys[0].revoked_at.is_some());
+}
+
+#[tokio::test]
+async fn test_sessions_can_be_listed_and_revoked() {
+    let service = test_service();
+
+    let origin = SessionOrigin { user_agent: Some("Firefox".to_string()), ip_address: Some("10.0.0.1".to_string()) };
+    let first_token = service.register_client(test_client_info("esteban", "secret"), origin).await.unwrap();
+    service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let first_session_id = service.token_provider.verify_token(&first_token).unwrap().claims.sid.unwrap();
+
+    let sessions = service.list_sessions("esteban", Some(first_session_id)).await.unwrap();
+    assert_eq!(sessions.len(), 2);
+    let current: Vec<_> = sessions.iter().filter(|session| session.current).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].user_agent.as_deref(), Some("Firefox"));
+
+    service.revoke_session("esteban", first_session_id).await.unwrap();
+    let result = service.revoke_session("esteban", first_session_id).await;
+    assert!(matches!(result, Err(UserServiceError::SessionNotFound)));
+    assert_eq!(service.list_sessions("esteban", None).await.unwrap().len(), 1);
+
+    // a password change logs out every session
+    service.change_password("esteban", PasswordChange {
+        current_password: "secret".to_string(),
+        new_password: "new-secret".to_string()
+    }, SessionOrigin::default()).await.unwrap();
+    assert_eq!(service.list_sessions("esteban", None).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_profile_update_and_account_deletion() {
+    let service = test_service();
+
+    service.register_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.unwrap();
+
+    let profile_update: ProfileUpdate = serde_json::from_str(
+        r#"{"display_name": " Esteban ", "avatar_url": "https://cdn.example/e.png", "favorite_genres": ["Drama", "Comedy", "Drama"]}"#).unwrap();
+    let profile = service.update_profile("esteban", profile_update).await.unwrap();
+    assert_eq!(profile.profile.display_name.as_deref(), Some("Esteban"));
+    assert_eq!(profile.profile.favorite_genres, vec!["Comedy", "Drama"]);
+
+    // left out fields are kept, null clears them
+    let profile_update: ProfileUpdate = serde_json::from_str(r#"{"avatar_url": null}"#).unwrap();
+    let profile = service.update_profile("esteban", profile_update).await.unwrap();
+    assert_eq!(profile.profile.display_name.as_deref(), Some("Esteban"));
+    assert_eq!(profile.profile.avatar_url, None);
+
+    let profile_update: ProfileUpdate = serde_json::from_str(r#"{"avatar_url": "javascript:alert(1)"}"#).unwrap();
+    let result = service.update_profile("esteban", profile_update).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidProfile)));
+
+    let result = service.delete_account("esteban", AccountDeletion { password: "wrong".to_string() }).await;
+    assert!(matches!(result, Err(UserServiceError::InvalidPassword(_))));
+
+    service.delete_account("esteban", AccountDeletion { password: "secret".to_string() }).await.unwrap();
+    assert!(matches!(service.get_profile("esteban").await, Err(UserServiceError::ClientNotFound)));
+    assert!(service.login_client(test_client_info("esteban", "secret"), SessionOrigin::default()).await.is_err());
+
+    // the name is free again, the one of the deleted account can't be taken
+    service.register_client(test_client_info("esteban", "other"), SessionOrigin::default()).await.unwrap();
+    let result = service.register_client(test_client_info("Deleted-1", "other"), SessionOrigin::default()).await;
+    assert!(matches!(result, Err(UserServiceError::ReservedClientName)));
+}
+
+#[tokio::test]
+async fn test_personal_data_export_and_anonymization() {
+    let service = test_service();
+
+    let origin = SessionOrigin { user_agent: Some("Firefox".to_string()), ip_address: Some("10.0.0.1".to_string()) };
+    service.register_client(test_client_info("esteban", "secret"), origin).await.unwrap();
+
+    service.register_client(test_client_info("child", "secret"), SessionOrigin::default()).await.unwrap();
+    service.link_child("esteban", ChildLinkInfo { client_name: "child".to_string(), password: "secret".to_string(), age_limit: Some(12) })
+        .await.unwrap();
+
+    let client = service.client_db.get_client("esteban").await.unwrap();
+    service.client_db.link_identity(client.client_id, "https://corp.example", "corp-42").await.unwrap();
+    service.client_db.add_oauth_app(client.client_id, "app", None, "Movie night", &["https://example.com/callback".to_string()],
+        &[MOVIES_READ_SCOPE.to_string()]).await.unwrap();
+
+    let export = service.export_personal_data("esteban").await.unwrap();
+    assert_eq!(export.account.client_name, "esteban");
+    assert_eq!(export.personal_data.sessions[0].ip_address.as_deref(), Some("10.0.0.1"));
+    assert_eq!(export.personal_data.linked_identities[0].subject, "corp-42");
+    assert_eq!(export.personal_data.oauth_apps[0].name, "Movie night");
+
+    let child_export = service.export_personal_data("child").await.unwrap();
+    assert_eq!((child_export.personal_data.parent_client_id, child_export.personal_data.parental_age_limit), (Some(client.client_id), Some(12)));
+
+    service.anonymize_client("admin", "esteban").await.unwrap();
+    assert!(matches!(service.export_personal_data("esteban").await, Err(UserServiceError::ClientNotFound)));
+    assert!(matches!(service.anonymize_client("admin", "esteban").await, Err(UserServiceError::ClientNotFound)));
+}
+
+#[tokio::test]
+async fn test_child_cannot_be_taken_over() {
+    let service = test_service();
+
+    for client_name in ["parent", "other", "child"] {
+        service.register_client(test_client_info(client_name, "secret"), SessionOrigin::default()).await.unwrap();
+    }
+
+    let child_link = |age_limit| ChildLinkInfo { client_name: "child".to_string(), password: "secret".to_string(), age_limit };
+    service.link_child("parent", child_link(Some(12))).await.unwrap();
+    service.link_child("parent", child_link(Some(16))).await.unwrap();
+
+    let result = service.link_child("other", child_link(None)).await;
+    assert!(matches!(result, Err(UserServiceError::ChildAlreadyLinked)));
 }
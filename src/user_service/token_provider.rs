@@ -1,17 +1,36 @@
-use std::usize;
-
 use chrono::{Duration, Utc};
-use super::err::Result;
+use super::err::{Result, UserServiceError};
 use serde::{Deserialize, Serialize};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
 
-use super::domain::Client;
-
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
-    pub exp: usize
+    pub exp: usize,
+    // session version of the client when the token was issued
+    #[serde(default)]
+    pub ver: i32,
+    // none for access tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    // space separated scopes of delegated tokens, none for a full session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // OAuth client_id of the application the token was issued to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azp: Option<String>,
+    // login session of full access tokens, revoking it revokes the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i32>
+}
+
+pub const ACCESS_TOKEN_SECONDS: i64 = 60 * 60;
+
+const CHALLENGE_TOKEN_TYPE: &str = "2fa";
+
+struct Delegation {
+    scope: String,
+    azp: String
 }
 
 pub struct TokenProvider {
@@ -23,15 +42,37 @@ impl TokenProvider {
         Self { token_key }
     }
 
-    pub fn generate_token(&self, client_name: String) -> Result<String> {
+    pub fn generate_token(&self, client_name: String, session_version: i32, session_id: i32) -> Result<String> {
+        self.encode_claims(client_name, session_version, Duration::seconds(ACCESS_TOKEN_SECONDS), None, None, Some(session_id))
+    }
+
+    // OAuth access token, limited to the granted scopes
+    pub fn generate_scoped_token(&self, client_name: String, session_version: i32, scopes: &[String], app_public_id: String) -> Result<String> {
+        let delegation = Delegation { scope: scopes.join(" "), azp: app_public_id };
+
+        self.encode_claims(client_name, session_version, Duration::seconds(ACCESS_TOKEN_SECONDS), None, Some(delegation), None)
+    }
+
+    // proves the password was right, traded for an access token together with the second factor
+    pub fn generate_challenge_token(&self, client_name: String, session_version: i32) -> Result<String> {
+        self.encode_claims(client_name, session_version, Duration::minutes(5), Some(CHALLENGE_TOKEN_TYPE.to_string()), None, None)
+    }
+
+    fn encode_claims(&self, client_name: String, session_version: i32, valid_for: Duration, token_type: Option<String>,
+        delegation: Option<Delegation>, session_id: Option<i32>) -> Result<String> {
         let expiration = Utc::now()
-            .checked_add_signed(Duration::hours(1))
+            .checked_add_signed(valid_for)
             .expect("valid timestamp")
             .timestamp() as usize;
 
         let claims = Claims {
             sub: client_name,
-            exp: expiration
+            exp: expiration,
+            ver: session_version,
+            typ: token_type,
+            scope: delegation.as_ref().map(|delegation| delegation.scope.clone()),
+            azp: delegation.map(|delegation| delegation.azp),
+            sid: session_id
         };
 
         let token = encode (
@@ -43,7 +84,28 @@ impl TokenProvider {
         Ok(token)
     }
 
+    // only access tokens pass, challenge tokens are rejected
     pub fn verify_token(&self, token: &str) -> Result<TokenData<Claims>> {
+        let token_data = self.decode_claims(token)?;
+
+        if token_data.claims.typ.is_some() {
+            return Err(UserServiceError::InvalidChallenge);
+        }
+
+        Ok(token_data)
+    }
+
+    pub fn verify_challenge_token(&self, token: &str) -> Result<Claims> {
+        let token_data = self.decode_claims(token).map_err(|_| UserServiceError::InvalidChallenge)?;
+
+        if token_data.claims.typ.as_deref() != Some(CHALLENGE_TOKEN_TYPE) {
+            return Err(UserServiceError::InvalidChallenge);
+        }
+
+        Ok(token_data.claims)
+    }
+
+    fn decode_claims(&self, token: &str) -> Result<TokenData<Claims>> {
 
         let mut validation =  Validation::new(jsonwebtoken::Algorithm::HS256);
         validation.leeway = 60;
@@ -62,8 +124,9 @@ impl TokenProvider {
 fn test_token_provider_validity() {
     let token_provider = TokenProvider::new("gxQy0CBeYonc3UByo72Q24B7K8EizgRo0NfzxMdwEoQ=".to_string());
 
-    let token_result = token_provider.generate_token("esteban".to_string()).unwrap();
+    let token_result = token_provider.generate_token("esteban".to_string(), 0, 7).unwrap();
     let claims = token_provider.verify_token(&token_result).unwrap();
 
     assert_eq!(claims.claims.sub, "esteban");
+    assert_eq!(claims.claims.sid, Some(7));
 }
@@ -1,5 +1,23 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
-use super::{domain::Client, err::Result};
+use super::{domain::{ApiKey, AuthorizationCode, Client, ClientSession, LinkedIdentity, MovieActivity, OAuthApp, OidcLoginState, OwnedOAuthApp,
+    PersonalData, Profile, ProfileUpdate, SessionOrigin}, err::{Result, UserServiceError}, repository::ClientRepository};
+
+// postgres code for unique_violation
+const UNIQUE_VIOLATION: &str = "23505";
+
+fn map_unique_violation(err: sqlx::Error) -> UserServiceError {
+    match err {
+        sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+            match db_err.constraint() {
+                Some("client_verified_email_key") => UserServiceError::EmailTaken,
+                _ => UserServiceError::ClientNameTaken
+            }
+        }
+        err => err.into()
+    }
+}
 
 pub struct ClientDb {
     pool: PgPool
@@ -9,22 +27,525 @@ impl ClientDb {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
+
+#[async_trait]
+impl ClientRepository for ClientDb {
+    async fn add_client(&self, client: &Client) -> Result<i32> {
+        let client_id = sqlx::query_scalar!("INSERT INTO client(client_name, encrypted_password, birth_date, email)
+SELECT $1, $2, $3, $4::VARCHAR
+WHERE NOT EXISTS (SELECT 1 FROM client WHERE email = $4::VARCHAR AND email_verified_at IS NOT NULL) RETURNING client_id",
+                      client.client_name, client.encrypted_password, client.birth_date, client.email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(map_unique_violation)?;
+
+        client_id.ok_or(UserServiceError::EmailTaken)
+    }
+
+    async fn get_client(&self, client_name: &str) -> Result<Client> {
+        let client = sqlx::query_as!(Client,
+            "SELECT client_id, client_name, encrypted_password, birth_date, session_version, email, email_verified_at,
+totp_secret, totp_enabled_at, failed_login_count, locked_until
+FROM client WHERE client_name = $1 AND deleted_at IS NULL", client_name
+        ).fetch_optional(&self.pool).await?;
+
+        client.ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn get_client_by_id(&self, client_id: i32) -> Result<Client> {
+        let client = sqlx::query_as!(Client,
+            "SELECT client_id, client_name, encrypted_password, birth_date, session_version, email, email_verified_at,
+totp_secret, totp_enabled_at, failed_login_count, locked_until
+FROM client WHERE client_id = $1 AND deleted_at IS NULL", client_id
+        ).fetch_optional(&self.pool).await?;
+
+        client.ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn get_profile(&self, client_id: i32) -> Result<Profile> {
+        let profile = sqlx::query_as!(Profile,
+            r#"SELECT c.display_name, c.avatar_url, l.language_name AS "preferred_language?",
+ARRAY(SELECT g.genre_name FROM client_favorite_genre f JOIN genre g ON g.genre_id = f.genre_id
+    WHERE f.client_id = c.client_id ORDER BY g.genre_name) AS "favorite_genres!: Vec<String>", c.created_at
+FROM client c LEFT JOIN language l ON l.language_id = c.preferred_language_id
+WHERE c.client_id = $1 AND c.deleted_at IS NULL"#, client_id
+        ).fetch_optional(&self.pool).await?;
+
+        profile.ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn update_profile(&self, client_id: i32, profile_update: &ProfileUpdate) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let preferred_language_id = match &profile_update.preferred_language {
+            Some(Some(language_name)) => Some(sqlx::query_scalar!("SELECT language_id FROM language WHERE language_name = $1", language_name)
+                .fetch_optional(&mut tx)
+                .await?
+                .ok_or(UserServiceError::InvalidProfile)?),
+            _ => None,
+        };
+
+        sqlx::query!("UPDATE client SET
+    display_name = CASE WHEN $2 THEN $3 ELSE display_name END,
+    avatar_url = CASE WHEN $4 THEN $5 ELSE avatar_url END,
+    preferred_language_id = CASE WHEN $6 THEN $7 ELSE preferred_language_id END
+WHERE client_id = $1",
+            client_id,
+            profile_update.display_name.is_some(), profile_update.display_name.clone().flatten(),
+            profile_update.avatar_url.is_some(), profile_update.avatar_url.clone().flatten(),
+            profile_update.preferred_language.is_some(), preferred_language_id)
+            .execute(&mut tx)
+            .await?;
+
+        if let Some(favorite_genres) = &profile_update.favorite_genres {
+            let genre_ids = sqlx::query_scalar!("SELECT genre_id FROM genre WHERE genre_name = ANY($1)", favorite_genres)
+                .fetch_all(&mut tx)
+                .await?;
+
+            if genre_ids.len() != favorite_genres.len() {
+                return Err(UserServiceError::InvalidProfile);
+            }
+
+            sqlx::query!("DELETE FROM client_favorite_genre WHERE client_id = $1", client_id).execute(&mut tx).await?;
+            sqlx::query!("INSERT INTO client_favorite_genre(client_id, genre_id) SELECT $1, UNNEST($2::INTEGER[])",
+                client_id, &genre_ids).execute(&mut tx).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_client(&self, client_id: i32) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!("DELETE FROM rate_limit_bucket WHERE bucket_key = (SELECT 'client:' || client_name FROM client WHERE client_id = $1)",
+            client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM favorite_movie WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM watched_movie WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM client_favorite_genre WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM client_session WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM api_key WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM client_identity WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM client_backup_code WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM password_reset_token WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM email_verification_token WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM oauth_authorization_code
+WHERE client_id = $1 OR app_id IN (SELECT app_id FROM oauth_app WHERE owner_id = $1)", client_id).execute(&mut tx).await?;
+        sqlx::query!("DELETE FROM oauth_app WHERE owner_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("UPDATE client SET parent_client_id = NULL, parental_age_limit = NULL WHERE parent_client_id = $1", client_id)
+            .execute(&mut tx).await?;
+
+        // the row stays for the ratings, nothing left in it points to the person
+        sqlx::query!("UPDATE client SET client_name = 'deleted-' || client_id, encrypted_password = '', birth_date = NULL,
+    parent_client_id = NULL, parental_age_limit = NULL, session_version = session_version + 1, email = NULL, email_verified_at = NULL,
+    totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL, failed_login_count = 0, last_failed_login_at = NULL,
+    locked_until = NULL, display_name = NULL, avatar_url = NULL, preferred_language_id = NULL, deleted_at = NOW()
+WHERE client_id = $1", client_id).execute(&mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_personal_data(&self, client_id: i32) -> Result<PersonalData> {
+        let parent_link = sqlx::query!("SELECT parent_client_id, parental_age_limit FROM client WHERE client_id = $1", client_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(UserServiceError::ClientNotFound)?;
+
+        let ratings = sqlx::query_as!(MovieActivity,
+            r#"SELECT m.movie_id, m.distribution_title, r.rating AS "rating?", r.rated_at AS at
+FROM movie_rating r JOIN movie m ON m.movie_id = r.movie_id WHERE r.client_id = $1 ORDER BY r.rated_at"#, client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let favorites = sqlx::query_as!(MovieActivity,
+            r#"SELECT m.movie_id, m.distribution_title, NULL::INTEGER AS "rating?", f.added_at AS at
+FROM favorite_movie f JOIN movie m ON m.movie_id = f.movie_id WHERE f.client_id = $1 ORDER BY f.added_at"#, client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let watched = sqlx::query_as!(MovieActivity,
+            r#"SELECT m.movie_id, m.distribution_title, NULL::INTEGER AS "rating?", w.watched_at AS at
+FROM watched_movie w JOIN movie m ON m.movie_id = w.movie_id WHERE w.client_id = $1 ORDER BY w.watched_at"#, client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let sessions = sqlx::query_as!(ClientSession,
+            "SELECT session_id, user_agent, ip_address, created_at, last_seen_at, expires_at, revoked_at, FALSE AS \"current!\"
+FROM client_session WHERE client_id = $1 ORDER BY created_at", client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let linked_identities = sqlx::query_as!(LinkedIdentity,
+            "SELECT issuer, subject, created_at AS linked_at FROM client_identity WHERE client_id = $1 ORDER BY created_at", client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let oauth_apps = sqlx::query_as!(OwnedOAuthApp,
+            "SELECT public_id, name, redirect_uris AS \"redirect_uris: Vec<String>\", scopes AS \"scopes: Vec<String>\", created_at
+FROM oauth_app WHERE owner_id = $1 ORDER BY created_at", client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(PersonalData {
+            parent_client_id: parent_link.parent_client_id,
+            parental_age_limit: parent_link.parental_age_limit,
+            ratings,
+            favorites,
+            watched,
+            sessions,
+            api_keys: self.get_api_keys(client_id).await?,
+            linked_identities,
+            oauth_apps
+        })
+    }
+
+    async fn get_stale_unverified_clients(&self, inactive_since: DateTime<Utc>) -> Result<Vec<i32>> {
+        let client_ids = sqlx::query_scalar!("SELECT client_id FROM client c
+WHERE c.email IS NOT NULL AND c.email_verified_at IS NULL AND c.deleted_at IS NULL AND NOT c.is_admin AND c.created_at < $1
+    AND NOT EXISTS (SELECT 1 FROM client_session s WHERE s.client_id = c.client_id AND s.last_seen_at >= $1)
+    AND NOT EXISTS (SELECT 1 FROM api_key k WHERE k.client_id = c.client_id AND k.last_used_at >= $1)", inactive_since)
+            .fetch_all(&self.pool)
+            .await?;
 
-    pub async fn add_client(&self, client: &Client) -> Result<()> {
-        sqlx::query!("INSERT INTO client(client_name, encrypted_password)
-                      VALUES ($1, $2)", client.client_name, client.encrypted_password)
+        Ok(client_ids)
+    }
+
+    async fn delete_sessions_ended_before(&self, ended_before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM client_session WHERE expires_at < $1 OR revoked_at < $1", ended_before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn set_encrypted_password(&self, client_id: i32, verified_password: &str, encrypted_password: &str) -> Result<()> {
+        sqlx::query!("UPDATE client SET encrypted_password = $3 WHERE client_id = $1 AND encrypted_password = $2",
+            client_id, verified_password, encrypted_password)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
 
-    pub async fn get_encrypted_password(&self, client_name: &String) -> Result<String> {
-        let encrypted_password: String = sqlx::query_scalar!(
-            "SELECT encrypted_password FROM client WHERE client_name = $1", client_name
-        ).fetch_one(&self.pool).await?;
+    async fn update_password(&self, client_id: i32, encrypted_password: &str) -> Result<i32> {
+        let session_version = sqlx::query_scalar!("WITH revoked_session AS (
+    UPDATE client_session SET revoked_at = NOW() WHERE client_id = $1 AND revoked_at IS NULL
+), used_reset_token AS (
+    UPDATE password_reset_token SET used_at = NOW() WHERE client_id = $1 AND used_at IS NULL
+)
+UPDATE client SET encrypted_password = $2, session_version = session_version + 1
+WHERE client_id = $1 RETURNING session_version", client_id, encrypted_password)
+            .fetch_one(&self.pool)
+            .await?;
 
-        Ok(encrypted_password)
+        Ok(session_version)
+    }
+
+    async fn add_password_reset_token(&self, client_id: i32, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query!("INSERT INTO password_reset_token(token_hash, client_id, expires_at) VALUES ($1, $2, $3)",
+            token_hash, client_id, expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn use_password_reset_token(&self, token_hash: &str) -> Result<Option<i32>> {
+        let client_id = sqlx::query_scalar!("UPDATE password_reset_token SET used_at = NOW()
+WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW() RETURNING client_id", token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(client_id)
+    }
+
+    async fn set_email(&self, client_id: i32, email: &str) -> Result<()> {
+        let updated = sqlx::query!("UPDATE client SET email = $2, email_verified_at = NULL WHERE client_id = $1
+AND NOT EXISTS (SELECT 1 FROM client WHERE email = $2 AND email_verified_at IS NOT NULL AND client_id <> $1)", client_id, email)
+            .execute(&self.pool)
+            .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(UserServiceError::EmailTaken);
+        }
+
+        Ok(())
+    }
+
+    async fn add_email_verification_token(&self, client_id: i32, email: &str, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query!("INSERT INTO email_verification_token(token_hash, client_id, email, expires_at) VALUES ($1, $2, $3, $4)",
+            token_hash, client_id, email, expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn verify_email(&self, token_hash: &str) -> Result<bool> {
+        let verified = sqlx::query_scalar!("WITH token AS (
+    UPDATE email_verification_token SET used_at = NOW()
+    WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+    RETURNING client_id, email
+)
+UPDATE client c SET email_verified_at = NOW() FROM token t
+WHERE c.client_id = t.client_id AND c.email = t.email
+RETURNING c.client_id", token_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(map_unique_violation)?;
+
+        Ok(verified.is_some())
+    }
+
+    async fn record_failed_login(&self, client_id: i32, forget_before: DateTime<Utc>) -> Result<i32> {
+        let failed_login_count = sqlx::query_scalar!("UPDATE client SET
+    failed_login_count = CASE WHEN last_failed_login_at >= $2 THEN failed_login_count + 1 ELSE 1 END,
+    last_failed_login_at = NOW()
+WHERE client_id = $1 RETURNING failed_login_count", client_id, forget_before)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(failed_login_count)
+    }
+
+    async fn lock_client(&self, client_id: i32, locked_until: DateTime<Utc>) -> Result<()> {
+        sqlx::query!("UPDATE client SET locked_until = $2 WHERE client_id = $1", client_id, locked_until)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, client_id: i32) -> Result<()> {
+        sqlx::query!("UPDATE client SET failed_login_count = 0, locked_until = NULL WHERE client_id = $1", client_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
     }
-}
 
+    async fn set_totp_secret(&self, client_id: i32, totp_secret: &str) -> Result<()> {
+        sqlx::query!("UPDATE client SET totp_secret = $2 WHERE client_id = $1 AND totp_enabled_at IS NULL", client_id, totp_secret)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn enable_totp(&self, client_id: i32, backup_code_hashes: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!("DELETE FROM client_backup_code WHERE client_id = $1", client_id).execute(&mut tx).await?;
+        sqlx::query!("INSERT INTO client_backup_code(client_id, code_hash) SELECT $1, UNNEST($2::VARCHAR[])",
+            client_id, backup_code_hashes).execute(&mut tx).await?;
+        sqlx::query!("UPDATE client SET totp_enabled_at = NOW() WHERE client_id = $1", client_id).execute(&mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, client_id: i32, step: i64) -> Result<bool> {
+        let result = sqlx::query!("UPDATE client SET totp_last_step = $2
+WHERE client_id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)", client_id, step)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_backup_code(&self, client_id: i32, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query!("UPDATE client_backup_code SET used_at = NOW()
+WHERE client_id = $1 AND code_hash = $2 AND used_at IS NULL", client_id, code_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_api_key(&self, client_id: i32, name: &str, key_prefix: &str, key_hash: &str, scopes: &[String],
+        expires_at: Option<DateTime<Utc>>) -> Result<ApiKey> {
+        let api_key = sqlx::query_as!(ApiKey,
+            "INSERT INTO api_key(client_id, name, key_prefix, key_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6)
+RETURNING api_key_id, name, key_prefix, scopes AS \"scopes: Vec<String>\", created_at, last_used_at, expires_at, revoked_at",
+            client_id, name, key_prefix, key_hash, scopes as &[String], expires_at)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(api_key)
+    }
+
+    async fn get_api_keys(&self, client_id: i32) -> Result<Vec<ApiKey>> {
+        let api_keys = sqlx::query_as!(ApiKey,
+            "SELECT api_key_id, name, key_prefix, scopes AS \"scopes: Vec<String>\", created_at, last_used_at, expires_at, revoked_at
+FROM api_key WHERE client_id = $1 ORDER BY created_at DESC", client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(api_keys)
+    }
+
+    async fn revoke_api_key(&self, client_id: i32, api_key_id: i32) -> Result<bool> {
+        let result = sqlx::query!("UPDATE api_key SET revoked_at = NOW()
+WHERE api_key_id = $1 AND client_id = $2 AND revoked_at IS NULL", api_key_id, client_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_session(&self, client_id: i32, origin: &SessionOrigin, expires_at: DateTime<Utc>) -> Result<i32> {
+        let session_id = sqlx::query_scalar!("INSERT INTO client_session(client_id, user_agent, ip_address, expires_at)
+VALUES ($1, $2, $3, $4) RETURNING session_id", client_id, origin.user_agent, origin.ip_address, expires_at)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(session_id)
+    }
+
+    async fn get_active_sessions(&self, client_id: i32) -> Result<Vec<ClientSession>> {
+        let sessions = sqlx::query_as!(ClientSession,
+            "SELECT session_id, user_agent, ip_address, created_at, last_seen_at, expires_at, revoked_at, FALSE AS \"current!\"
+FROM client_session WHERE client_id = $1 AND revoked_at IS NULL AND expires_at > NOW() ORDER BY last_seen_at DESC", client_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(sessions)
+    }
+
+    async fn revoke_session(&self, client_id: i32, session_id: i32) -> Result<bool> {
+        let result = sqlx::query!("UPDATE client_session SET revoked_at = NOW()
+WHERE session_id = $1 AND client_id = $2 AND revoked_at IS NULL AND expires_at > NOW()", session_id, client_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_oauth_app(&self, owner_id: i32, public_id: &str, secret_hash: Option<&str>, name: &str, redirect_uris: &[String],
+        scopes: &[String]) -> Result<OAuthApp> {
+        let oauth_app = sqlx::query_as!(OAuthApp,
+            "INSERT INTO oauth_app(owner_id, public_id, secret_hash, name, redirect_uris, scopes) VALUES ($1, $2, $3, $4, $5, $6)
+RETURNING app_id, public_id, secret_hash, owner_id, name, redirect_uris AS \"redirect_uris: Vec<String>\", scopes AS \"scopes: Vec<String>\"",
+            owner_id, public_id, secret_hash, name, redirect_uris as &[String], scopes as &[String])
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(oauth_app)
+    }
+
+    async fn get_oauth_app(&self, public_id: &str) -> Result<OAuthApp> {
+        let oauth_app = sqlx::query_as!(OAuthApp,
+            "SELECT app_id, public_id, secret_hash, owner_id, name, redirect_uris AS \"redirect_uris: Vec<String>\", scopes AS \"scopes: Vec<String>\"
+FROM oauth_app WHERE public_id = $1", public_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        oauth_app.ok_or(UserServiceError::OAuthAppNotFound)
+    }
+
+    async fn add_authorization_code(&self, code_hash: &str, authorization_code: &AuthorizationCode) -> Result<()> {
+        sqlx::query!("INSERT INTO oauth_authorization_code(code_hash, app_id, client_id, redirect_uri, scopes, code_challenge, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            code_hash, authorization_code.app_id, authorization_code.client_id, authorization_code.redirect_uri,
+            &authorization_code.scopes as &[String], authorization_code.code_challenge, authorization_code.expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn use_authorization_code(&self, code_hash: &str) -> Result<Option<AuthorizationCode>> {
+        let authorization_code = sqlx::query_as!(AuthorizationCode,
+            "UPDATE oauth_authorization_code SET used_at = NOW()
+WHERE code_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+RETURNING app_id, client_id, redirect_uri, scopes AS \"scopes: Vec<String>\", code_challenge, expires_at", code_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(authorization_code)
+    }
+
+    async fn add_oidc_login_state(&self, state_hash: &str, login_state: &OidcLoginState) -> Result<()> {
+        sqlx::query!("INSERT INTO oidc_login_state(state_hash, nonce, code_verifier, binding_hash, expires_at) VALUES ($1, $2, $3, $4, $5)",
+            state_hash, login_state.nonce, login_state.code_verifier, login_state.binding_hash, login_state.expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn use_oidc_login_state(&self, state_hash: &str) -> Result<Option<OidcLoginState>> {
+        let login_state = sqlx::query_as!(OidcLoginState,
+            "UPDATE oidc_login_state SET used_at = NOW()
+WHERE state_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+RETURNING nonce, code_verifier, binding_hash, expires_at", state_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(login_state)
+    }
+
+    async fn get_client_by_identity(&self, issuer: &str, subject: &str) -> Result<Client> {
+        let client = sqlx::query_as!(Client,
+            "SELECT c.client_id, c.client_name, c.encrypted_password, c.birth_date, c.session_version, c.email, c.email_verified_at,
+c.totp_secret, c.totp_enabled_at, c.failed_login_count, c.locked_until
+FROM client c JOIN client_identity i ON i.client_id = c.client_id
+WHERE i.issuer = $1 AND i.subject = $2", issuer, subject
+        ).fetch_optional(&self.pool).await?;
+
+        client.ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn get_client_by_verified_email(&self, email: &str) -> Result<Client> {
+        let client = sqlx::query_as!(Client,
+            "SELECT client_id, client_name, encrypted_password, birth_date, session_version, email, email_verified_at,
+totp_secret, totp_enabled_at, failed_login_count, locked_until
+FROM client WHERE email = $1 AND email_verified_at IS NOT NULL", email
+        ).fetch_optional(&self.pool).await?;
+
+        client.ok_or(UserServiceError::ClientNotFound)
+    }
+
+    async fn link_identity(&self, client_id: i32, issuer: &str, subject: &str) -> Result<()> {
+        sqlx::query!("INSERT INTO client_identity(issuer, subject, client_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            issuer, subject, client_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, client_id: i32) -> Result<()> {
+        sqlx::query!("UPDATE client SET email_verified_at = NOW() WHERE client_id = $1 AND email IS NOT NULL", client_id)
+            .execute(&self.pool)
+            .await
+            .map_err(map_unique_violation)?;
+
+        Ok(())
+    }
+
+    async fn set_parent(&self, child_id: i32, parent_id: i32, age_limit: Option<i32>) -> Result<bool> {
+        let result = sqlx::query!("UPDATE client SET parent_client_id = $2, parental_age_limit = $3
+WHERE client_id = $1 AND (parent_client_id IS NULL OR parent_client_id = $2)",
+            child_id, parent_id, age_limit)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_parental_age_limit(&self, parent_name: &str, child_name: &str, age_limit: Option<i32>) -> Result<bool> {
+        let result = sqlx::query!("UPDATE client SET parental_age_limit = $3 
+WHERE client_name = $2 AND parent_client_id = (SELECT client_id FROM client WHERE client_name = $1)",
+            parent_name, child_name, age_limit)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}